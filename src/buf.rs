@@ -0,0 +1,97 @@
+use std::fmt;
+
+use actix_web::dev;
+use actix_web::Error as ActixError;
+use actix_web::{FromRequest, HttpRequest};
+use serde::de::Deserialize;
+
+use crate::config::XmlConfig;
+use crate::error::XMLPayloadError;
+use crate::extract::{extract_body, ExtractFuture};
+use crate::parse;
+
+/// Buffered Xml extractor, for deserializing types borrowing from the payload
+///
+/// [`Xml`](struct.Xml.html) requires `T: DeserializeOwned`, so every string is copied out of
+/// the payload. `XmlBuf` keeps the decoded payload instead, and
+/// [`deserialize`](#method.deserialize) borrows from it, e.g. into `&str` or `Cow<str>` fields.
+/// Strings containing escapes can't be borrowed, so prefer `Cow<str>` over `&str` unless the
+/// payload is known not to contain any.
+///
+/// All checks of [`XmlConfig`](struct.XmlConfig.html) are applied during extraction.
+///
+/// ## Example
+///
+/// ```rust
+/// use std::borrow::Cow;
+///
+/// use actix_xml::XmlBuf;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Info<'a> {
+///     #[serde(borrow)]
+///     username: Cow<'a, str>,
+/// }
+///
+/// async fn index(buf: XmlBuf) -> actix_web::Result<String> {
+///     let info: Info = buf.deserialize()?;
+///     Ok(format!("Welcome {}!", info.username))
+/// }
+/// ```
+pub struct XmlBuf {
+    text: String,
+    rewritten: bool,
+    config: XmlConfig,
+}
+
+impl XmlBuf {
+    /// Deserialize the payload, borrowing from it where possible
+    pub fn deserialize<'de, T>(&'de self) -> Result<T, XMLPayloadError>
+    where
+        T: Deserialize<'de>,
+    {
+        parse::deserialize(&self.text, self.rewritten, &self.config)
+    }
+
+    /// The decoded payload
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Deconstruct to the decoded payload
+    pub fn into_string(self) -> String {
+        self.text
+    }
+}
+
+impl fmt::Debug for XmlBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "XmlBuf: {:?}", self.text)
+    }
+}
+
+/// Decode a buffered payload into an `XmlBuf`, applying the checks enabled in `config`, and
+/// return it along with its size.
+fn buffer(
+    body: &[u8],
+    charset: Option<&str>,
+    config: &XmlConfig,
+) -> Result<(usize, XmlBuf), XMLPayloadError> {
+    let prepared = parse::prepare(body, charset, config)?;
+    let buf = XmlBuf {
+        text: prepared.text.into_owned(),
+        rewritten: prepared.rewritten,
+        config: config.clone(),
+    };
+    Ok((body.len(), buf))
+}
+
+impl FromRequest for XmlBuf {
+    type Error = ActixError;
+    type Future = ExtractFuture<Self>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        extract_body(req, payload, buffer, |buf| buf)
+    }
+}
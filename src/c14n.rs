@@ -0,0 +1,282 @@
+use std::borrow::Cow;
+
+use quick_xml::escape::unescape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{DeError as XMLError, Reader};
+
+use crate::config::XmlConfig;
+use crate::error::XMLPayloadError;
+use crate::parse;
+
+/// Namespace bound to the `xml` prefix, which is never declared.
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Canonicalize an XML document with Exclusive XML Canonicalization 1.0, without comments
+///
+/// This is the canonical form XML signatures are computed over
+/// (`http://www.w3.org/2001/10/xml-exc-c14n#`). The whole document is canonicalized, so no
+/// namespace prefixes are kept inclusive. Compared to the input:
+///
+/// * the document is encoded in utf-8, without XML declaration nor DOCTYPE, and its line breaks
+///   are normalized to `\n`
+/// * comments are removed, and so is whitespace outside the root element, while processing
+///   instructions outside of it are separated from it by line breaks
+/// * empty elements are written as start and end tag pairs, and CDATA sections as text
+/// * character and entity references are replaced by the characters they stand for, except
+///   the ones escaped by the canonical form
+/// * attribute values are double quoted, with their whitespace normalized
+/// * namespace declarations are only kept on the elements where their prefix is used first,
+///   and sorted by prefix ahead of the attributes, which are sorted by namespace and local name
+///
+/// The encoding is detected like for request payloads, from the byte order mark or the XML
+/// declaration if the `encoding` feature is enabled. DTDs aren't read, so attributes aren't
+/// defaulted, and entities other than the predefined ones are rejected. To canonicalize a
+/// request payload, see [`XmlBody::into_canonical`](struct.XmlBody.html#method.into_canonical).
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::canonicalize;
+///
+/// let canonical = canonicalize(
+///     b"<?xml version=\"1.0\"?>\n<a:doc xmlns:b='urn:b' xmlns:a='urn:a' z='1' id=\"x\"><e/></a:doc>",
+/// )
+/// .unwrap();
+/// assert_eq!(
+///     canonical,
+///     b"<a:doc xmlns:a=\"urn:a\" id=\"x\" z=\"1\"><e></e></a:doc>"
+/// );
+/// ```
+pub fn canonicalize(bytes: &[u8]) -> Result<Vec<u8>, XMLPayloadError> {
+    canonicalize_with(bytes, None, &XmlConfig::default())
+}
+
+/// Canonicalize a buffered payload, decoded from `charset` as configured in `config`.
+pub(crate) fn canonicalize_with(
+    body: &[u8],
+    charset: Option<&str>,
+    config: &XmlConfig,
+) -> Result<Vec<u8>, XMLPayloadError> {
+    let text = parse::decode(body, charset, config)?;
+    Ok(canonical(&normalize_line_breaks(&text))?)
+}
+
+/// Namespaces declared on an element and the declarations written on its canonical form.
+#[derive(Default)]
+struct Scope {
+    declared: Vec<(String, String)>,
+    rendered: Vec<(String, String)>,
+}
+
+/// Namespace bound to `prefix` by the innermost of `scopes` binding it, as selected by `pick`.
+fn lookup<'a>(
+    scopes: &'a [Scope],
+    prefix: &str,
+    pick: fn(&Scope) -> &[(String, String)],
+) -> Option<&'a str> {
+    scopes.iter().rev().find_map(|scope| {
+        pick(scope)
+            .iter()
+            .find(|(declared, _)| declared == prefix)
+            .map(|(_, uri)| uri.as_str())
+    })
+}
+
+fn canonical(text: &str) -> Result<Vec<u8>, XMLError> {
+    let mut reader = Reader::from_str(text);
+    reader.expand_empty_elements(true);
+    let mut out = Vec::with_capacity(text.len());
+    let mut scopes: Vec<Scope> = Vec::new();
+    let mut seen_root = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                seen_root = true;
+                start_tag(&element, &mut scopes, &mut out)?;
+            }
+            Event::End(element) => {
+                scopes.pop();
+                out.extend_from_slice(b"</");
+                out.extend_from_slice(element.name().as_ref());
+                out.push(b'>');
+            }
+            Event::Text(content) if !scopes.is_empty() => {
+                let content = std::str::from_utf8(&content).map_err(quick_xml::Error::from)?;
+                escape_text(
+                    &unescape(content).map_err(quick_xml::Error::from)?,
+                    &mut out,
+                );
+            }
+            Event::CData(content) => {
+                let content = std::str::from_utf8(&content).map_err(quick_xml::Error::from)?;
+                escape_text(content, &mut out);
+            }
+            Event::PI(content) => {
+                let content = std::str::from_utf8(&content).map_err(quick_xml::Error::from)?;
+                let (target, data) = match content.find(|c: char| c.is_ascii_whitespace()) {
+                    Some(end) => (&content[..end], content[end..].trim_start()),
+                    None => (content, ""),
+                };
+                // Outside of the root element, line breaks separate the instructions from it
+                if scopes.is_empty() && seen_root {
+                    out.push(b'\n');
+                }
+                out.extend_from_slice(b"<?");
+                out.extend_from_slice(target.as_bytes());
+                if !data.is_empty() {
+                    out.push(b' ');
+                    out.extend_from_slice(data.as_bytes());
+                }
+                out.extend_from_slice(b"?>");
+                if scopes.is_empty() && !seen_root {
+                    out.push(b'\n');
+                }
+            }
+            Event::Eof => break,
+            // The declaration, the DOCTYPE, comments and whitespace outside the root element
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+/// Write the canonical start tag of `element`, opening its namespace scope.
+fn start_tag(
+    element: &BytesStart,
+    scopes: &mut Vec<Scope>,
+    out: &mut Vec<u8>,
+) -> Result<(), XMLError> {
+    let mut scope = Scope::default();
+    let mut attributes = Vec::new();
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(quick_xml::Error::from)?;
+        let key = std::str::from_utf8(attribute.key.as_ref())
+            .map_err(quick_xml::Error::from)?
+            .to_owned();
+        let value = std::str::from_utf8(&attribute.value).map_err(quick_xml::Error::from)?;
+        // Literal whitespace of attribute values is normalized to spaces, unlike references
+        let value = value.replace(['\t', '\n'], " ");
+        let value = unescape(&value)
+            .map_err(quick_xml::Error::from)?
+            .into_owned();
+        match key.strip_prefix("xmlns") {
+            Some("") => scope.declared.push((String::new(), value)),
+            Some(prefix) if prefix.starts_with(':') => {
+                if &prefix[1..] != "xml" {
+                    scope.declared.push((prefix[1..].to_owned(), value));
+                }
+            }
+            _ => attributes.push((key, value)),
+        }
+    }
+    scopes.push(scope);
+
+    // Only the prefixes used by the element and its attributes are declared, if they aren't
+    // bound the same way by an ancestor already
+    let name = std::str::from_utf8(element.name().into_inner()).map_err(quick_xml::Error::from)?;
+    let mut used = vec![prefix_of(name).unwrap_or_default()];
+    used.extend(attributes.iter().filter_map(|(key, _)| prefix_of(key)));
+    let mut rendered = Vec::new();
+    for prefix in used {
+        if prefix == "xml" || rendered.iter().any(|(rendered, _)| rendered == prefix) {
+            continue;
+        }
+        let uri = match lookup(scopes, prefix, |scope| &scope.declared) {
+            Some(uri) => uri,
+            None if prefix.is_empty() => "",
+            None => {
+                return Err(XMLError::Custom(format!(
+                    "namespace prefix `{}` is not bound",
+                    prefix
+                )))
+            }
+        };
+        let parents = &scopes[..scopes.len() - 1];
+        if lookup(parents, prefix, |scope| &scope.rendered).unwrap_or_default() != uri {
+            rendered.push((prefix.to_owned(), uri.to_owned()));
+        }
+    }
+    rendered.sort();
+
+    let mut sorted = Vec::with_capacity(attributes.len());
+    for (key, value) in attributes {
+        let (namespace, local) = match key.split_once(':') {
+            Some(("xml", local)) => (XML_NAMESPACE, local),
+            Some((prefix, local)) => (
+                lookup(scopes, prefix, |scope| &scope.declared).unwrap_or_default(),
+                local,
+            ),
+            None => ("", key.as_str()),
+        };
+        sorted.push(((namespace.to_owned(), local.to_owned()), key.clone(), value));
+    }
+    sorted.sort();
+
+    out.push(b'<');
+    out.extend_from_slice(name.as_bytes());
+    for (prefix, uri) in &rendered {
+        match prefix.as_str() {
+            "" => out.extend_from_slice(b" xmlns=\""),
+            prefix => {
+                out.extend_from_slice(b" xmlns:");
+                out.extend_from_slice(prefix.as_bytes());
+                out.extend_from_slice(b"=\"");
+            }
+        }
+        escape_attribute(uri, out);
+        out.push(b'"');
+    }
+    for (_, key, value) in &sorted {
+        out.push(b' ');
+        out.extend_from_slice(key.as_bytes());
+        out.extend_from_slice(b"=\"");
+        escape_attribute(value, out);
+        out.push(b'"');
+    }
+    out.push(b'>');
+
+    if let Some(scope) = scopes.last_mut() {
+        scope.rendered = rendered;
+    }
+    Ok(())
+}
+
+/// Namespace prefix of a qualified name, if any.
+fn prefix_of(name: &str) -> Option<&str> {
+    name.split_once(':').map(|(prefix, _)| prefix)
+}
+
+/// Normalize `\r\n` and lone `\r` line breaks to `\n`, as XML processors do before parsing.
+fn normalize_line_breaks(text: &str) -> Cow<'_, str> {
+    if text.contains('\r') {
+        Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+fn escape_text(text: &str, out: &mut Vec<u8>) {
+    for c in text.chars() {
+        match c {
+            '&' => out.extend_from_slice(b"&amp;"),
+            '<' => out.extend_from_slice(b"&lt;"),
+            '>' => out.extend_from_slice(b"&gt;"),
+            '\r' => out.extend_from_slice(b"&#xD;"),
+            c => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+}
+
+fn escape_attribute(value: &str, out: &mut Vec<u8>) {
+    for c in value.chars() {
+        match c {
+            '&' => out.extend_from_slice(b"&amp;"),
+            '<' => out.extend_from_slice(b"&lt;"),
+            '"' => out.extend_from_slice(b"&quot;"),
+            '\t' => out.extend_from_slice(b"&#x9;"),
+            '\n' => out.extend_from_slice(b"&#xA;"),
+            '\r' => out.extend_from_slice(b"&#xD;"),
+            c => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::{fmt, ops};
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+/// Text content of an element holding raw markup in CDATA sections, e.g. a script.
+///
+/// The content of CDATA sections is captured verbatim, entities and angle brackets included,
+/// and concatenated with the unescaped text around them. This also covers CDATA sections split
+/// to embed a `]]>` sequence, like `<![CDATA[a]]]]><![CDATA[>b]]>` for `a]]>b`.
+///
+/// Only payloads deserialized with [`Xml`](struct.Xml.html) or
+/// [`parse_xml`](fn.parse_xml.html) are merged this way, not those deserialized with
+/// [`Xml::from_slice`](struct.Xml.html#method.from_slice). The content is serialized as
+/// escaped text.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::{parse_xml, Cdata, XmlConfig};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Page {
+///     script: Cdata,
+/// }
+///
+/// let page: Page = parse_xml(
+///     b"<Page><script><![CDATA[if (a < b) { c = '&amp;'; }]]></script></Page>",
+///     &XmlConfig::default(),
+/// )
+/// .unwrap();
+/// assert_eq!(page.script.as_str(), "if (a < b) { c = '&amp;'; }");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Cdata(pub String);
+
+impl Cdata {
+    /// Extracts a string slice of the content
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Deconstruct to the inner string
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl ops::Deref for Cdata {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Cdata {
+    fn from(content: String) -> Self {
+        Cdata(content)
+    }
+}
+
+impl fmt::Display for Cdata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cdata {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(Cdata)
+    }
+}
+
+impl Serialize for Cdata {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
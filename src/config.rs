@@ -1,8 +1,19 @@
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+use std::{env, fmt, ops};
 
-use actix_web::{web, HttpMessage, HttpRequest};
+use actix_web::http::header::{Accept, Header, HeaderValue, Quality, TryIntoHeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, Error as ActixError, HttpMessage, HttpRequest};
+use quick_xml::de::{Deserializer, SliceReader};
 
-use crate::error::XMLPayloadError;
+use crate::entity::EntityResolver;
+use crate::error::{ErrorStatus, XMLPayloadError};
+use crate::pool::BufferPool;
+use crate::schema::Schema;
+use crate::stats::{Outcome, XmlStats};
+use crate::warning::XmlWarning;
 
 /// XML extractor configuration
 ///
@@ -39,15 +50,147 @@ use crate::error::XMLPayloadError;
 /// }
 /// ```
 ///
+/// # Precedence
+///
+/// The config is looked up in the app data of the request, so the innermost config registered
+/// on a resource, a scope or the whole `App` (in this order) applies. At each level, an
+/// `XmlConfig` takes precedence over a `web::Data<XmlConfig>`. When no config is registered,
+/// the default config is used. A config set by the innermost
+/// [`XmlConfigMiddleware`](struct.XmlConfigMiddleware.html) wrapping the request takes
+/// precedence over all of them, and an `XmlConfig` inserted in the extensions of the request,
+/// e.g. by a middleware choosing the config dynamically, takes precedence over everything.
+///
+/// ```rust
+/// use actix_web::{web, App};
+/// use actix_xml::{Xml, XmlConfig};
+///
+/// async fn index(info: Xml<String>) -> String {
+///     info.into_inner()
+/// }
+///
+/// fn main() {
+///     let app = App::new()
+///         // applies to every resource without a config of its own
+///         .app_data(XmlConfig::with_limit(4096))
+///         .service(web::resource("/index.html").route(web::post().to(index)));
+/// }
+/// ```
+///
 #[derive(Clone)]
 pub struct XmlConfig {
-    pub(crate) limit: usize,
+    pub(crate) limit: Option<usize>,
+    stream_limit: Option<usize>,
     content_type: Option<Arc<dyn Fn(mime::Mime) -> bool + Send + Sync>>,
+    accepted_content_types: Vec<mime::Mime>,
+    accept_any_content_type: bool,
+    accept_xml_suffix: bool,
+    require_content_type: bool,
+    pub(crate) require_content_length: bool,
+    pub(crate) err_handler: Option<ErrorHandler>,
+    pub(crate) on_complete: Option<CompletionHandler>,
+    on_warning: Option<WarningHandler>,
+    pub(crate) overflow_status: Option<StatusCode>,
+    pub(crate) semantic_status_codes: bool,
+    pub(crate) log_level: Option<log::Level>,
+    log_target: Option<&'static str>,
+    log_path: bool,
+    response_content_type: Option<HeaderValue>,
+    pub(crate) indent: Option<(u8, usize)>,
+    pub(crate) declaration: Option<(String, String)>,
+    pub(crate) stream_response: bool,
+    pub(crate) allow_internal_subset: bool,
+    pub(crate) forbid_doctype: bool,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) max_elements: Option<usize>,
+    pub(crate) max_attributes: Option<usize>,
+    pub(crate) max_attribute_length: Option<usize>,
+    pub(crate) root: Option<String>,
+    pub(crate) wrap_root: Option<String>,
+    pub(crate) required_namespace: Option<String>,
+    pub(crate) entity_resolver: Option<Arc<dyn EntityResolver + Send + Sync>>,
+    pub(crate) namespaces: Vec<(String, String)>,
+    pub(crate) trim_text: bool,
+    pub(crate) check_end_names: bool,
+    pub(crate) expand_empty_elements: bool,
+    pub(crate) check_well_formed: bool,
+    pub(crate) lenient: bool,
+    pub(crate) prefix_attributes: bool,
+    pub(crate) mixed_content: bool,
+    pub(crate) deny_unknown_elements: bool,
+    pub(crate) lenient_scalars: bool,
+    pub(crate) verbose_errors: bool,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) stream_parsers: usize,
+    #[cfg(feature = "encoding")]
+    pub(crate) force_encoding: Option<&'static encoding_rs::Encoding>,
+    #[cfg(feature = "encoding")]
+    pub(crate) default_encoding: Option<&'static encoding_rs::Encoding>,
+    pub(crate) schema: Option<Arc<dyn Schema + Send + Sync>>,
+    pub(crate) buffer_pool: Option<Arc<dyn BufferPool + Send + Sync>>,
+    pub(crate) deserializer_builder: Option<DeserializerBuilder>,
 }
 
-const DEFAULT_CONFIG: XmlConfig = XmlConfig {
-    limit: 262_144,
+pub(crate) type ErrorHandler =
+    Arc<dyn Fn(XMLPayloadError, &HttpRequest) -> ActixError + Send + Sync>;
+
+pub(crate) type CompletionHandler = Arc<dyn Fn(&XmlStats) + Send + Sync>;
+
+pub(crate) type WarningHandler = Arc<dyn Fn(&XmlWarning) + Send + Sync>;
+
+pub(crate) type DeserializerBuilder =
+    Arc<dyn for<'de> Fn(&'de str) -> Deserializer<'de, SliceReader<'de>> + Send + Sync>;
+
+static DEFAULT_CONFIG: XmlConfig = XmlConfig {
+    limit: Some(262_144),
+    stream_limit: None,
     content_type: None,
+    accepted_content_types: Vec::new(),
+    accept_any_content_type: false,
+    accept_xml_suffix: true,
+    require_content_type: true,
+    require_content_length: false,
+    err_handler: None,
+    on_complete: None,
+    on_warning: None,
+    overflow_status: None,
+    semantic_status_codes: false,
+    log_level: Some(log::Level::Debug),
+    log_target: None,
+    log_path: true,
+    response_content_type: None,
+    indent: None,
+    declaration: None,
+    stream_response: false,
+    allow_internal_subset: false,
+    forbid_doctype: false,
+    max_depth: None,
+    max_elements: None,
+    max_attributes: None,
+    max_attribute_length: None,
+    root: None,
+    wrap_root: None,
+    required_namespace: None,
+    entity_resolver: None,
+    namespaces: Vec::new(),
+    trim_text: true,
+    check_end_names: true,
+    expand_empty_elements: true,
+    check_well_formed: false,
+    lenient: false,
+    prefix_attributes: false,
+    mixed_content: false,
+    deny_unknown_elements: false,
+    lenient_scalars: false,
+    verbose_errors: false,
+    read_timeout: None,
+    stream_parsers: 16,
+    #[cfg(feature = "encoding")]
+    force_encoding: None,
+    #[cfg(feature = "encoding")]
+    default_encoding: None,
+    schema: None,
+    buffer_pool: None,
+    deserializer_builder: None,
 };
 
 impl Default for XmlConfig {
@@ -61,9 +204,104 @@ impl XmlConfig {
         Default::default()
     }
 
-    /// Change max size of payload. By default max size is 256Kb
+    /// Create a default config, overridden by the following environment variables:
+    ///
+    /// * `XML_MAX_BODY_BYTES`: [`limit`](#method.limit)
+    /// * `XML_STREAM_LIMIT_BYTES`: [`stream_limit`](#method.stream_limit)
+    /// * `XML_MAX_DEPTH`: [`max_depth`](#method.max_depth)
+    /// * `XML_ACCEPT_ANY_CONTENT_TYPE` (`true` or `false`):
+    ///   [`accept_any_content_type`](#method.accept_any_content_type)
+    /// * `XML_REQUIRE_CONTENT_TYPE` (`true` or `false`):
+    ///   [`require_content_type`](#method.require_content_type)
+    ///
+    /// Unset variables are skipped, and invalid ones are skipped with a warning.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Some(limit) = env_var("XML_MAX_BODY_BYTES") {
+            config = config.limit(limit);
+        }
+        if let Some(limit) = env_var("XML_STREAM_LIMIT_BYTES") {
+            config = config.stream_limit(limit);
+        }
+        if let Some(max_depth) = env_var("XML_MAX_DEPTH") {
+            config = config.max_depth(max_depth);
+        }
+        if let Some(accept) = env_var("XML_ACCEPT_ANY_CONTENT_TYPE") {
+            config = config.accept_any_content_type(accept);
+        }
+        if let Some(require) = env_var("XML_REQUIRE_CONTENT_TYPE") {
+            config = config.require_content_type(require);
+        }
+        config
+    }
+
+    /// Create a default config with max size of payload set to `limit`
+    pub fn with_limit(limit: usize) -> Self {
+        Self::default().limit(limit)
+    }
+
+    /// Create a config for untrusted clients, with:
+    ///
+    /// * a max payload size of 64Kb
+    /// * only `text/xml` and `application/xml` accepted, not `+xml` suffixes
+    ///   (see [`accept_xml_suffix`](#method.accept_xml_suffix))
+    /// * DOCTYPE declarations forbidden (see [`forbid_doctype`](#method.forbid_doctype))
+    pub fn strict() -> Self {
+        Self::default()
+            .limit(65_536)
+            .accept_xml_suffix(false)
+            .forbid_doctype(true)
+    }
+
+    /// Create a config for clients sending sloppy documents, with:
+    ///
+    /// * a max payload size of 4Mb
+    /// * `+xml` suffixes accepted, like `application/atom+xml`
+    ///   (see [`accept_xml_suffix`](#method.accept_xml_suffix))
+    /// * malformed documents recovered (see [`lenient`](#method.lenient))
+    pub fn permissive() -> Self {
+        Self::default()
+            .limit(4_194_304)
+            .accept_xml_suffix(true)
+            .lenient(true)
+    }
+
+    /// Change max size of payload, as declared by the content-length header. Unless
+    /// [`stream_limit`](#method.stream_limit) is set, it's also enforced while reading the
+    /// payload. By default max size is 256Kb
     pub fn limit(mut self, limit: usize) -> Self {
-        self.limit = limit;
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Change max size of payload to `limit` kibibytes, see [`limit`](#method.limit). Sizes
+    /// too large for `usize` saturate to `usize::MAX`
+    pub fn limit_kb(self, limit: usize) -> Self {
+        self.limit(limit.saturating_mul(1024))
+    }
+
+    /// Change max size of payload to `limit` mebibytes, see [`limit`](#method.limit). Sizes
+    /// too large for `usize` saturate to `usize::MAX`
+    pub fn limit_mb(self, limit: usize) -> Self {
+        self.limit(limit.saturating_mul(1024 * 1024))
+    }
+
+    /// Disable the payload size limit, unless [`stream_limit`](#method.stream_limit) is set.
+    ///
+    /// Only use this for trusted input, as clients are otherwise able to exhaust the memory of
+    /// the server.
+    pub fn no_limit(mut self) -> Self {
+        self.limit = None;
+        self
+    }
+
+    /// Change max size of payload enforced while reading it, which is the only limit applied to
+    /// chunked payloads without a content-length header. It applies to the decompressed
+    /// payload, so it also guards against decompression bombs. Set it to `usize::MAX` to trust the
+    /// content-length of requests from trusted upstreams. By default it equals
+    /// [`limit`](#method.limit)
+    pub fn stream_limit(mut self, limit: usize) -> Self {
+        self.stream_limit = Some(limit);
         self
     }
 
@@ -76,30 +314,697 @@ impl XmlConfig {
         self
     }
 
-    pub(crate) fn check_content_type(&self, req: &HttpRequest) -> Result<(), XMLPayloadError> {
-        // check content-type
-        if let Ok(Some(mime)) = req.mime_type() {
-            if mime == "text/xml"
-                || mime == "application/xml"
-                || self
-                    .content_type
-                    .as_ref()
-                    .map_or(false, |predicate| predicate(mime))
-            {
-                Ok(())
+    /// Accept the given content types in addition to the built-in ones. Parameters like
+    /// `charset` are ignored when matching, and a `*` subtype accepts any subtype of the type,
+    /// e.g. `application/*`
+    pub fn accept_content_types<I>(mut self, content_types: I) -> Self
+    where
+        I: IntoIterator<Item = mime::Mime>,
+    {
+        self.accepted_content_types.extend(content_types);
+        self
+    }
+
+    /// Accept content types with a `+xml` suffix, like `application/atom+xml`, in addition to
+    /// `text/xml` and `application/xml`. Enabled by default
+    pub fn accept_xml_suffix(mut self, accept: bool) -> Self {
+        self.accept_xml_suffix = accept;
+        self
+    }
+
+    /// Skip the content type check entirely, accepting requests with any or no `Content-Type`.
+    ///
+    /// Only enable this if requests are routed to the handler by other means, e.g. behind a
+    /// gateway that rewrites every content type. Any payload will be parsed as XML, so clients
+    /// are able to submit XML through plain HTML forms (`text/plain` is a "simple" content type
+    /// that skips CORS preflight), which matters if the handler relies on cookies for
+    /// authentication.
+    pub fn accept_any_content_type(mut self, accept: bool) -> Self {
+        self.accept_any_content_type = accept;
+        self
+    }
+
+    /// Require requests to have a `Content-Type` header. If set to `false`, requests without one
+    /// are parsed as XML, while requests with an unaccepted content type are still rejected.
+    /// By default the header is required
+    pub fn require_content_type(mut self, require: bool) -> Self {
+        self.require_content_type = require;
+        self
+    }
+
+    /// Whether the request must have an accepted content type to be parsed, like
+    /// `JsonConfig::content_type_required` of actix-web, for configs ported from `web::Json`.
+    ///
+    /// If set to `false`, the content type isn't checked at all, so a missing or invalid
+    /// `Content-Type` header is parsed as XML, which is
+    /// [`accept_any_content_type(true)`](#method.accept_any_content_type) along with its
+    /// caveats. To only accept requests without the header, use
+    /// [`require_content_type`](#method.require_content_type) instead. By default the content
+    /// type is required
+    pub fn content_type_required(mut self, required: bool) -> Self {
+        self.accept_any_content_type = !required;
+        self.require_content_type = required;
+        self
+    }
+
+    /// Require requests to have a `Content-Length` header, rejecting e.g. chunked requests with
+    /// [`XMLPayloadError::LengthRequired`](enum.XMLPayloadError.html) before reading their
+    /// payload. By default the header is optional
+    pub fn require_content_length(mut self, require: bool) -> Self {
+        self.require_content_length = require;
+        self
+    }
+
+    /// Fail with [`XMLPayloadError::Timeout`](enum.XMLPayloadError.html) (`408 Request Timeout`)
+    /// if reading the whole payload takes longer than `timeout`, e.g. when a client sends it
+    /// slowly to hold the connection. By default there is no timeout, except for
+    /// [`XmlStream`](struct.XmlStream.html) payloads which must be read within 60 seconds
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Parse at most `max` [`XmlStream`](struct.XmlStream.html) payloads of the process while
+    /// they're read, each holding a thread of the blocking pool while it waits for the client.
+    /// Further payloads are read in full, within the [stream limit](#method.stream_limit),
+    /// before being parsed, so slow clients can't use up the pool. By default 16 payloads are
+    /// parsed while they're read
+    pub fn stream_parsers(mut self, max: usize) -> Self {
+        self.stream_parsers = max;
+        self
+    }
+
+    /// Decode payloads with `encoding`, ignoring the charset of the content type and the
+    /// encoding declared in the XML declaration, e.g. for a partner sending Shift-JIS while
+    /// claiming utf-8. Only a byte order mark of `encoding` is stripped
+    #[cfg(feature = "encoding")]
+    pub fn force_encoding(mut self, encoding: &'static encoding_rs::Encoding) -> Self {
+        self.force_encoding = Some(encoding);
+        self
+    }
+
+    /// Decode payloads with `encoding` when neither a byte order mark, the charset of the
+    /// content type nor the XML declaration tells the encoding, e.g. for a partner always
+    /// sending windows-1252 without saying so. By default such payloads are decoded as utf-8
+    #[cfg(feature = "encoding")]
+    pub fn default_encoding(mut self, encoding: &'static encoding_rs::Encoding) -> Self {
+        self.default_encoding = Some(encoding);
+        self
+    }
+
+    /// Accept documents with a DOCTYPE internal subset (`<!DOCTYPE root [ ... ]>`).
+    ///
+    /// Internal subsets are where nested entities of "billion laughs" style attacks are declared,
+    /// so such documents are rejected with
+    /// [`XMLPayloadError::EntityExpansionLimit`](enum.XMLPayloadError.html) by default.
+    /// Note that quick-xml never expands entities declared in a DTD, so allowing them won't make
+    /// these entities resolvable.
+    pub fn allow_internal_subset(mut self, allow: bool) -> Self {
+        self.allow_internal_subset = allow;
+        self
+    }
+
+    /// Reject documents with any DOCTYPE declaration with
+    /// [`XMLPayloadError::DoctypeForbidden`](enum.XMLPayloadError.html), for hardening against
+    /// DTD based attacks. DOCTYPE declarations without an internal subset are accepted by default,
+    /// as quick-xml never loads external DTDs
+    pub fn forbid_doctype(mut self, forbid: bool) -> Self {
+        self.forbid_doctype = forbid;
+        self
+    }
+
+    /// Change max nesting depth of elements. By default depth is unlimited
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Change max number of elements in a document, the root element included. This protects
+    /// against payloads with a huge number of tiny elements, which take far more memory once
+    /// deserialized than their size suggests. By default the number is unlimited
+    pub fn max_elements(mut self, max_elements: usize) -> Self {
+        self.max_elements = Some(max_elements);
+        self
+    }
+
+    /// Change max number of attributes of an element, namespace bindings included. This
+    /// protects against elements with a huge number of attributes. By default the number is
+    /// unlimited
+    pub fn max_attributes_per_element(mut self, max_attributes: usize) -> Self {
+        self.max_attributes = Some(max_attributes);
+        self
+    }
+
+    /// Change max length of attribute values, in bytes as written in the document, i.e. before
+    /// references are unescaped. By default the length is unlimited
+    pub fn max_attribute_length(mut self, max_length: usize) -> Self {
+        self.max_attribute_length = Some(max_length);
+        self
+    }
+
+    /// Require the root element to be named `name`, prefix included. Documents with another
+    /// root element are rejected before deserialization
+    pub fn require_root(mut self, name: impl Into<String>) -> Self {
+        self.root = Some(name.into());
+        self
+    }
+
+    /// Require the root element to be in the namespace `uri`, e.g. to tell versions of an API
+    /// apart. Documents with a root element in another namespace, or in none, are rejected with
+    /// [`XMLPayloadError::NamespaceMismatch`](enum.XMLPayloadError.html) before deserialization.
+    /// The namespace may be bound to the root element with a prefix or as the default namespace
+    pub fn require_namespace(mut self, uri: impl Into<String>) -> Self {
+        self.required_namespace = Some(uri.into());
+        self
+    }
+
+    /// Wrap the payload in a `name` element before deserialization, after its prolog (XML
+    /// declaration, DOCTYPE, comments). This lets payloads made of several top-level elements,
+    /// e.g. `<item/><item/>`, deserialize into a struct with a `Vec` field named after them.
+    ///
+    /// Checks of the document, like [`require_root`](#method.require_root) and the depth and
+    /// element limits, apply to the payload as sent
+    pub fn wrap_root(mut self, name: impl Into<String>) -> Self {
+        self.wrap_root = Some(name.into());
+        self
+    }
+
+    /// Set resolver for entities other than the predefined ones. By default documents
+    /// referencing custom entities fail to deserialize
+    pub fn entity_resolver<R>(mut self, resolver: R) -> Self
+    where
+        R: EntityResolver + Send + Sync + 'static,
+    {
+        self.entity_resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Validate documents against `schema` before deserializing them. Invalid documents are
+    /// rejected with [`XMLPayloadError::SchemaValidation`](enum.XMLPayloadError.html)
+    pub fn schema<S>(mut self, schema: S) -> Self
+    where
+        S: Schema + Send + Sync + 'static,
+    {
+        self.schema = Some(Arc::new(schema));
+        self
+    }
+
+    /// Read payloads into buffers taken from `pool`, instead of allocating one per request.
+    /// Pass an `Arc` to share the pool with other configs. By default buffers aren't reused
+    pub fn buffer_pool<P>(mut self, pool: P) -> Self
+    where
+        P: BufferPool + Send + Sync + 'static,
+    {
+        self.buffer_pool = Some(Arc::new(pool));
+        self
+    }
+
+    /// Construct the deserializer of documents with `builder` instead of
+    /// `quick_xml::de::Deserializer::from_str`, e.g. to set options of the deserializer in one
+    /// place.
+    ///
+    /// `builder` is given the document once decoded and rewritten according to the other options
+    /// of the config. The deserializer borrows this text, which is dropped right after
+    /// deserialization, so `builder` must accept text of any lifetime and the deserialized type
+    /// can only borrow from it through [`XmlBuf`](struct.XmlBuf.html).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use actix_xml::XmlConfig;
+    /// use quick_xml::de::Deserializer;
+    ///
+    /// let config = XmlConfig::default().deserializer_builder(|text| Deserializer::from_str(text));
+    /// ```
+    pub fn deserializer_builder<F>(mut self, builder: F) -> Self
+    where
+        F: for<'de> Fn(&'de str) -> Deserializer<'de, SliceReader<'de>> + Send + Sync + 'static,
+    {
+        self.deserializer_builder = Some(Arc::new(builder));
+        self
+    }
+
+    /// Trim whitespace around text content of elements. Whitespace-only text between elements
+    /// is always ignored. By default text is trimmed
+    pub fn trim_text(mut self, trim: bool) -> Self {
+        self.trim_text = trim;
+        self
+    }
+
+    /// Check that the name of each end tag matches its start tag. If disabled, end tags close
+    /// the innermost open element whatever their name is. By default names are checked
+    pub fn check_end_names(mut self, check: bool) -> Self {
+        self.check_end_names = check;
+        self
+    }
+
+    /// Read self-closing elements like `<field/>` as empty elements, the same as
+    /// `<field></field>`, so that an `Option<String>` field reads `Some("")` from either. If
+    /// disabled, self-closing elements without attributes are read as absent instead, e.g.
+    /// `None` for an `Option` field and no item for a `Vec` field, while the root element is
+    /// always kept. By default self-closing elements are expanded, as quick-xml does
+    pub fn expand_empty_elements(mut self, expand: bool) -> Self {
+        self.expand_empty_elements = expand;
+        self
+    }
+
+    /// Read the whole document before deserializing it, rejecting malformed markup with
+    /// [`XMLPayloadError::NotWellFormed`](enum.XMLPayloadError.html) and truncated documents with
+    /// [`XMLPayloadError::Incomplete`](enum.XMLPayloadError.html). Deserialize errors are then
+    /// left to documents that don't have the shape of the target type. The check runs on the
+    /// document as sent, before the config rewrites it, but accepts what the config does: what
+    /// [`lenient`](#method.lenient) parsing recovers from, entities of the
+    /// [`entity_resolver`](#method.entity_resolver), and mismatched end tags if
+    /// [`check_end_names`](#method.check_end_names) is off. By default syntax errors are only
+    /// reported as they are met while deserializing
+    pub fn check_well_formed(mut self, check: bool) -> Self {
+        self.check_well_formed = check;
+        self
+    }
+
+    /// Recover from minor malformations sent by some legacy clients instead of rejecting the
+    /// document:
+    ///
+    /// * end tags close the open element with the same name, closing the elements nested in it
+    ///   as well, and are dropped if there's none
+    /// * elements left open at the end of the document are closed
+    /// * ampersands not starting a character or entity reference, in text and attribute values,
+    ///   are taken literally
+    ///
+    /// By default documents are parsed strictly
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Match attributes against fields renamed to `@name`, e.g. `#[serde(rename = "@id")]`,
+    /// instead of fields named after the attribute. This tells attributes apart from child
+    /// elements of the same name, which are otherwise both matched by the same field.
+    /// Namespace bindings (`xmlns` attributes) are not renamed. By default attributes are not
+    /// prefixed
+    pub fn prefix_attributes(mut self, prefix: bool) -> Self {
+        self.prefix_attributes = prefix;
+        self
+    }
+
+    /// Capture the text of elements that also have child elements, e.g. `Hello ` and `!` in
+    /// `<p>Hello <b>world</b>!</p>`, by reading each text run of such elements as an element
+    /// named `$text`. Match them with an enum variant renamed to `$text`, among the variants of
+    /// the child elements, in a `Vec` field renamed to `$value`; see the
+    /// [crate documentation](index.html#mixed-content). Whitespace around these text runs is
+    /// kept, while whitespace-only runs are ignored. By default the deserializer doesn't tell
+    /// such text runs apart from child elements
+    pub fn mixed_content(mut self, mixed: bool) -> Self {
+        self.mixed_content = mixed;
+        self
+    }
+
+    /// Reject child elements and attributes not matching any field of the struct they're
+    /// deserialized into, like `#[serde(deny_unknown_fields)]` on every struct. Namespace
+    /// bindings (`xmlns` attributes) and the text content of elements are not checked. By default
+    /// unknown elements and attributes are ignored, and missing ones can be filled in with
+    /// `#[serde(default)]`
+    pub fn deny_unknown_elements(mut self, deny: bool) -> Self {
+        self.deny_unknown_elements = deny;
+        self
+    }
+
+    /// Parse numbers and booleans leniently: whitespace around numbers is ignored, e.g. in
+    /// attribute values, and booleans may also be spelled `yes`/`no`, `y`/`n` or `on`/`off`, in
+    /// any case. By default quick-xml parses them strictly
+    pub fn lenient_scalars(mut self, lenient: bool) -> Self {
+        self.lenient_scalars = lenient;
+        self
+    }
+
+    /// Describe deserialize errors in detail: the path of the field that failed and the markup
+    /// around it are added to [`XMLPayloadError::Deserialize`](enum.XMLPayloadError.html).
+    /// As these may include parts of the payload in responses and logs, errors are terse by
+    /// default
+    pub fn verbose_errors(mut self, verbose: bool) -> Self {
+        self.verbose_errors = verbose;
+        self
+    }
+
+    /// Rename elements and attributes in the namespace `uri` to use `prefix`, whatever prefix
+    /// the document binds to the namespace. With an empty `prefix`, they are renamed to their
+    /// local name, so that they match unprefixed fields.
+    ///
+    /// Note that fields are matched against the local name of elements anyway, but against the
+    /// qualified name of attributes (e.g. `soap:mustUnderstand`).
+    pub fn namespace(mut self, prefix: impl Into<String>, uri: impl Into<String>) -> Self {
+        self.namespaces.push((prefix.into(), uri.into()));
+        self
+    }
+
+    /// Set custom error handler
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(XMLPayloadError, &HttpRequest) -> ActixError + Send + Sync + 'static,
+    {
+        self.err_handler = Some(Arc::new(f));
+        self
+    }
+
+    /// Set a callback invoked with the [`XmlStats`](struct.XmlStats.html) of each buffered or
+    /// [streaming](struct.XmlStream.html) extraction once it completes, e.g. to record metrics
+    /// on payload sizes and parse durations.
+    ///
+    /// Extractions rejected by their content type before reading the payload are not reported.
+    pub fn on_complete<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&XmlStats) + Send + Sync + 'static,
+    {
+        self.on_complete = Some(Arc::new(f));
+        self
+    }
+
+    /// Set a callback invoked with each [`XmlWarning`](enum.XmlWarning.html) met while reading
+    /// a payload, e.g. to log data quality issues of documents accepted in
+    /// [`lenient`](#method.lenient) mode. Warnings don't fail the extraction.
+    pub fn on_warning<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&XmlWarning) + Send + Sync + 'static,
+    {
+        self.on_warning = Some(Arc::new(f));
+        self
+    }
+
+    /// Set the status code of responses to oversized payloads, unless a custom error handler is
+    /// set. By default `413 Payload Too Large` is used
+    pub fn overflow_status(mut self, status: StatusCode) -> Self {
+        self.overflow_status = Some(status);
+        self
+    }
+
+    /// Respond `422 Unprocessable Entity` instead of `400 Bad Request` to well-formed documents
+    /// that don't match the target type, e.g. with a missing field or a value of the wrong type,
+    /// unless a custom error handler is set. Syntax errors are still answered with
+    /// `400 Bad Request`; enable [`check_well_formed`](#method.check_well_formed) to catch them
+    /// all before deserializing. By default both are answered with `400 Bad Request`
+    pub fn semantic_status_codes(mut self, semantic: bool) -> Self {
+        self.semantic_status_codes = semantic;
+        self
+    }
+
+    /// Set the level at which failed extractions are logged, along with the request path, or
+    /// disable logging them with `None`. By default they are logged at debug level
+    pub fn log_errors_at(mut self, level: Option<log::Level>) -> Self {
+        self.log_level = level;
+        self
+    }
+
+    /// Set the target failed extractions are logged with, e.g. to filter them apart from the
+    /// rest of the crate. By default the target is the module of the crate logging them
+    pub fn log_target(mut self, target: &'static str) -> Self {
+        self.log_target = Some(target);
+        self
+    }
+
+    /// Include the path of the request in the logs of failed extractions, or leave it out, e.g.
+    /// when paths carry personal data. By default it's included
+    pub fn log_path(mut self, include: bool) -> Self {
+        self.log_path = include;
+        self
+    }
+
+    /// Set the content type of responses produced by [`Xml`](struct.Xml.html).
+    /// By default `application/xml` is used
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` can't be converted into a valid header value.
+    pub fn response_content_type<V>(mut self, value: V) -> Self
+    where
+        V: TryIntoHeaderValue,
+    {
+        self.response_content_type = Some(
+            value
+                .try_into_value()
+                .unwrap_or_else(|_| panic!("invalid response content type")),
+        );
+        self
+    }
+
+    /// Pretty-print responses, indenting nested elements by `indent_size` repetitions of
+    /// `indent_char` (usually `b' '` or `b'\t'`). By default responses are written compactly
+    pub fn pretty(mut self, indent_char: u8, indent_size: usize) -> Self {
+        self.indent = Some((indent_char, indent_size));
+        self
+    }
+
+    /// Prepend an XML declaration (`<?xml version="1.0" encoding="UTF-8"?>`) to responses.
+    /// By default no declaration is written
+    pub fn emit_declaration(mut self, emit: bool) -> Self {
+        self.declaration = if emit {
+            Some(("1.0".to_string(), "UTF-8".to_string()))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Prepend an XML declaration with the given version and encoding to responses
+    ///
+    /// Note that the response is always encoded in utf-8 regardless of `encoding`.
+    pub fn declaration(mut self, version: impl Into<String>, encoding: impl Into<String>) -> Self {
+        self.declaration = Some((version.into(), encoding.into()));
+        self
+    }
+
+    /// Send [`Xml`](struct.Xml.html) responses as a chunked body of 8Kb chunks instead of a
+    /// single buffer, so the document is never held as one contiguous string. The value is
+    /// still serialized when the response is built, and errors are reported as usual. To
+    /// serialize large documents while they're sent, respond with
+    /// [`Xml::streaming`](struct.Xml.html#method.streaming) instead. By default responses are
+    /// buffered
+    pub fn stream_response(mut self, stream: bool) -> Self {
+        self.stream_response = stream;
+        self
+    }
+
+    pub(crate) fn get_stream_limit(&self) -> Option<usize> {
+        self.stream_limit.or(self.limit)
+    }
+
+    /// Negotiate the content type of the response to `req` from its `Accept` header.
+    ///
+    /// `text/xml` or `application/xml` is used if the client ranks it, or the `text/*` or
+    /// `application/*` range matching it, before `*/*`, otherwise the configured response
+    /// content type is used. Types the client refuses with a quality of 0 are skipped.
+    pub(crate) fn negotiate_response_content_type(&self, req: &HttpRequest) -> HeaderValue {
+        if let Ok(accept) = Accept::parse(req) {
+            let accepted = accept
+                .iter()
+                .filter(|item| item.quality > Quality::ZERO)
+                .cloned()
+                .collect();
+            for mime in Accept(accepted).ranked() {
+                match mime.essence_str() {
+                    "text/xml" | "text/*" => return HeaderValue::from_static("text/xml"),
+                    "application/xml" | "application/*" => {
+                        return HeaderValue::from_static("application/xml")
+                    }
+                    "*/*" => break,
+                    _ => (),
+                }
+            }
+        }
+
+        self.get_response_content_type()
+    }
+
+    fn get_response_content_type(&self) -> HeaderValue {
+        const DEFAULT_RESPONSE_CONTENT_TYPE: HeaderValue =
+            HeaderValue::from_static("application/xml");
+
+        self.response_content_type
+            .clone()
+            .unwrap_or(DEFAULT_RESPONSE_CONTENT_TYPE)
+    }
+
+    /// Whether warnings are reported, so that looking for them can be skipped otherwise.
+    pub(crate) fn warns(&self) -> bool {
+        self.on_warning.is_some()
+    }
+
+    /// Report `warning` to the [`on_warning`](#method.on_warning) callback, if any.
+    pub(crate) fn warn(&self, warning: XmlWarning) {
+        if let Some(on_warning) = &self.on_warning {
+            on_warning(&warning);
+        }
+    }
+
+    /// Log the error of an extraction from `req` and turn it into a response error, with the
+    /// [`error_handler`](#method.error_handler) if any.
+    pub(crate) fn reject(&self, e: XMLPayloadError, req: &HttpRequest) -> ActixError {
+        if let Some(level) = self.log_level {
+            let target = self.log_target.unwrap_or(module_path!());
+            if self.log_path {
+                log::log!(
+                    target: target,
+                    level,
+                    "Failed to extract XML from payload: {}. Request path: {}",
+                    e,
+                    req.path()
+                );
             } else {
-                Err(XMLPayloadError::ContentType)
+                log::log!(target: target, level, "Failed to extract XML from payload: {}", e);
             }
-        } else {
-            Err(XMLPayloadError::ContentType)
         }
+        match &self.err_handler {
+            Some(err_handler) => (*err_handler)(e, req),
+            None => e.into_error(self.error_status()),
+        }
+    }
+
+    /// Status codes of error responses set in this config.
+    pub(crate) fn error_status(&self) -> ErrorStatus {
+        ErrorStatus {
+            overflow: self.overflow_status,
+            semantic: self.semantic_status_codes,
+        }
+    }
+
+    /// Check the headers of `req` before reading its payload, so that requests rejected by
+    /// their content type or content length never touch the payload.
+    pub(crate) fn check_headers(&self, req: &HttpRequest) -> Result<(), XMLPayloadError> {
+        self.check_content_type(req)?;
+        self.check_content_length(req)
+    }
+
+    /// Check the content length of `req` against the limit, reporting rejections to the
+    /// [`on_complete`](#method.on_complete) callback like `XmlBody` does.
+    fn check_content_length(&self, req: &HttpRequest) -> Result<(), XMLPayloadError> {
+        let length = crate::content_length(req);
+        let res = match (&length, self.limit) {
+            (Err(_), _) => Err(XMLPayloadError::InvalidLength),
+            (Ok(None), _) if self.require_content_length => Err(XMLPayloadError::LengthRequired),
+            (Ok(Some(length)), Some(limit)) if *length > limit => Err(XMLPayloadError::Overflow {
+                limit,
+                length: Some(*length),
+                decompressed: false,
+            }),
+            _ => return Ok(()),
+        };
+
+        if let Some(on_complete) = &self.on_complete {
+            on_complete(&XmlStats {
+                bytes: length.ok().flatten().unwrap_or(0),
+                parse_duration: Duration::ZERO,
+                outcome: Outcome::from_error(res.as_ref().err()),
+            });
+        }
+        res
+    }
+
+    pub(crate) fn check_content_type(&self, req: &HttpRequest) -> Result<(), XMLPayloadError> {
+        if self.accept_any_content_type {
+            return Ok(());
+        }
+
+        // Only the first header counts if there are several, and one that isn't a valid mime
+        // type is rejected like a missing one
+        match req.mime_type() {
+            Ok(Some(mime)) if self.accepts(&mime) => Ok(()),
+            Ok(Some(mime)) => Err(XMLPayloadError::ContentType {
+                received: Some(mime),
+            }),
+            Ok(None) if !self.require_content_type => Ok(()),
+            _ => Err(XMLPayloadError::ContentType { received: None }),
+        }
+    }
+
+    /// Whether `mime` is an accepted XML content type, regardless of
+    /// [`accept_any_content_type`](#method.accept_any_content_type).
+    pub(crate) fn accepts(&self, mime: &mime::Mime) -> bool {
+        // Parameters such as the charset don't take part in the match
+        mime.essence_str() == "text/xml"
+            || mime.essence_str() == "application/xml"
+            || (self.accept_xml_suffix && mime.suffix() == Some(mime::XML))
+            || self
+                .accepted_content_types
+                .iter()
+                .any(|accepted| match accepted.subtype() {
+                    mime::STAR => {
+                        accepted.type_() == mime::STAR || accepted.type_() == mime.type_()
+                    }
+                    _ => accepted.essence_str() == mime.essence_str(),
+                })
+            || self
+                .content_type
+                .as_ref()
+                .is_some_and(|predicate| predicate(mime.clone()))
     }
 
-    /// Extract payload config from app data. Check both `T` and `Data<T>`, in that order, and fall
-    /// back to the default payload config.
-    pub(crate) fn from_req(req: &HttpRequest) -> &Self {
-        req.app_data::<Self>()
-            .or_else(|| req.app_data::<web::Data<Self>>().map(|d| d.as_ref()))
-            .unwrap_or(&DEFAULT_CONFIG)
+    /// Extract payload config from the request extensions, as inserted by hand or set by
+    /// [`XmlConfigMiddleware`](struct.XmlConfigMiddleware.html), or from app data. Check both
+    /// `T` and `Data<T>`, in that order, and fall back to the default payload config.
+    ///
+    /// App data is resolved from the innermost resource outwards, so a resource's config overrides
+    /// the app-level one.
+    pub(crate) fn from_req(req: &HttpRequest) -> ConfigRef<'_> {
+        {
+            // The extensions can't be borrowed past this call, so the config is copied
+            let extensions = req.extensions();
+            if let Some(config) = extensions.get::<Self>() {
+                return ConfigRef::Request(Box::new(config.clone()));
+            }
+            if let Some(ScopedConfig(config)) = extensions.get::<ScopedConfig>() {
+                return ConfigRef::Scoped(config.clone());
+            }
+        }
+        ConfigRef::AppData(
+            req.app_data::<Self>()
+                .or_else(|| req.app_data::<web::Data<Self>>().map(|d| d.as_ref()))
+                .unwrap_or(&DEFAULT_CONFIG),
+        )
+    }
+}
+
+/// Config set for the requests of a scope by
+/// [`XmlConfigMiddleware`](struct.XmlConfigMiddleware.html), in their extensions.
+pub(crate) struct ScopedConfig(pub(crate) Arc<XmlConfig>);
+
+/// Config of a request, borrowed from app data, set by a middleware or copied from the request
+/// extensions.
+pub(crate) enum ConfigRef<'a> {
+    AppData(&'a XmlConfig),
+    Scoped(Arc<XmlConfig>),
+    Request(Box<XmlConfig>),
+}
+
+impl ops::Deref for ConfigRef<'_> {
+    type Target = XmlConfig;
+
+    fn deref(&self) -> &XmlConfig {
+        match self {
+            ConfigRef::AppData(config) => config,
+            ConfigRef::Scoped(config) => config,
+            ConfigRef::Request(config) => config,
+        }
+    }
+}
+
+/// Read and parse the environment variable `name`, warning if it's set but invalid.
+fn env_var<T>(name: &str) -> Option<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let value = match env::var(name) {
+        Ok(value) => value,
+        Err(env::VarError::NotPresent) => return None,
+        Err(e) => {
+            log::warn!("Ignoring environment variable {}: {}", name, e);
+            return None;
+        }
+    };
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(e) => {
+            log::warn!("Ignoring environment variable {}={:?}: {}", name, value, e);
+            None
+        }
     }
 }
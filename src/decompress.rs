@@ -0,0 +1,57 @@
+use std::io::Read;
+
+use actix_web::error::PayloadError;
+use actix_web::http::header;
+use actix_web::HttpRequest;
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+use crate::error::XMLPayloadError;
+
+/// Content encoding of a request that can be decompressed without actix-web's compress support.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ContentEncoding {
+    Gzip,
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// Read the content encoding of `req`, if it's a supported one.
+    pub(crate) fn from_req(req: &HttpRequest) -> Option<Self> {
+        let encoding = req.headers().get(header::CONTENT_ENCODING)?.to_str().ok()?;
+        match encoding.trim() {
+            e if e.eq_ignore_ascii_case("gzip") || e.eq_ignore_ascii_case("x-gzip") => {
+                Some(ContentEncoding::Gzip)
+            }
+            e if e.eq_ignore_ascii_case("deflate") => Some(ContentEncoding::Deflate),
+            _ => None,
+        }
+    }
+
+    /// Decompress `body`, failing with an overflow as soon as the output exceeds `limit`.
+    pub(crate) fn decompress(
+        self,
+        body: &[u8],
+        limit: Option<usize>,
+    ) -> Result<Vec<u8>, XMLPayloadError> {
+        let decoder: Box<dyn Read> = match self {
+            ContentEncoding::Gzip => Box::new(GzDecoder::new(body)),
+            ContentEncoding::Deflate => Box::new(ZlibDecoder::new(body)),
+        };
+
+        // Reading one byte past the limit is enough to tell it's exceeded
+        let mut decompressed = Vec::with_capacity(body.len().min(limit.unwrap_or(usize::MAX)));
+        decoder
+            .take(limit.map_or(u64::MAX, |limit| limit as u64 + 1))
+            .read_to_end(&mut decompressed)
+            .map_err(|_| PayloadError::EncodingCorrupted)?;
+
+        if let Some(limit) = limit.filter(|&limit| decompressed.len() > limit) {
+            return Err(XMLPayloadError::Overflow {
+                limit,
+                length: None,
+                decompressed: true,
+            });
+        }
+        Ok(decompressed)
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use quick_xml::escape::unescape_with;
+use quick_xml::events::{BytesStart, BytesText, Event};
+use quick_xml::{DeError as XMLError, Reader, Writer};
+
+/// Resolver of custom entities, for documents referencing entities other than the predefined
+/// ones (`&lt;`, `&gt;`, `&amp;`, `&apos;` and `&quot;`).
+///
+/// Use [`XmlConfig::entity_resolver`](struct.XmlConfig.html#method.entity_resolver) to register
+/// one.
+///
+/// # Example
+///
+/// ```rust
+/// use actix_xml::{EntityResolver, XmlConfig};
+///
+/// struct Company;
+///
+/// impl EntityResolver for Company {
+///     fn resolve(&self, entity: &str) -> Option<&str> {
+///         match entity {
+///             "company" => Some("Example Inc."),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// let config = XmlConfig::default().entity_resolver(Company);
+/// ```
+pub trait EntityResolver {
+    /// Resolve `entity` (the name between `&` and `;`) into its replacement text, or return
+    /// `None` if the entity is unknown.
+    ///
+    /// The replacement text is taken literally, i.e. markup in it won't be parsed.
+    fn resolve(&self, entity: &str) -> Option<&str>;
+}
+
+impl EntityResolver for HashMap<String, String> {
+    fn resolve(&self, entity: &str) -> Option<&str> {
+        self.get(entity).map(String::as_str)
+    }
+}
+
+/// Replace custom entities in text and attribute values of `text`, escaping their replacement.
+pub(crate) fn resolve_entities(
+    text: &str,
+    resolver: &(dyn EntityResolver + Send + Sync),
+) -> Result<String, XMLError> {
+    let resolve = |entity: &str| resolver.resolve(entity);
+
+    let mut reader = Reader::from_str(text);
+    // End names are checked by the deserializer, if enabled
+    reader.check_end_names(false);
+    let mut writer = Writer::new(Vec::with_capacity(text.len()));
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                writer.write_event(Event::Start(resolve_attributes(&element, resolve)?))?
+            }
+            Event::Empty(element) => {
+                writer.write_event(Event::Empty(resolve_attributes(&element, resolve)?))?
+            }
+            Event::Text(content) => {
+                let content = unescape(&content, resolve)?;
+                writer.write_event(Event::Text(BytesText::new(&content)))?
+            }
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+fn resolve_attributes<'a, 'e>(
+    element: &BytesStart,
+    resolve: impl Fn(&str) -> Option<&'e str> + Copy,
+) -> Result<BytesStart<'a>, XMLError> {
+    let name = std::str::from_utf8(element.name().into_inner()).map_err(quick_xml::Error::from)?;
+    let mut resolved = BytesStart::new(name.to_string());
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(quick_xml::Error::from)?;
+        let key =
+            std::str::from_utf8(attribute.key.into_inner()).map_err(quick_xml::Error::from)?;
+        let value = unescape(&attribute.value, resolve)?;
+        resolved.push_attribute((key, value.as_str()));
+    }
+    Ok(resolved)
+}
+
+fn unescape<'e>(raw: &[u8], resolve: impl Fn(&str) -> Option<&'e str>) -> Result<String, XMLError> {
+    let raw = std::str::from_utf8(raw).map_err(quick_xml::Error::from)?;
+    Ok(unescape_with(raw, resolve)
+        .map_err(quick_xml::Error::from)?
+        .into_owned())
+}
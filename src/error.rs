@@ -1,31 +1,360 @@
-use actix_web::error::PayloadError;
+use std::time::Duration;
+
+use actix_web::error::{InternalError, PayloadError};
 use actix_web::http::StatusCode;
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{Error as ActixError, HttpResponse, ResponseError};
 use quick_xml::DeError as XMLError;
 use thiserror::Error;
 
 /// A set of errors that can occur during parsing xml payloads
 #[derive(Debug, Error)]
 pub enum XMLPayloadError {
-    /// Payload size is bigger than allowed. (default: 32kB)
-    #[error("Xml payload size is bigger than allowed")]
-    Overflow,
+    /// Payload size is bigger than allowed. (default: 256kB)
+    #[error(
+        "Xml payload size {} exceeds limit {limit}{}",
+        .length.map_or_else(|| "unknown".to_string(), |length| length.to_string()),
+        if *.decompressed { " once decompressed" } else { "" }
+    )]
+    Overflow {
+        /// The configured payload size limit
+        limit: usize,
+        /// The payload size, as declared by the content-length header or counted while streaming
+        length: Option<usize>,
+        /// Whether the size is that of the payload once decompressed, following the
+        /// `Content-Encoding` header, e.g. to tell decompression bombs apart
+        decompressed: bool,
+    },
+    /// Payload is empty or whitespace-only, and the target type can't be deserialized from it
+    /// (unlike e.g. `Option<T>`)
+    #[error("Xml payload is empty")]
+    Empty,
     /// Content type error
-    #[error("Content type error")]
-    ContentType,
+    #[error(
+        "{}",
+        .received.as_ref().map_or_else(
+            || "missing content type".to_string(),
+            |received| format!("Unsupported content type: {}", received)
+        )
+    )]
+    ContentType {
+        /// The content type of the request, if it has a valid one
+        received: Option<mime::Mime>,
+    },
+    /// Content length header is missing, while it's required by the config
+    #[error("Xml payload has no content length")]
+    LengthRequired,
+    /// Content length header isn't a number, or is too large to be one
+    #[error("Xml payload has an invalid content length")]
+    InvalidLength,
+    /// Payload wasn't read in full within the time allowed by the config
+    #[error("Xml payload wasn't read within {timeout:?}")]
+    Timeout {
+        /// The configured read timeout
+        timeout: Duration,
+    },
     /// Deserialize error
-    #[error("Xml deserialize error: {0}")]
-    Deserialize(#[from] XMLError),
+    #[error(
+        "Xml deserialize error: {source}{}{}",
+        .position.map(|position| format!(" at byte {}", position)).unwrap_or_default(),
+        .context.as_ref().map(|context| format!(" ({})", context)).unwrap_or_default()
+    )]
+    Deserialize {
+        /// The underlying quick-xml error
+        source: XMLError,
+        /// Approximate byte offset into the decoded document where deserialization failed, if
+        /// known. It's only computed for malformed documents the config didn't rewrite before
+        /// deserializing them, e.g. to resolve entities or normalize namespaces
+        position: Option<usize>,
+        /// Path of the field that failed and markup around it, with
+        /// [`XmlConfig::verbose_errors`](struct.XmlConfig.html#method.verbose_errors)
+        context: Option<String>,
+    },
+    /// Document isn't well-formed XML, as found by the check enabled in the config
+    #[error("Xml document is not well-formed at byte {position}: {source}")]
+    NotWellFormed {
+        /// The underlying quick-xml error
+        source: XMLError,
+        /// Approximate byte offset of the malformed markup into the decoded document
+        position: usize,
+    },
+    /// Document ends before it's complete, e.g. because the payload was truncated
+    #[error(
+        "Xml payload is incomplete: {source}{}",
+        .position.map(|position| format!(" at byte {}", position)).unwrap_or_default()
+    )]
+    Incomplete {
+        /// The underlying quick-xml error
+        source: XMLError,
+        /// Byte offset into the decoded document where it ends, if known. Like for
+        /// [`Deserialize`](#variant.Deserialize) errors, it's not computed for documents the
+        /// config rewrote before deserializing them
+        position: Option<usize>,
+    },
+    /// Serialize error
+    #[error("Xml serialize error: {0}")]
+    Serialize(XMLError),
+    /// Document declares a DOCTYPE internal subset, which may be used for entity expansion attacks
+    #[error("Xml document declares a DOCTYPE internal subset, which is not allowed")]
+    EntityExpansionLimit,
+    /// Document has a DOCTYPE declaration, which is forbidden by the config
+    #[error("Xml document has a DOCTYPE declaration, which is not allowed")]
+    DoctypeForbidden,
+    /// Elements are nested deeper than allowed
+    #[error("Xml elements are nested deeper than limit {limit}")]
+    DepthLimitExceeded {
+        /// The configured depth limit
+        limit: usize,
+    },
+    /// Document has more elements than allowed
+    #[error("Xml document has more elements than limit {limit}")]
+    ElementLimitExceeded {
+        /// The configured element limit
+        limit: usize,
+    },
+    /// Element has more attributes, or an attribute value longer, than allowed
+    #[error(
+        "Xml {} limit {limit}",
+        if *.value_length { "attribute value is longer than" } else { "element has more attributes than" }
+    )]
+    AttributeLimitExceeded {
+        /// The configured limit
+        limit: usize,
+        /// Whether the limit is that on the length of attribute values, rather than on the
+        /// number of attributes of an element
+        value_length: bool,
+    },
+    /// Payload starts like another format than XML, e.g. JSON sent as XML
+    #[error("Xml payload is not XML: it starts with `{found}` instead of `<`")]
+    NotXml {
+        /// The first character of the payload that isn't whitespace, `{` or `[`
+        found: char,
+    },
+    /// Multipart payload has no complete part with an accepted XML content type
+    #[error("Multipart payload has no XML part")]
+    NoXmlPart,
+    /// Document has text before the root element, e.g. plain text sent as XML
+    #[error("expected XML element at root")]
+    NotAnElement,
+    /// Root element isn't the one required by the config
+    #[error("Unexpected root element: expected `{expected}`, found `{found}`")]
+    UnexpectedRoot {
+        /// The required root element name
+        expected: String,
+        /// The root element name found in the document
+        found: String,
+    },
+    /// Root element isn't in the namespace required by the config
+    #[error(
+        "Unexpected root namespace: expected `{expected}`, found {}",
+        .found.as_ref().map_or_else(|| "none".to_string(), |found| format!("`{}`", found))
+    )]
+    NamespaceMismatch {
+        /// The required namespace URI
+        expected: String,
+        /// The namespace URI of the root element, if it has one
+        found: Option<String>,
+    },
+    /// Document doesn't conform to the schema set in the config
+    #[error("Xml schema validation error: {0}")]
+    SchemaValidation(String),
     /// Payload error
     #[error("Error that occur during reading payload: {0}")]
     Payload(#[from] PayloadError),
 }
 
+/// Kind of an [`XMLPayloadError`](enum.XMLPayloadError.html), one per variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XmlErrorKind {
+    /// See [`XMLPayloadError::Overflow`]
+    Overflow,
+    /// See [`XMLPayloadError::Empty`]
+    Empty,
+    /// See [`XMLPayloadError::ContentType`]
+    ContentType,
+    /// See [`XMLPayloadError::LengthRequired`]
+    LengthRequired,
+    /// See [`XMLPayloadError::InvalidLength`]
+    InvalidLength,
+    /// See [`XMLPayloadError::Timeout`]
+    Timeout,
+    /// See [`XMLPayloadError::Deserialize`]
+    Deserialize,
+    /// See [`XMLPayloadError::NotWellFormed`]
+    NotWellFormed,
+    /// See [`XMLPayloadError::Incomplete`]
+    Incomplete,
+    /// See [`XMLPayloadError::Serialize`]
+    Serialize,
+    /// See [`XMLPayloadError::EntityExpansionLimit`]
+    EntityExpansionLimit,
+    /// See [`XMLPayloadError::DoctypeForbidden`]
+    DoctypeForbidden,
+    /// See [`XMLPayloadError::DepthLimitExceeded`]
+    DepthLimitExceeded,
+    /// See [`XMLPayloadError::ElementLimitExceeded`]
+    ElementLimitExceeded,
+    /// See [`XMLPayloadError::AttributeLimitExceeded`]
+    AttributeLimitExceeded,
+    /// See [`XMLPayloadError::NotXml`]
+    NotXml,
+    /// See [`XMLPayloadError::NoXmlPart`]
+    NoXmlPart,
+    /// See [`XMLPayloadError::NotAnElement`]
+    NotAnElement,
+    /// See [`XMLPayloadError::UnexpectedRoot`]
+    UnexpectedRoot,
+    /// See [`XMLPayloadError::NamespaceMismatch`]
+    NamespaceMismatch,
+    /// See [`XMLPayloadError::SchemaValidation`]
+    SchemaValidation,
+    /// See [`XMLPayloadError::Payload`]
+    Payload,
+}
+
+impl XmlErrorKind {
+    /// Snake case name of the kind, e.g. `content_type`, used as the code in error response
+    /// bodies
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            XmlErrorKind::Overflow => "overflow",
+            XmlErrorKind::Empty => "empty",
+            XmlErrorKind::ContentType => "content_type",
+            XmlErrorKind::LengthRequired => "length_required",
+            XmlErrorKind::InvalidLength => "invalid_length",
+            XmlErrorKind::Timeout => "timeout",
+            XmlErrorKind::Deserialize => "deserialize",
+            XmlErrorKind::NotWellFormed => "not_well_formed",
+            XmlErrorKind::Incomplete => "incomplete",
+            XmlErrorKind::Serialize => "serialize",
+            XmlErrorKind::EntityExpansionLimit => "entity_expansion_limit",
+            XmlErrorKind::DoctypeForbidden => "doctype_forbidden",
+            XmlErrorKind::DepthLimitExceeded => "depth_limit_exceeded",
+            XmlErrorKind::ElementLimitExceeded => "element_limit_exceeded",
+            XmlErrorKind::AttributeLimitExceeded => "attribute_limit_exceeded",
+            XmlErrorKind::NotXml => "not_xml",
+            XmlErrorKind::NoXmlPart => "no_xml_part",
+            XmlErrorKind::NotAnElement => "not_an_element",
+            XmlErrorKind::UnexpectedRoot => "unexpected_root",
+            XmlErrorKind::NamespaceMismatch => "namespace_mismatch",
+            XmlErrorKind::SchemaValidation => "schema_validation",
+            XmlErrorKind::Payload => "payload",
+        }
+    }
+}
+
+impl From<XMLError> for XMLPayloadError {
+    fn from(source: XMLError) -> Self {
+        match source {
+            XMLError::UnexpectedEof | XMLError::InvalidXml(quick_xml::Error::UnexpectedEof(_)) => {
+                XMLPayloadError::Incomplete {
+                    source,
+                    position: None,
+                }
+            }
+            source => XMLPayloadError::Deserialize {
+                source,
+                position: None,
+                context: None,
+            },
+        }
+    }
+}
+
+/// Status codes of error responses overriding the default ones, as set in the config.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ErrorStatus {
+    /// Status of responses to oversized payloads
+    pub(crate) overflow: Option<StatusCode>,
+    /// Respond `422 Unprocessable Entity` to documents that don't match the target type
+    pub(crate) semantic: bool,
+}
+
+impl XMLPayloadError {
+    /// Convert into an actix error, responding with the statuses of `status` where they apply.
+    pub(crate) fn into_error(self, status: ErrorStatus) -> ActixError {
+        let status = match &self {
+            XMLPayloadError::Overflow { .. } => status.overflow,
+            // Syntax errors met while deserializing are reported as invalid XML, anything else
+            // is about the shape of the document
+            XMLPayloadError::Deserialize { source, .. }
+                if status.semantic && !matches!(source, XMLError::InvalidXml(_)) =>
+            {
+                Some(StatusCode::UNPROCESSABLE_ENTITY)
+            }
+            _ => None,
+        };
+        match status {
+            Some(status) => {
+                let response = self.response_with_status(status);
+                InternalError::from_response(self, response).into()
+            }
+            None => self.into(),
+        }
+    }
+
+    /// Classify the error, e.g. to keep a copy of it since the error itself isn't `Clone`
+    pub fn kind(&self) -> XmlErrorKind {
+        match self {
+            XMLPayloadError::Overflow { .. } => XmlErrorKind::Overflow,
+            XMLPayloadError::Empty => XmlErrorKind::Empty,
+            XMLPayloadError::ContentType { .. } => XmlErrorKind::ContentType,
+            XMLPayloadError::LengthRequired => XmlErrorKind::LengthRequired,
+            XMLPayloadError::InvalidLength => XmlErrorKind::InvalidLength,
+            XMLPayloadError::Timeout { .. } => XmlErrorKind::Timeout,
+            XMLPayloadError::Deserialize { .. } => XmlErrorKind::Deserialize,
+            XMLPayloadError::NotWellFormed { .. } => XmlErrorKind::NotWellFormed,
+            XMLPayloadError::Incomplete { .. } => XmlErrorKind::Incomplete,
+            XMLPayloadError::Serialize(_) => XmlErrorKind::Serialize,
+            XMLPayloadError::EntityExpansionLimit => XmlErrorKind::EntityExpansionLimit,
+            XMLPayloadError::DoctypeForbidden => XmlErrorKind::DoctypeForbidden,
+            XMLPayloadError::DepthLimitExceeded { .. } => XmlErrorKind::DepthLimitExceeded,
+            XMLPayloadError::ElementLimitExceeded { .. } => XmlErrorKind::ElementLimitExceeded,
+            XMLPayloadError::AttributeLimitExceeded { .. } => XmlErrorKind::AttributeLimitExceeded,
+            XMLPayloadError::NotXml { .. } => XmlErrorKind::NotXml,
+            XMLPayloadError::NoXmlPart => XmlErrorKind::NoXmlPart,
+            XMLPayloadError::NotAnElement => XmlErrorKind::NotAnElement,
+            XMLPayloadError::UnexpectedRoot { .. } => XmlErrorKind::UnexpectedRoot,
+            XMLPayloadError::NamespaceMismatch { .. } => XmlErrorKind::NamespaceMismatch,
+            XMLPayloadError::SchemaValidation(_) => XmlErrorKind::SchemaValidation,
+            XMLPayloadError::Payload(_) => XmlErrorKind::Payload,
+        }
+    }
+
+    /// Build the response to this error with the given status.
+    ///
+    /// With the `xml-error-body` feature, the body is an XML document like
+    /// `<error><code>overflow</code><message>...</message></error>`.
+    fn response_with_status(&self, status: StatusCode) -> HttpResponse {
+        #[cfg(feature = "xml-error-body")]
+        {
+            use quick_xml::escape::escape;
+
+            HttpResponse::build(status)
+                .content_type("application/xml")
+                .body(format!(
+                    "<error><code>{}</code><message>{}</message></error>",
+                    self.kind().as_str(),
+                    escape(&self.to_string())
+                ))
+        }
+        #[cfg(not(feature = "xml-error-body"))]
+        HttpResponse::new(status)
+    }
+}
+
 impl ResponseError for XMLPayloadError {
-    fn error_response(&self) -> actix_web::HttpResponse {
+    fn status_code(&self) -> StatusCode {
         match *self {
-            XMLPayloadError::Overflow => HttpResponse::new(StatusCode::PAYLOAD_TOO_LARGE),
-            _ => HttpResponse::new(StatusCode::BAD_REQUEST),
+            XMLPayloadError::Overflow { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            XMLPayloadError::ContentType { .. } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            XMLPayloadError::LengthRequired => StatusCode::LENGTH_REQUIRED,
+            XMLPayloadError::Timeout { .. } => StatusCode::REQUEST_TIMEOUT,
+            XMLPayloadError::Serialize(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        self.response_with_status(self.status_code())
+    }
 }
@@ -0,0 +1,203 @@
+use std::fmt;
+
+use quick_xml::escape::escape;
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+
+/// Catch-all for elements and attributes not matched by other fields of a struct.
+///
+/// Use it as a `#[serde(flatten)]` field to retain extension elements instead of silently
+/// dropping them. Each leftover element is captured as a pair of its name and its content.
+/// Nested elements are rebuilt into markup, with their attributes, since the original text is
+/// not available through serde: only the formatting of the markup may differ, e.g. empty
+/// elements are written with an end tag.
+///
+/// Attributes are captured with an XPath-like name, so they can't be mistaken for elements:
+/// leftover attributes of the struct as `@name`, and attributes of a leftover element before
+/// it, as `element/@name`.
+///
+/// Note that due to the way serde buffers flattened structs, other fields of the struct can
+/// only be deserialized from strings (e.g. `String`, but not `u32`).
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::{Xml, XmlExtra};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Info {
+///     username: String,
+///     #[serde(flatten)]
+///     extra: XmlExtra,
+/// }
+///
+/// let info = Xml::<Info>::from_slice(
+///     b"<Info><username>user</username><note lang=\"en\">hello</note></Info>"
+/// ).unwrap();
+/// assert_eq!(
+///     info.extra.0,
+///     vec![
+///         ("note/@lang".to_string(), "en".to_string()),
+///         ("note".to_string(), "hello".to_string()),
+///     ]
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XmlExtra(pub Vec<(String, String)>);
+
+impl<'de> Deserialize<'de> for XmlExtra {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ExtraVisitor;
+
+        impl<'de> Visitor<'de> for ExtraVisitor {
+            type Value = XmlExtra;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("xml elements")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut extra = Vec::new();
+                while let Some((name, node)) = map.next_entry::<String, Node>()? {
+                    match node {
+                        Node::Text(text) if name == "$value" => extra.push((name, text)),
+                        Node::Text(value) => extra.push((format!("@{}", name), value)),
+                        Node::Element {
+                            attributes,
+                            content,
+                        } => {
+                            for (attribute, value) in attributes {
+                                extra.push((format!("{}/@{}", name, attribute), value));
+                            }
+                            extra.push((name, content));
+                        }
+                    }
+                }
+                Ok(XmlExtra(extra))
+            }
+        }
+
+        deserializer.deserialize_map(ExtraVisitor)
+    }
+}
+
+/// Value of a leftover entry, told apart by quick-xml reporting attributes and text as strings,
+/// and elements as maps.
+enum Node {
+    /// Value of an attribute, or text
+    Text(String),
+    /// Element, with its content rebuilt into markup
+    Element {
+        attributes: Vec<(String, String)>,
+        content: String,
+    },
+}
+
+impl Node {
+    /// Content of the node as markup.
+    fn into_content(self) -> String {
+        match self {
+            Node::Text(text) => escape(&text).into_owned(),
+            Node::Element { content, .. } => content,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct NodeVisitor;
+
+        impl<'de> Visitor<'de> for NodeVisitor {
+            type Value = Node;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("xml content")
+            }
+
+            fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+                Ok(Node::Text(v.to_string()))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(Node::Text(v.to_string()))
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(Node::Text(v.to_string()))
+            }
+
+            fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+                Ok(Node::Text(v.to_string()))
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(Node::Text(v.to_string()))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+                Ok(Node::Text(v))
+            }
+
+            fn visit_unit<E>(self) -> Result<Self::Value, E> {
+                Ok(Node::Element {
+                    attributes: Vec::new(),
+                    content: String::new(),
+                })
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut content = String::new();
+                while let Some(item) = seq.next_element::<Node>()? {
+                    content.push_str(&item.into_content());
+                }
+                Ok(Node::Element {
+                    attributes: Vec::new(),
+                    content,
+                })
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut attributes = Vec::new();
+                let mut content = String::new();
+                while let Some((name, node)) = map.next_entry::<String, Node>()? {
+                    match node {
+                        Node::Text(value) if name != "$value" => attributes.push((name, value)),
+                        Node::Element {
+                            attributes: child_attributes,
+                            content: child,
+                        } if name != "$value" => {
+                            content.push('<');
+                            content.push_str(&name);
+                            for (attribute, value) in child_attributes {
+                                content.push_str(&format!(" {}=\"{}\"", attribute, escape(&value)));
+                            }
+                            content.push_str(&format!(">{}</{}>", child, name));
+                        }
+                        node => content.push_str(&node.into_content()),
+                    }
+                }
+                Ok(Node::Element {
+                    attributes,
+                    content,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
@@ -0,0 +1,83 @@
+use std::future::Future;
+
+use actix_web::dev;
+use actix_web::Error as ActixError;
+use actix_web::{HttpMessage, HttpRequest};
+use futures::future::{err, Either, LocalBoxFuture, Ready};
+use futures::{FutureExt, TryFutureExt};
+
+use crate::config::XmlConfig;
+use crate::error::XMLPayloadError;
+use crate::{Parser, XmlBody, XmlMeta};
+
+/// Future of the extractors of the crate, ready right away if the headers are rejected.
+pub(crate) type ExtractFuture<T> =
+    Either<LocalBoxFuture<'static, Result<T, ActixError>>, Ready<Result<T, ActixError>>>;
+
+/// Extract a value from `req` the way every extractor of the crate does.
+///
+/// `read` checks the headers of `req` with its config, then returns the future reading the
+/// payload, which resolves to the value along with the size of the document in bytes. The
+/// size is recorded in the request extensions as [`XmlMeta`], errors are logged and turned into
+/// responses by [`XmlConfig::reject`], and the extraction is traced with the `tracing` feature.
+pub(crate) fn extract<T, R, F>(req: &HttpRequest, read: R) -> ExtractFuture<T>
+where
+    T: 'static,
+    R: FnOnce(&XmlConfig) -> Result<F, XMLPayloadError>,
+    F: Future<Output = Result<(usize, T), XMLPayloadError>> + 'static,
+{
+    let config = XmlConfig::from_req(req);
+    #[cfg(feature = "tracing")]
+    let extraction = crate::trace::Extraction::new(req, config.limit);
+
+    let read = match read(&config) {
+        Ok(read) => read,
+        Err(e) => {
+            #[cfg(feature = "tracing")]
+            extraction.finish(Some(&e));
+            return Either::Right(err(config.reject(e, req)));
+        }
+    };
+
+    let req = req.clone();
+    #[cfg(feature = "tracing")]
+    let span = extraction.span();
+    let fut = read.map(move |res| {
+        #[cfg(feature = "tracing")]
+        extraction.finish(res.as_ref().err());
+
+        match res {
+            Err(e) => Err(XmlConfig::from_req(&req).reject(e, &req)),
+            Ok((bytes, value)) => {
+                req.extensions_mut().insert(XmlMeta { bytes });
+                Ok(value)
+            }
+        }
+    });
+    #[cfg(feature = "tracing")]
+    let fut = tracing::Instrument::instrument(fut, span);
+
+    Either::Left(fut.boxed_local())
+}
+
+/// Extract a value from the buffered payload of `req`, after checking its headers, with `parse`
+/// resolving to the document along with its size and `wrap` making the value of it.
+pub(crate) fn extract_body<T, V>(
+    req: &HttpRequest,
+    payload: &mut dev::Payload,
+    parse: Parser<(usize, T)>,
+    wrap: fn(T) -> V,
+) -> ExtractFuture<V>
+where
+    T: 'static,
+    V: 'static,
+{
+    extract(req, |config| {
+        config.check_headers(req)?;
+        // the type parameter is irrelevant as the parser is replaced
+        Ok(XmlBody::<()>::new(req, payload)
+            .config_limits(config)
+            .with_parser(parse)
+            .map_ok(move |(bytes, value)| (bytes, wrap(value))))
+    })
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use serde::de::value::{MapAccessDeserializer, StringDeserializer};
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+
+/// Deserialize repeated elements into a `HashMap` keyed by one of their attributes
+///
+/// Use it on a field matching repeated elements, e.g. the `item` elements of
+/// `<items><item id="a">..</item><item id="b">..</item></items>`, with a function passed to
+/// `#[serde(deserialize_with = "..")]`. The attribute named `attr` is removed from each element
+/// and deserialized as the key, and the rest of the element as the value. `attr` is matched
+/// like a field name, so use `@id` for the `id` attribute when
+/// [`XmlConfig::prefix_attributes`](struct.XmlConfig.html#method.prefix_attributes) is enabled.
+///
+/// Elements without the attribute fail to deserialize, and so do elements with the same key,
+/// unless [`last_wins`](struct.KeyedByAttr.html#method.last_wins) is set.
+///
+/// ## Example
+///
+/// ```rust
+/// use std::collections::HashMap;
+///
+/// use actix_xml::{keyed_by_attr, parse_xml, XmlConfig};
+/// use serde::de::{DeserializeSeed, Deserializer};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Item {
+///     name: String,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Items {
+///     #[serde(rename = "item", deserialize_with = "by_id")]
+///     items: HashMap<String, Item>,
+/// }
+///
+/// fn by_id<'de, D>(deserializer: D) -> Result<HashMap<String, Item>, D::Error>
+/// where
+///     D: Deserializer<'de>,
+/// {
+///     keyed_by_attr("id").deserialize(deserializer)
+/// }
+///
+/// let items: Items = parse_xml(
+///     b"<items><item id=\"a\"><name>Tea</name></item><item id=\"b\"><name>Milk</name></item></items>",
+///     &XmlConfig::default(),
+/// )
+/// .unwrap();
+/// assert_eq!(items.items["b"].name, "Milk");
+/// ```
+pub fn keyed_by_attr<K, V>(attr: &'static str) -> KeyedByAttr<K, V> {
+    KeyedByAttr {
+        attr,
+        last_wins: false,
+        marker: PhantomData,
+    }
+}
+
+/// Seed deserializing repeated elements into a `HashMap`, see
+/// [`keyed_by_attr`](fn.keyed_by_attr.html)
+pub struct KeyedByAttr<K, V> {
+    attr: &'static str,
+    last_wins: bool,
+    marker: PhantomData<fn() -> HashMap<K, V>>,
+}
+
+impl<K, V> KeyedByAttr<K, V> {
+    /// Keep the last of the elements with the same key instead of failing. By default
+    /// duplicate keys fail to deserialize
+    pub fn last_wins(mut self, last_wins: bool) -> Self {
+        self.last_wins = last_wins;
+        self
+    }
+}
+
+impl<K, V> Clone for KeyedByAttr<K, V> {
+    fn clone(&self) -> Self {
+        KeyedByAttr {
+            attr: self.attr,
+            last_wins: self.last_wins,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for KeyedByAttr<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeyedByAttr")
+            .field("attr", &self.attr)
+            .field("last_wins", &self.last_wins)
+            .finish()
+    }
+}
+
+impl<'de, K, V> DeserializeSeed<'de> for KeyedByAttr<K, V>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+{
+    type Value = HashMap<K, V>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, K, V> Visitor<'de> for KeyedByAttr<K, V>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+{
+    type Value = HashMap<K, V>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a sequence of elements with a `{}` attribute", self.attr)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut map = HashMap::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some((key, value)) = seq.next_element_seed(Entry::<K, V> {
+            attr: self.attr,
+            marker: PhantomData,
+        })? {
+            if map.insert(key, value).is_some() && !self.last_wins {
+                return Err(de::Error::custom(format_args!(
+                    "duplicate `{}` attribute value",
+                    self.attr
+                )));
+            }
+        }
+        Ok(map)
+    }
+}
+
+/// Seed deserializing an element into its key attribute and the rest of the element.
+struct Entry<K, V> {
+    attr: &'static str,
+    marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<'de, K, V> DeserializeSeed<'de> for Entry<K, V>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+{
+    type Value = (K, V);
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, K, V> Visitor<'de> for Entry<K, V>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+{
+    type Value = (K, V);
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an element with a `{}` attribute", self.attr)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut without_key = WithoutKey {
+            attr: self.attr,
+            key: None,
+            done: false,
+            map,
+        };
+        let value = V::deserialize(MapAccessDeserializer::new(&mut without_key))?;
+        // The key may follow the fields read by the value
+        while without_key.next_key::<de::IgnoredAny>()?.is_some() {
+            without_key.next_value::<de::IgnoredAny>()?;
+        }
+        match without_key.key {
+            Some(key) => Ok((key, value)),
+            None => Err(de::Error::missing_field(self.attr)),
+        }
+    }
+}
+
+/// Map of the entries of an element, with the key attribute set aside.
+struct WithoutKey<K, A> {
+    attr: &'static str,
+    key: Option<K>,
+    done: bool,
+    map: A,
+}
+
+impl<'de, K, A> MapAccess<'de> for WithoutKey<K, A>
+where
+    K: Deserialize<'de>,
+    A: MapAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn next_key_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, A::Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        if self.done {
+            return Ok(None);
+        }
+        while let Some(name) = self.map.next_key::<String>()? {
+            if name != self.attr {
+                return seed.deserialize(StringDeserializer::new(name)).map(Some);
+            }
+            if self.key.is_some() {
+                return Err(de::Error::duplicate_field(self.attr));
+            }
+            self.key = Some(self.map.next_value()?);
+        }
+        self.done = true;
+        Ok(None)
+    }
+
+    fn next_value_seed<S>(&mut self, seed: S) -> Result<S::Value, A::Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        self.map.next_value_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.map.size_hint()
+    }
+}
@@ -29,9 +29,112 @@
 //! }
 //! ```
 //!
+//! ## Fields
+//!
+//! Fields are matched against both the attributes and the child elements of an element, by
+//! name. The text content of an element is matched by a field renamed to `$value`, e.g.
+//! `#[serde(rename = "$value")]`. To tell an attribute apart from a child element of the same
+//! name, enable [`XmlConfig::prefix_attributes`](struct.XmlConfig.html#method.prefix_attributes)
+//! and rename the fields of attributes to `@name`:
+//!
+//! ```rust
+//! use actix_xml::{parse_xml, XmlConfig};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Item {
+//!     #[serde(rename = "@name")]
+//!     label: String,
+//!     name: String,
+//! }
+//!
+//! let config = XmlConfig::default().prefix_attributes(true);
+//! let item: Item = parse_xml(b"<Item name=\"a\"><name>b</name></Item>", &config).unwrap();
+//! assert_eq!((item.label.as_str(), item.name.as_str()), ("a", "b"));
+//! ```
+//!
+//! ## Mixed content
+//!
+//! Elements mixing text and child elements, like `<p>Hello <b>world</b>!</p>`, are read with
+//! [`XmlConfig::mixed_content`](struct.XmlConfig.html#method.mixed_content) enabled into a
+//! `Vec` field renamed to `$value`, of an enum with a variant renamed to `$text` for the text
+//! runs and a variant for each kind of child element:
+//!
+//! ```rust
+//! use actix_xml::{parse_xml, XmlConfig};
+//! use serde::Deserialize;
+//!
+//! #[derive(Debug, PartialEq, Deserialize)]
+//! enum Inline {
+//!     #[serde(rename = "$text")]
+//!     Text(String),
+//!     #[serde(rename = "b")]
+//!     Bold(String),
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Paragraph {
+//!     #[serde(rename = "$value")]
+//!     content: Vec<Inline>,
+//! }
+//!
+//! let config = XmlConfig::default().mixed_content(true);
+//! let p: Paragraph = parse_xml(b"<p>Hello <b>world</b>!</p>", &config).unwrap();
+//! assert_eq!(
+//!     p.content,
+//!     vec![
+//!         Inline::Text("Hello ".to_owned()),
+//!         Inline::Bold("world".to_owned()),
+//!         Inline::Text("!".to_owned()),
+//!     ]
+//! );
+//! ```
+//!
+//! ## Root element dispatch
+//!
+//! Endpoints receiving one of several kinds of messages, told apart by their root element, can
+//! extract an enum: the local name of the root element, without namespace prefix, selects the
+//! variant, and the element is deserialized as its content. Rename variants to match root
+//! elements with other names. Documents with any other root element fail to deserialize.
+//!
+//! ```rust
+//! use actix_xml::{parse_xml, XmlConfig};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Order {
+//!     id: u32,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! enum Message {
+//!     Ping,
+//!     Order(Order),
+//!     #[serde(rename = "cancel")]
+//!     Cancel { id: u32 },
+//! }
+//!
+//! let config = XmlConfig::default();
+//! let message: Message = parse_xml(b"<Order id=\"7\"/>", &config).unwrap();
+//! assert!(matches!(message, Message::Order(Order { id: 7 })));
+//! let message: Message = parse_xml(b"<ev:cancel><id>7</id></ev:cancel>", &config).unwrap();
+//! assert!(matches!(message, Message::Cancel { id: 7 }));
+//! ```
+//!
 //! ## Features
 //!
 //! - `encoding`: support non utf-8 payload
+//! - `tracing`: open a `xml_extract` span (target `actix_xml`) around each extraction, recording
+//!   the content length, the limit, the outcome and the elapsed time
+//! - `self-decompress`: decompress `gzip` and `deflate` payloads when all of the `compress-*`
+//!   features are disabled
+//! - `xml-error-body`: respond to extraction errors with an XML document like
+//!   `<error><code>deserialize</code><message>...</message></error>`
+//! - `test-util`: helpers for testing handlers extracting XML payloads, in the `test_util`
+//!   module
+//! - `value`: deserialize payloads without a fixed schema into an `XmlValue` tree
+//! - `c14n`: canonicalize documents with Exclusive XML Canonicalization, e.g. to check XML
+//!   signatures, with `canonicalize` and `XmlBody::into_canonical`
 //! - `compress-brotli`(default): enable actix-web `compress-brotli` support
 //! - `compress-gzip`(default): enable actix-web `compress-gzip` support
 //! - `compress-zstd`(default): enable actix-web `compress-zstd` support
@@ -39,25 +142,94 @@
 //! If you've removed one of the `compress-*` feature flag for actix-web, make sure to remove it by setting `default-features=false`, or
 //! it will be re-enabled for actix-web.
 
+use std::convert::Infallible;
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use std::{fmt, ops};
+use std::time::{Duration, Instant};
+use std::{fmt, io, ops, str};
 
+use actix_web::body::{BoxBody, EitherBody};
 use actix_web::dev;
+use actix_web::error::PayloadError;
 use actix_web::http::header;
-use actix_web::web::BytesMut;
+use actix_web::web::{Bytes, BytesMut};
 use actix_web::Error as ActixError;
-use actix_web::{FromRequest, HttpRequest};
-use futures::future::{err, Either, LocalBoxFuture, Ready};
-use futures::{FutureExt, StreamExt};
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse, Responder};
+use futures::channel::mpsc;
+use futures::future::{ok, Either, LocalBoxFuture, Ready};
+use futures::stream::LocalBoxStream;
+use futures::{FutureExt, SinkExt, Stream, StreamExt};
+use quick_xml::events::{BytesDecl, Event};
+use quick_xml::DeError as XMLError;
 use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::extract::{extract_body, ExtractFuture};
 
+pub use crate::buf::XmlBuf;
+#[cfg(feature = "c14n")]
+pub use crate::c14n::canonicalize;
+pub use crate::cdata::Cdata;
 pub use crate::config::XmlConfig;
-pub use crate::error::XMLPayloadError;
+pub use crate::entity::EntityResolver;
+pub use crate::error::{XMLPayloadError, XmlErrorKind};
+pub use crate::extra::XmlExtra;
+pub use crate::keyed::{keyed_by_attr, KeyedByAttr};
+pub use crate::meta::XmlMeta;
+pub use crate::middleware::{XmlConfigMiddleware, XmlConfigService};
+pub use crate::multipart::XmlPart;
+pub use crate::parse::parse_xml;
+pub use crate::parts::{XmlInfo, XmlParts};
+pub use crate::peek::{XmlRoot, XmlRootMiddleware, XmlRootService};
+pub use crate::pool::{BufferPool, BytesPool};
+pub use crate::root::XmlWithRoot;
+pub use crate::scalar::{de_bool_flexible, de_number_locale};
+pub use crate::schema::Schema;
+pub use crate::seq::XmlSeq;
+pub use crate::stats::{Outcome, XmlStats};
+pub use crate::stream::XmlStream;
+pub use crate::validate::{ValidatedXml, XmlValidator};
+#[cfg(feature = "value")]
+pub use crate::value::{XmlElement, XmlValue};
+pub use crate::warning::XmlWarning;
 
+mod buf;
+#[cfg(feature = "c14n")]
+mod c14n;
+mod cdata;
 mod config;
+#[cfg(all(feature = "self-decompress", not(feature = "__compress")))]
+mod decompress;
+mod entity;
 mod error;
+mod extra;
+mod extract;
+mod keyed;
+mod meta;
+mod middleware;
+mod multipart;
+mod namespace;
+mod parse;
+mod parts;
+mod path;
+mod peek;
+mod pool;
+mod root;
+mod scalar;
+mod schema;
+mod seq;
+mod ser;
+mod stats;
+mod stream;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+#[cfg(feature = "tracing")]
+mod trace;
+mod validate;
+#[cfg(feature = "value")]
+mod value;
+mod warning;
 
 #[cfg(test)]
 mod tests;
@@ -93,13 +265,105 @@ mod tests;
 ///     );
 /// }
 /// ```
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Xml<T>(pub T);
 
 impl<T> Xml<T> {
+    /// Wrap a value, e.g. to respond with it
+    pub fn new(value: T) -> Self {
+        Xml(value)
+    }
+
     /// Deconstruct to an inner value
     pub fn into_inner(self) -> T {
         self.0
     }
+
+    /// Get a reference to the inner value
+    pub fn get_ref(&self) -> &T {
+        &self.0
+    }
+
+    /// Get a mutable reference to the inner value
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> Xml<T>
+where
+    T: DeserializeOwned,
+{
+    /// Deserialize from a byte slice, without any of the checks performed on request payloads.
+    ///
+    /// Useful for constructing inputs when testing handlers.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use actix_xml::Xml;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Info {
+    ///     username: String,
+    /// }
+    ///
+    /// let info = Xml::<Info>::from_slice(b"<Info username=\"user\" />").unwrap();
+    /// assert_eq!(info.username, "user");
+    /// ```
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, XMLPayloadError> {
+        Ok(Xml(quick_xml::de::from_reader(bytes)?))
+    }
+}
+
+impl<T> Xml<T>
+where
+    T: Serialize,
+{
+    /// Serialize the inner value to markup, e.g. to log a request or response body.
+    ///
+    /// The inner value is serialized as is, without the declaration or indentation of the
+    /// config, which only apply to responses. `Display` is left to the inner value's own
+    /// implementation.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use actix_xml::Xml;
+    /// use serde::Serialize;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Info {
+    ///     username: String,
+    /// }
+    ///
+    /// let info = Xml(Info {
+    ///     username: "user".to_string(),
+    /// });
+    /// assert_eq!(info.to_xml_string().unwrap(), r#"<Info username="user"/>"#);
+    /// ```
+    pub fn to_xml_string(&self) -> Result<String, XMLPayloadError> {
+        quick_xml::se::to_string(&self.0).map_err(XMLPayloadError::Serialize)
+    }
+}
+
+/// Deserialize from a string, without any of the checks performed on request payloads.
+impl<T> str::FromStr for Xml<T>
+where
+    T: DeserializeOwned,
+{
+    type Err = XMLPayloadError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Xml(quick_xml::de::from_str(s)?))
+    }
+}
+
+impl<T> From<T> for Xml<T> {
+    fn from(value: T) -> Self {
+        Xml(value)
+    }
 }
 
 impl<T> ops::Deref for Xml<T> {
@@ -135,6 +399,78 @@ where
 }
 
 impl<T> FromRequest for Xml<T>
+where
+    T: DeserializeOwned + 'static,
+{
+    type Error = ActixError;
+    type Future = ExtractFuture<Self>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        extract_body(req, payload, parse::parse_with_len::<T>, Xml)
+    }
+}
+
+/// Optional Xml extractor
+///
+/// Note that actix-web's blanket `FromRequest` impl for `Option<T>` turns *every* extraction
+/// error into `None`, so `Option<Xml<T>>` silently swallows malformed payloads as well.
+/// `OptionalXml` only treats an absent body as `None`:
+///
+/// * content type mismatch (see [`XmlConfig`](struct.XmlConfig.html)) resolves to `None`
+/// * empty (or whitespace-only) payload resolves to `None`
+/// * oversized payloads, payload errors and deserialize errors are still returned as errors
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::OptionalXml;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Info {
+///     username: String,
+/// }
+///
+/// async fn index(info: OptionalXml<Info>) -> String {
+///     match info.into_inner() {
+///         Some(info) => format!("Welcome {}!", info.username),
+///         None => "Welcome stranger!".to_string(),
+///     }
+/// }
+/// ```
+pub struct OptionalXml<T>(pub Option<T>);
+
+impl<T> OptionalXml<T> {
+    /// Deconstruct to an inner value
+    pub fn into_inner(self) -> Option<T> {
+        self.0
+    }
+}
+
+impl<T> ops::Deref for OptionalXml<T> {
+    type Target = Option<T>;
+
+    fn deref(&self) -> &Option<T> {
+        &self.0
+    }
+}
+
+impl<T> ops::DerefMut for OptionalXml<T> {
+    fn deref_mut(&mut self) -> &mut Option<T> {
+        &mut self.0
+    }
+}
+
+impl<T> fmt::Debug for OptionalXml<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OptionalXML: {:?}", self.0)
+    }
+}
+
+impl<T> FromRequest for OptionalXml<T>
 where
     T: DeserializeOwned + 'static,
 {
@@ -144,52 +480,360 @@ where
         Either<LocalBoxFuture<'static, Result<Self, ActixError>>, Ready<Result<Self, ActixError>>>;
 
     fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
-        let path = req.path().to_string();
         let config = XmlConfig::from_req(req);
 
-        if let Err(e) = config.check_content_type(req) {
-            return Either::Right(err(e.into()));
+        if config.check_content_type(req).is_err() {
+            return Either::Right(ok(OptionalXml(None)));
         }
 
+        // An empty body deserializes into `None`
         Either::Left(
-            XmlBody::new(req, payload)
-                .limit(config.limit)
-                .map(move |res| match res {
-                    Err(e) => {
-                        log::debug!(
-                            "Failed to deserialize XML from payload. \
-                         Request path: {}",
-                            path
-                        );
-
-                        Err(e.into())
-                    }
-                    Ok(data) => Ok(Xml(data)),
-                })
+            Xml::<Option<T>>::from_request(req, payload)
+                .map(|res| res.map(|Xml(data)| OptionalXml(data)))
                 .boxed_local(),
         )
     }
 }
 
+/// Serializes the inner value as the response body.
+///
+/// The `Content-Type` of the response is `text/xml` or `application/xml`, whichever the `Accept`
+/// header of the request prefers, either by name or by the `text/*` or `application/*` range.
+/// If neither is accepted, `application/xml` is used unless overridden by
+/// [`XmlConfig::response_content_type`](struct.XmlConfig.html#method.response_content_type).
+///
+/// With [`XmlConfig::stream_response`](struct.XmlConfig.html#method.stream_response), the body
+/// is sent in chunks, still serialized when the response is built. Use
+/// [`Xml::streaming`](struct.Xml.html#method.streaming) to serialize it while it's sent.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::Xml;
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Info {
+///     username: String,
+/// }
+///
+/// async fn index() -> Xml<Info> {
+///     Xml(Info {
+///         username: "user".to_string(),
+///     })
+/// }
+/// ```
+impl<T> Responder for Xml<T>
+where
+    T: Serialize,
+{
+    type Body = EitherBody<String>;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let config = XmlConfig::from_req(req);
+
+        if config.stream_response {
+            let mut chunks = Vec::new();
+            let res = serialize_into(
+                &self.0,
+                &config,
+                ChunkWriter::new(|chunk| {
+                    chunks.push(Ok::<_, Infallible>(chunk));
+                    Ok(())
+                }),
+            )
+            .and_then(|mut writer| {
+                Ok(io::Write::flush(&mut writer).map_err(quick_xml::Error::from)?)
+            });
+            return match res {
+                Ok(()) => HttpResponse::Ok()
+                    .content_type(config.negotiate_response_content_type(req))
+                    .streaming(futures::stream::iter(chunks))
+                    .map_into_right_body(),
+                Err(e) => serialize_error(e),
+            };
+        }
+
+        match serialize(&self.0, &config) {
+            Ok(body) => match HttpResponse::Ok()
+                .content_type(config.negotiate_response_content_type(req))
+                .message_body(body)
+            {
+                Ok(res) => res.map_into_left_body(),
+                Err(e) => HttpResponse::from_error(e).map_into_right_body(),
+            },
+            Err(e) => serialize_error(e),
+        }
+    }
+}
+
+impl<T> Xml<T>
+where
+    T: Serialize + Send + 'static,
+{
+    /// Respond with the inner value serialized while the body is sent, for large documents like
+    /// exports.
+    ///
+    /// The value is serialized on the blocking thread pool into chunks of 8Kb, waiting for the
+    /// server to send them once a few are pending, so only these are held in memory, along with
+    /// the children of nested structs which quick-xml serializes at once. As the status is sent
+    /// before serializing, serialization errors abort the body instead of being reported to the
+    /// client.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use actix_xml::{Xml, XmlStreamResponse};
+    /// use serde::Serialize;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Export {
+    ///     item: Vec<String>,
+    /// }
+    ///
+    /// async fn export() -> XmlStreamResponse<Export> {
+    ///     Xml(Export {
+    ///         item: (0..100_000).map(|i| i.to_string()).collect(),
+    ///     })
+    ///     .streaming()
+    /// }
+    /// ```
+    pub fn streaming(self) -> XmlStreamResponse<T> {
+        XmlStreamResponse(self.0)
+    }
+}
+
+/// Responder serializing the inner value while the body is sent, made by
+/// [`Xml::streaming`](struct.Xml.html#method.streaming)
+///
+/// The content type and serialization options of the response are the same as for `Xml`.
+#[derive(Debug)]
+pub struct XmlStreamResponse<T>(pub T);
+
+impl<T> Responder for XmlStreamResponse<T>
+where
+    T: Serialize + Send + 'static,
+{
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let config = XmlConfig::from_req(req);
+        let (sender, body) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+        let mut errors = sender.clone();
+        let value = self.0;
+        let stream_config = XmlConfig::clone(&config);
+        // The serializer blocks while the channel is full, so it's only ahead of the server by a
+        // few chunks
+        actix_web::rt::task::spawn_blocking(move || {
+            let mut sender = sender;
+            let writer = ChunkWriter::new(move |chunk| {
+                futures::executor::block_on(sender.send(Ok(chunk))).map_err(|_| {
+                    io::Error::new(io::ErrorKind::BrokenPipe, "response body was dropped")
+                })
+            });
+            let res = serialize_into(&value, &stream_config, writer).and_then(|mut writer| {
+                Ok(io::Write::flush(&mut writer).map_err(quick_xml::Error::from)?)
+            });
+            // Dropping the senders closes the channel, ending the body
+            if let Err(e) = res {
+                log::error!("Failed to serialize XML response: {}", e);
+                let _ = futures::executor::block_on(
+                    errors.send(Err::<Bytes, _>(XMLPayloadError::Serialize(e))),
+                );
+            }
+        });
+        HttpResponse::Ok()
+            .content_type(config.negotiate_response_content_type(req))
+            .streaming(body)
+    }
+}
+
+fn serialize_error(e: XMLError) -> HttpResponse<EitherBody<String>> {
+    log::error!("Failed to serialize XML response: {}", e);
+
+    HttpResponse::from_error(XMLPayloadError::Serialize(e)).map_into_right_body()
+}
+
+fn serialize<T>(value: &T, config: &XmlConfig) -> Result<String, XMLError>
+where
+    T: Serialize,
+{
+    Ok(String::from_utf8(serialize_into(
+        value,
+        config,
+        Vec::new(),
+    )?)?)
+}
+
+/// Serialize `value` into `out` as configured, returning `out`.
+fn serialize_into<T, W>(value: &T, config: &XmlConfig, out: W) -> Result<W, XMLError>
+where
+    T: Serialize,
+    W: io::Write,
+{
+    match config.indent {
+        Some((indent_char, indent_size)) => {
+            serialize_compact(value, config, Indent::new(out, indent_char, indent_size))?
+                .into_inner()
+        }
+        None => serialize_compact(value, config, out),
+    }
+}
+
+fn serialize_compact<T, W>(value: &T, config: &XmlConfig, out: W) -> Result<W, XMLError>
+where
+    T: Serialize,
+    W: io::Write,
+{
+    let mut writer = quick_xml::Writer::new(out);
+    if let Some((version, encoding)) = &config.declaration {
+        writer.write_event(Event::Decl(BytesDecl::new(version, Some(encoding), None)))?;
+    }
+    ser::to_writer(value, writer.into_inner())
+}
+
+/// Writer re-emitting the serialized xml written to it with indentation into `out`.
+///
+/// The serializer of quick-xml doesn't indent nested elements reliably, so the compact output is
+/// replayed through an indenting writer instead, as it's written. Serialized xml only contains
+/// `>` at the end of tags, so everything up to the last one is complete markup.
+struct Indent<W: io::Write> {
+    pending: Vec<u8>,
+    writer: quick_xml::Writer<W>,
+}
+
+impl<W: io::Write> Indent<W> {
+    fn new(out: W, indent_char: u8, indent_size: usize) -> Self {
+        Indent {
+            pending: Vec::new(),
+            writer: quick_xml::Writer::new_with_indent(out, indent_char, indent_size),
+        }
+    }
+
+    fn replay(&mut self, markup: &[u8]) -> Result<(), quick_xml::Error> {
+        let mut reader = quick_xml::Reader::from_reader(markup);
+        // End tags may close elements started by earlier markup
+        reader.check_end_names(false);
+        loop {
+            match reader.read_event()? {
+                Event::Eof => return Ok(()),
+                event => self.writer.write_event(event)?,
+            }
+        }
+    }
+
+    fn into_inner(mut self) -> Result<W, XMLError> {
+        let pending = std::mem::take(&mut self.pending);
+        self.replay(&pending)?;
+        Ok(self.writer.into_inner())
+    }
+}
+
+impl<W: io::Write> io::Write for Indent<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        if let Some(end) = self.pending.iter().rposition(|&b| b == b'>') {
+            let markup: Vec<u8> = self.pending.drain(..=end).collect();
+            self.replay(&markup)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.inner().flush()
+    }
+}
+
+/// Size of the chunks of streamed responses
+const RESPONSE_CHUNK_SIZE: usize = 8192;
+
+/// Number of chunks of a streamed response buffered ahead of the server
+const RESPONSE_CHANNEL_CAPACITY: usize = 4;
+
+/// Writer handing what's written to `send` in chunks, which make up a streamed response body.
+struct ChunkWriter<F> {
+    chunk: BytesMut,
+    send: F,
+}
+
+impl<F> ChunkWriter<F>
+where
+    F: FnMut(Bytes) -> io::Result<()>,
+{
+    fn new(send: F) -> Self {
+        ChunkWriter {
+            chunk: BytesMut::with_capacity(RESPONSE_CHUNK_SIZE),
+            send,
+        }
+    }
+}
+
+impl<F> io::Write for ChunkWriter<F>
+where
+    F: FnMut(Bytes) -> io::Result<()>,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+        if self.chunk.len() >= RESPONSE_CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        (self.send)(self.chunk.split().freeze())
+    }
+}
+
 /// Request's payload xml parser, it resolves to a deserialized `T` value.
 /// This future could be used with `ServiceRequest` and `ServiceFromRequest`.
 ///
 /// Returns error:
 ///
-/// * content type is not `text/xml` or `application/xml`
-///   (unless specified in [`XmlConfig`](struct.XmlConfig.html))
-/// * content length is greater than 256k
+/// * content type is not `text/xml`, `application/xml` or a `+xml` suffixed type like
+///   `application/atom+xml` (unless specified in [`XmlConfig`](struct.XmlConfig.html)). If a
+///   request has several `Content-Type` headers, only the first one is checked, and it's
+///   rejected if it isn't a valid mime type
+/// * content length is greater than 256k, or isn't a valid number
+/// * payload is greater than 256k once decompressed
 pub struct XmlBody<U> {
-    limit: usize,
+    limit: Option<usize>,
+    stream_limit: Option<usize>,
+    config: XmlConfig,
+    charset: Option<String>,
     length: Option<usize>,
-    #[cfg(feature = "__compress")]
-    stream: Option<dev::Decompress<dev::Payload>>,
-    #[cfg(not(feature = "__compress"))]
-    stream: Option<dev::Payload>,
+    stream: Option<LocalBoxStream<'static, Result<Bytes, PayloadError>>>,
+    /// Whether the chunks of `stream` are decompressed
+    decompressed: bool,
+    #[cfg(all(feature = "self-decompress", not(feature = "__compress")))]
+    encoding: Option<decompress::ContentEncoding>,
     err: Option<XMLPayloadError>,
+    parse: Parser<U>,
     fut: Option<LocalBoxFuture<'static, Result<U, XMLPayloadError>>>,
 }
 
+/// Content length declared by the headers of `req`, if any.
+///
+/// A length that isn't a number, or doesn't fit in `usize`, is rejected rather than taken for a
+/// missing one, since the payload can't be trusted to be any shorter.
+pub(crate) fn content_length(req: &HttpRequest) -> Result<Option<usize>, XMLPayloadError> {
+    req.headers()
+        .get(&header::CONTENT_LENGTH)
+        .map(|l| {
+            l.to_str()
+                .ok()
+                .and_then(|s| s.parse::<usize>().ok())
+                .ok_or(XMLPayloadError::InvalidLength)
+        })
+        .transpose()
+}
+
+pub(crate) type Parser<U> = fn(&[u8], Option<&str>, &XmlConfig) -> Result<U, XMLPayloadError>;
+
 impl<U> XmlBody<U>
 where
     U: DeserializeOwned + 'static,
@@ -197,21 +841,63 @@ where
     /// Create `XmlBody` for request.
     #[allow(clippy::borrow_interior_mutable_const)]
     pub fn new(req: &HttpRequest, payload: &mut dev::Payload) -> Self {
-        let len = req
-            .headers()
-            .get(&header::CONTENT_LENGTH)
-            .and_then(|l| l.to_str().ok())
-            .and_then(|s| s.parse::<usize>().ok());
+        let (len, err) = match content_length(req) {
+            Ok(len) => (len, None),
+            Err(e) => (None, Some(e)),
+        };
+
+        let charset = req
+            .mime_type()
+            .ok()
+            .flatten()
+            .and_then(|mime| mime.get_param(mime::CHARSET).map(|c| c.to_string()));
 
         #[cfg(feature = "__compress")]
-        let payload = dev::Decompress::from_headers(payload.take(), req.headers());
+        let (payload, decompressed) = (
+            dev::Decompress::from_headers(payload.take(), req.headers()),
+            parts::decompressed(req),
+        );
         #[cfg(not(feature = "__compress"))]
-        let payload = payload.take();
+        let (payload, decompressed) = (payload.take(), false);
 
         XmlBody {
-            limit: 262_144,
+            limit: Some(262_144),
+            stream_limit: None,
+            config: XmlConfig::from_req(req).clone(),
+            charset,
             length: len,
-            stream: Some(payload),
+            stream: Some(payload.boxed_local()),
+            decompressed,
+            #[cfg(all(feature = "self-decompress", not(feature = "__compress")))]
+            encoding: decompress::ContentEncoding::from_req(req),
+            parse: parse::parse::<U>,
+            fut: None,
+            err,
+        }
+    }
+
+    /// Create `XmlBody` reading from `stream` instead of a request payload, e.g. a payload
+    /// already decompressed by a middleware.
+    ///
+    /// `content_length` is checked against the limit before reading the stream. No content type
+    /// or encoding headers are involved, so the stream is neither decompressed nor decoded from
+    /// a charset other than the one detected from the document, and the default
+    /// [`XmlConfig`](struct.XmlConfig.html) applies.
+    pub fn from_stream<S>(stream: S, content_length: Option<usize>) -> Self
+    where
+        S: Stream<Item = Result<Bytes, PayloadError>> + 'static,
+    {
+        XmlBody {
+            limit: Some(262_144),
+            stream_limit: None,
+            config: XmlConfig::default(),
+            charset: None,
+            length: content_length,
+            stream: Some(stream.boxed_local()),
+            decompressed: false,
+            #[cfg(all(feature = "self-decompress", not(feature = "__compress")))]
+            encoding: None,
+            parse: parse::parse::<U>,
             fut: None,
             err: None,
         }
@@ -219,14 +905,104 @@ where
 
     /// Change max size of payload. By default max size is 256Kb
     pub fn limit(mut self, limit: usize) -> Self {
-        self.limit = limit;
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Disable the payload size limit, unless a stream limit is set. Only use this for trusted
+    /// input
+    pub fn no_limit(mut self) -> Self {
+        self.limit = None;
+        self
+    }
+
+    /// Change max size of payload enforced while reading it, independently of the limit checked
+    /// against the content-length header. By default it equals the limit
+    pub fn stream_limit(mut self, limit: usize) -> Self {
+        self.stream_limit = Some(limit);
+        self
+    }
+
+    /// Apply the limits of `config`.
+    pub(crate) fn config_limits(mut self, config: &XmlConfig) -> Self {
+        self.limit = config.limit;
+        self.stream_limit = config.get_stream_limit();
         self
     }
+
+    /// Also resolve to the qualified name of the root element, e.g. `soap:Envelope`
+    pub fn with_root(self) -> XmlBody<(String, U)> {
+        self.with_parser(parse::parse_with_root::<U>)
+    }
+
+    /// Resolve to the buffered payload instead of deserializing it, e.g. to run a quick-xml
+    /// `Deserializer` borrowing from it.
+    ///
+    /// The payload has been checked against the limits and decompressed following the
+    /// `Content-Encoding` header, but it's neither decoded from its charset nor checked by the
+    /// options of [`XmlConfig`](struct.XmlConfig.html) applying to the document, such as
+    /// [`max_depth`](struct.XmlConfig.html#method.max_depth).
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use actix_web::{dev, error, HttpRequest};
+    /// use actix_xml::XmlBody;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Info<'a> {
+    ///     username: &'a str,
+    /// }
+    ///
+    /// async fn username(req: HttpRequest, mut payload: dev::Payload) -> actix_web::Result<String> {
+    ///     let bytes = XmlBody::<()>::new(&req, &mut payload).into_bytes().await?;
+    ///     let text = std::str::from_utf8(&bytes).map_err(error::ErrorBadRequest)?;
+    ///     let mut deserializer = quick_xml::de::Deserializer::from_str(text);
+    ///     let info = Info::deserialize(&mut deserializer).map_err(error::ErrorBadRequest)?;
+    ///     Ok(info.username.to_owned())
+    /// }
+    /// ```
+    pub fn into_bytes(self) -> XmlBody<Bytes> {
+        self.with_parser(|body, _, _| Ok(Bytes::copy_from_slice(body)))
+    }
+
+    /// Resolve to the payload canonicalized with Exclusive XML Canonicalization instead of
+    /// deserializing it, e.g. to check its XML signature. See
+    /// [`canonicalize`](fn.canonicalize.html) for the canonical form.
+    ///
+    /// The payload has been checked against the limits, decompressed and decoded from its
+    /// charset, but it's not checked by the options of [`XmlConfig`](struct.XmlConfig.html)
+    /// applying to the document.
+    #[cfg(feature = "c14n")]
+    pub fn into_canonical(self) -> XmlBody<Vec<u8>> {
+        self.with_parser(c14n::canonicalize_with)
+    }
+}
+
+impl<U> XmlBody<U> {
+    /// Resolve to the output of `parse` instead.
+    pub(crate) fn with_parser<V>(self, parse: Parser<V>) -> XmlBody<V> {
+        XmlBody {
+            limit: self.limit,
+            stream_limit: self.stream_limit,
+            config: self.config,
+            charset: self.charset,
+            length: self.length,
+            stream: self.stream,
+            decompressed: self.decompressed,
+            #[cfg(all(feature = "self-decompress", not(feature = "__compress")))]
+            encoding: self.encoding,
+            err: self.err,
+            parse,
+            fut: None,
+        }
+    }
 }
 
 impl<U> Future for XmlBody<U>
 where
-    U: DeserializeOwned + 'static,
+    U: 'static,
 {
     type Output = Result<U, XMLPayloadError>;
 
@@ -239,27 +1015,95 @@ where
             return Poll::Ready(Err(err));
         }
 
+        let length = self.length.take();
         let limit = self.limit;
-        if let Some(len) = self.length.take() {
-            if len > limit {
-                return Poll::Ready(Err(XMLPayloadError::Overflow));
-            }
-        }
-        let mut stream = self.stream.take().unwrap();
+        let stream_limit = self.stream_limit.or(limit);
+        let stream = self.stream.take().unwrap();
+        let decompressed = self.decompressed;
+        let config = self.config.clone();
+        let charset = self.charset.take();
+        let parse = self.parse;
+        #[cfg(all(feature = "self-decompress", not(feature = "__compress")))]
+        let encoding = self.encoding.take();
 
         self.fut = Some(
             async move {
-                let mut body = BytesMut::with_capacity(8192);
-
-                while let Some(item) = stream.next().await {
-                    let chunk = item?;
-                    if (body.len() + chunk.len()) > limit {
-                        return Err(XMLPayloadError::Overflow);
-                    } else {
-                        body.extend_from_slice(&chunk);
+                let mut stats = XmlStats {
+                    bytes: length.unwrap_or(0),
+                    parse_duration: Duration::ZERO,
+                    outcome: Outcome::Ok,
+                };
+                let res = async {
+                    if config.require_content_length && length.is_none() {
+                        return Err(XMLPayloadError::LengthRequired);
+                    }
+                    if let (Some(limit), Some(len)) = (limit, length) {
+                        if len > limit {
+                            return Err(XMLPayloadError::Overflow {
+                                limit,
+                                length: Some(len),
+                                decompressed: false,
+                            });
+                        }
                     }
+
+                    let read = async {
+                        // Owned by the read, so the stream is dropped as soon as it ends, e.g.
+                        // on overflow, without being drained or kept until parsing is done
+                        let mut stream = stream;
+                        // Given back to the pool on every exit, e.g. on overflow or timeout
+                        let mut body = pool::PooledBuffer::take(config.buffer_pool.clone());
+
+                        // Chunks are decompressed already, so checking before extending the
+                        // buffer keeps a decompression bomb from being accumulated
+                        while let Some(item) = stream.next().await {
+                            let chunk = item?;
+                            let length = body.len() + chunk.len();
+                            stats.bytes = length;
+                            match stream_limit {
+                                Some(limit) if length > limit => {
+                                    return Err(XMLPayloadError::Overflow {
+                                        limit,
+                                        length: Some(length),
+                                        decompressed,
+                                    });
+                                }
+                                _ => body.extend_from_slice(&chunk),
+                            }
+                        }
+                        Ok(body)
+                    };
+                    let body = match config.read_timeout {
+                        Some(timeout) => actix_web::rt::time::timeout(timeout, read)
+                            .await
+                            .map_err(|_| XMLPayloadError::Timeout { timeout })??,
+                        None => read.await?,
+                    };
+                    stats.bytes = body.len();
+
+                    let start = Instant::now();
+                    let res = {
+                        #[cfg(all(feature = "self-decompress", not(feature = "__compress")))]
+                        let payload: &[u8] = &match encoding {
+                            Some(encoding) => {
+                                std::borrow::Cow::Owned(encoding.decompress(&body, stream_limit)?)
+                            }
+                            None => std::borrow::Cow::Borrowed(&body[..]),
+                        };
+                        #[cfg(not(all(feature = "self-decompress", not(feature = "__compress"))))]
+                        let payload = &body[..];
+                        parse(payload, charset.as_deref(), &config)
+                    };
+                    stats.parse_duration = start.elapsed();
+                    res
+                }
+                .await;
+
+                if let Some(on_complete) = &config.on_complete {
+                    stats.outcome = Outcome::from_error(res.as_ref().err());
+                    on_complete(&stats);
                 }
-                Ok(quick_xml::de::from_reader(&*body)?)
+                res
             }
             .boxed_local(),
         );
@@ -0,0 +1,35 @@
+/// Metadata of the payload extracted by [`Xml`](struct.Xml.html)
+///
+/// `Xml` and the other extractors of the crate store it in the extensions of the request once
+/// the payload is extracted, [`XmlPart`](struct.XmlPart.html) recording the size of the XML
+/// part. Extractors of a handler are all started before any of them completes, so get it from
+/// the request in the body of the handler rather than extracting it.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_web::{HttpMessage, HttpRequest};
+/// use actix_xml::{Xml, XmlMeta};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Info {
+///     username: String,
+/// }
+///
+/// async fn index(info: Xml<Info>, req: HttpRequest) -> String {
+///     let bytes = req.extensions().get::<XmlMeta>().map_or(0, XmlMeta::byte_len);
+///     format!("Received {} bytes from {}", bytes, info.username)
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XmlMeta {
+    pub(crate) bytes: usize,
+}
+
+impl XmlMeta {
+    /// Size of the payload in bytes, once decompressed
+    pub fn byte_len(&self) -> usize {
+        self.bytes
+    }
+}
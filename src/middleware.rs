@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error as ActixError, HttpMessage};
+use futures::future::{ok, Ready};
+
+use crate::config::{ScopedConfig, XmlConfig};
+
+/// Middleware setting the [`XmlConfig`](struct.XmlConfig.html) of every request it wraps
+///
+/// Wrap a scope, or a whole app, with it instead of registering the config as app data of each
+/// resource. The extractors and responders of this crate use it over any config in app data,
+/// so nest another middleware to change the config of part of the scope.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_web::{web, App};
+/// use actix_xml::{Xml, XmlConfig, XmlConfigMiddleware};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Info {
+///     username: String,
+/// }
+///
+/// async fn index(info: Xml<Info>) -> String {
+///     format!("Welcome {}!", info.username)
+/// }
+///
+/// fn main() {
+///     let app = App::new().service(
+///         web::scope("/api")
+///             .wrap(XmlConfigMiddleware::new(XmlConfig::default().limit(4096)))
+///             .route("/index.html", web::post().to(index)),
+///     );
+/// }
+/// ```
+#[derive(Clone)]
+pub struct XmlConfigMiddleware {
+    config: Arc<XmlConfig>,
+}
+
+impl XmlConfigMiddleware {
+    /// Create a middleware setting `config` for the requests it wraps
+    pub fn new(config: XmlConfig) -> Self {
+        XmlConfigMiddleware {
+            config: Arc::new(config),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for XmlConfigMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError>,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Transform = XmlConfigService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(XmlConfigService {
+            service,
+            config: self.config.clone(),
+        })
+    }
+}
+
+/// Service created by [`XmlConfigMiddleware`](struct.XmlConfigMiddleware.html)
+pub struct XmlConfigService<S> {
+    service: S,
+    config: Arc<XmlConfig>,
+}
+
+impl<S, B> Service<ServiceRequest> for XmlConfigService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError>,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = S::Future;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        req.extensions_mut()
+            .insert(ScopedConfig(self.config.clone()));
+        self.service.call(req)
+    }
+}
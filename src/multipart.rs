@@ -0,0 +1,265 @@
+use std::time::{Duration, Instant};
+use std::{fmt, ops, str};
+
+use actix_web::dev;
+use actix_web::error::PayloadError;
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::Error as ActixError;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures::stream::LocalBoxStream;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+
+use crate::config::XmlConfig;
+use crate::error::XMLPayloadError;
+use crate::extract::{extract, ExtractFuture};
+use crate::parse;
+use crate::stats::{Outcome, XmlStats};
+
+/// Xml extractor for the XML part of a `multipart/related` payload
+///
+/// Some protocols send the document as one part of a multipart payload, next to binary
+/// attachments, e.g. SOAP with attachments or MTOM. `XmlPart` reads the parts in order and
+/// deserializes the first one whose content type is accepted by the
+/// [`XmlConfig`](struct.XmlConfig.html), e.g. `application/xml`, `text/xml` or
+/// `application/xop+xml`, while
+/// [`accept_any_content_type`](struct.XmlConfig.html#method.accept_any_content_type) doesn't
+/// apply to parts. Other parts are skipped without being buffered.
+///
+/// The limits of the config apply to the XML part only, so the content length of the request
+/// isn't checked against them, and neither is its content type, which must be
+/// `multipart/related` with a `boundary` parameter. Extraction works the same way as with
+/// [`Xml`](struct.Xml.html) otherwise, reading the part in the charset of its own content type.
+/// Compressed payloads are only decompressed with the `compress-*` features, not with
+/// `self-decompress`.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::XmlPart;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Envelope {
+///     body: String,
+/// }
+///
+/// async fn index(envelope: XmlPart<Envelope>) -> String {
+///     format!("Received {}", envelope.body)
+/// }
+/// ```
+pub struct XmlPart<T>(pub T);
+
+impl<T> XmlPart<T> {
+    /// Deconstruct to an inner value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> ops::Deref for XmlPart<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> ops::DerefMut for XmlPart<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> fmt::Debug for XmlPart<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "XmlPart: {:?}", self.0)
+    }
+}
+
+/// Boundary of a `multipart/related` payload, from the content type of `req`.
+fn boundary(req: &HttpRequest) -> Result<String, XMLPayloadError> {
+    match req.mime_type() {
+        Ok(Some(mime)) if mime.type_() == mime::MULTIPART && mime.subtype() == "related" => mime
+            .get_param(mime::BOUNDARY)
+            .map(|boundary| boundary.to_string())
+            .ok_or(XMLPayloadError::ContentType {
+                received: Some(mime),
+            }),
+        Ok(received) => Err(XMLPayloadError::ContentType { received }),
+        Err(_) => Err(XMLPayloadError::ContentType { received: None }),
+    }
+}
+
+/// Position of `needle` in `haystack`, if any.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Content type of a part, from the block of its headers.
+fn part_content_type(headers: &[u8]) -> Option<mime::Mime> {
+    str::from_utf8(headers)
+        .ok()?
+        .split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-type"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+}
+
+/// Where the reader of a multipart payload is.
+enum State {
+    /// Before the first delimiter
+    Preamble,
+    /// Right after a delimiter, before the line break or the `--` closing the payload
+    Delimiter,
+    /// Among the headers of a part
+    Headers,
+    /// In the body of a part, keeping it if it's the XML part along with its charset
+    Body { xml: bool, charset: Option<String> },
+}
+
+/// Read `stream` up to the end of its first XML part, returning its body and charset.
+///
+/// Non-XML parts are dropped as they're read, so only the XML part is checked against `limit`.
+async fn read_part(
+    mut stream: LocalBoxStream<'static, Result<Bytes, PayloadError>>,
+    boundary: &str,
+    limit: Option<usize>,
+    decompressed: bool,
+    config: &XmlConfig,
+) -> Result<(BytesMut, Option<String>), XMLPayloadError> {
+    // Delimiters follow a line break, except the first one, which may start the payload
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    let mut buffer = BytesMut::from(&b"\r\n"[..]);
+    let mut part = BytesMut::new();
+    let mut state = State::Preamble;
+
+    loop {
+        match &state {
+            State::Preamble | State::Body { .. } => {
+                let end = find(&buffer, &delimiter);
+                // Keep what could be the start of a delimiter split across chunks
+                let read = end.unwrap_or_else(|| buffer.len().saturating_sub(delimiter.len() - 1));
+                let content = buffer.split_to(read);
+                if let State::Body { xml: true, charset } = &state {
+                    let length = part.len() + content.len();
+                    if let Some(limit) = limit.filter(|limit| length > *limit) {
+                        return Err(XMLPayloadError::Overflow {
+                            limit,
+                            length: Some(length),
+                            decompressed,
+                        });
+                    }
+                    part.extend_from_slice(&content);
+                    if end.is_some() {
+                        return Ok((part, charset.clone()));
+                    }
+                }
+                if end.is_some() {
+                    let _ = buffer.split_to(delimiter.len());
+                    state = State::Delimiter;
+                    continue;
+                }
+            }
+            State::Delimiter => {
+                if buffer.starts_with(b"--") {
+                    return Err(XMLPayloadError::NoXmlPart);
+                }
+                // The line may be padded with whitespace before its break
+                if let Some(end) = find(&buffer, b"\r\n") {
+                    let _ = buffer.split_to(end + 2);
+                    state = State::Headers;
+                    continue;
+                }
+            }
+            State::Headers => {
+                let end = match buffer.starts_with(b"\r\n") {
+                    true => Some(0),
+                    false => find(&buffer, b"\r\n\r\n").map(|end| end + 2),
+                };
+                if let Some(end) = end {
+                    let headers = buffer.split_to(end + 2);
+                    let mime = part_content_type(&headers[..end]);
+                    state = State::Body {
+                        xml: mime.as_ref().is_some_and(|mime| config.accepts(mime)),
+                        charset: mime
+                            .as_ref()
+                            .and_then(|mime| mime.get_param(mime::CHARSET))
+                            .map(|charset| charset.to_string()),
+                    };
+                    continue;
+                }
+                // Headers are buffered whole, so they're bounded by the limit too
+                if let Some(limit) = limit.filter(|limit| buffer.len() > *limit) {
+                    return Err(XMLPayloadError::Overflow {
+                        limit,
+                        length: Some(buffer.len()),
+                        decompressed,
+                    });
+                }
+            }
+        }
+
+        match stream.next().await {
+            Some(chunk) => buffer.extend_from_slice(&chunk?),
+            None => return Err(XMLPayloadError::NoXmlPart),
+        }
+    }
+}
+
+impl<T> FromRequest for XmlPart<T>
+where
+    T: DeserializeOwned + 'static,
+{
+    type Error = ActixError;
+    type Future = ExtractFuture<Self>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        extract(req, |config| {
+            let boundary = boundary(req)?;
+
+            #[cfg(feature = "__compress")]
+            let (stream, decompressed) = (
+                dev::Decompress::from_headers(payload.take(), req.headers()).boxed_local(),
+                crate::parts::decompressed(req),
+            );
+            #[cfg(not(feature = "__compress"))]
+            let (stream, decompressed) = (payload.take().boxed_local(), false);
+
+            let config = config.clone();
+            Ok(async move {
+                let limit = config.get_stream_limit();
+                let read = read_part(stream, &boundary, limit, decompressed, &config);
+                let res = match config.read_timeout {
+                    Some(timeout) => actix_web::rt::time::timeout(timeout, read)
+                        .await
+                        .unwrap_or(Err(XMLPayloadError::Timeout { timeout })),
+                    None => read.await,
+                };
+
+                let mut stats = XmlStats {
+                    bytes: 0,
+                    parse_duration: Duration::ZERO,
+                    outcome: Outcome::Ok,
+                };
+                let res = res.and_then(|(part, charset)| {
+                    stats.bytes = part.len();
+                    let start = Instant::now();
+                    let res = parse::parse::<T>(&part, charset.as_deref(), &config);
+                    stats.parse_duration = start.elapsed();
+                    res
+                });
+                if let Some(on_complete) = &config.on_complete {
+                    stats.outcome = Outcome::from_error(res.as_ref().err());
+                    on_complete(&stats);
+                }
+                res.map(|value| (stats.bytes, XmlPart(value)))
+            })
+        })
+    }
+}
@@ -0,0 +1,116 @@
+use quick_xml::events::{BytesEnd, BytesStart, Event};
+use quick_xml::name::{QName, ResolveResult};
+use quick_xml::{DeError as XMLError, NsReader, Writer};
+
+use crate::error::XMLPayloadError;
+
+/// Check that the root element of `text` is in the namespace `expected`.
+///
+/// Syntax errors are left to the deserializer to report, as well as documents without a root
+/// element.
+pub(crate) fn check_root_namespace(text: &str, expected: &str) -> Result<(), XMLPayloadError> {
+    let mut reader = NsReader::from_str(text);
+    loop {
+        match reader.read_resolved_event() {
+            Ok((namespace, Event::Start(_) | Event::Empty(_))) => {
+                let found = match namespace {
+                    ResolveResult::Bound(namespace) => {
+                        Some(String::from_utf8_lossy(namespace.into_inner()).into_owned())
+                    }
+                    ResolveResult::Unbound | ResolveResult::Unknown(_) => None,
+                };
+                return if found.as_deref() == Some(expected) {
+                    Ok(())
+                } else {
+                    Err(XMLPayloadError::NamespaceMismatch {
+                        expected: expected.to_string(),
+                        found,
+                    })
+                };
+            }
+            Ok((_, Event::Eof)) | Err(_) => return Ok(()),
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Rename elements and attributes of `text` bound to one of the namespace URIs in `namespaces`
+/// after the prefix registered for it, or to their local name if that prefix is empty.
+pub(crate) fn normalize_namespaces(
+    text: &str,
+    namespaces: &[(String, String)],
+) -> Result<String, XMLError> {
+    let mut reader = NsReader::from_str(text);
+    // End names are checked by the deserializer, if enabled
+    reader.check_end_names(false);
+    let mut writer = Writer::new(Vec::with_capacity(text.len()));
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                writer.write_event(Event::Start(rename_element(&reader, &element, namespaces)?))?
+            }
+            Event::Empty(element) => {
+                writer.write_event(Event::Empty(rename_element(&reader, &element, namespaces)?))?
+            }
+            Event::End(element) => {
+                let (namespace, local) = reader.resolve_element(element.name());
+                let name = rename(namespace, local.into_inner(), element.name(), namespaces)?;
+                writer.write_event(Event::End(BytesEnd::new(name)))?
+            }
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+fn rename_element<'a>(
+    reader: &NsReader<&[u8]>,
+    element: &BytesStart,
+    namespaces: &[(String, String)],
+) -> Result<BytesStart<'a>, XMLError> {
+    let (namespace, local) = reader.resolve_element(element.name());
+    let mut renamed = BytesStart::new(rename(
+        namespace,
+        local.into_inner(),
+        element.name(),
+        namespaces,
+    )?);
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(quick_xml::Error::from)?;
+        let key = if attribute.key.as_namespace_binding().is_some() {
+            // namespace declarations are kept as is
+            utf8(attribute.key.into_inner())?.to_string()
+        } else {
+            let (namespace, local) = reader.resolve_attribute(attribute.key);
+            rename(namespace, local.into_inner(), attribute.key, namespaces)?
+        };
+        renamed.push_attribute((key.as_bytes(), attribute.value.as_ref()));
+    }
+    Ok(renamed)
+}
+
+fn rename(
+    namespace: ResolveResult,
+    local: &[u8],
+    name: QName,
+    namespaces: &[(String, String)],
+) -> Result<String, XMLError> {
+    let prefix = match namespace {
+        ResolveResult::Bound(namespace) => namespaces
+            .iter()
+            .find(|(_, uri)| uri.as_bytes() == namespace.into_inner())
+            .map(|(prefix, _)| prefix),
+        _ => None,
+    };
+
+    Ok(match prefix {
+        Some(prefix) if prefix.is_empty() => utf8(local)?.to_string(),
+        Some(prefix) => format!("{}:{}", prefix, utf8(local)?),
+        None => utf8(name.into_inner())?.to_string(),
+    })
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str, XMLError> {
+    Ok(std::str::from_utf8(bytes).map_err(quick_xml::Error::from)?)
+}
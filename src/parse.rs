@@ -0,0 +1,1157 @@
+use std::borrow::Cow;
+
+#[cfg(feature = "encoding")]
+use encoding_rs::{Encoding, UTF_8};
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{DeError as XMLError, Reader, Writer};
+use serde::de::{Deserialize, DeserializeOwned, Deserializer};
+
+use crate::config::XmlConfig;
+use crate::entity::resolve_entities;
+use crate::error::XMLPayloadError;
+use crate::namespace::{check_root_namespace, normalize_namespaces};
+use crate::path::{FieldPath, Segment, Tracked, Tracking};
+use crate::scalar::LenientScalars;
+use crate::warning::XmlWarning;
+
+/// Deserialize an XML document outside of a request, the same way request payloads are.
+///
+/// The size limit and the other checks of `config` are applied, and the encoding is detected
+/// from the byte order mark or the XML declaration if the `encoding` feature is enabled.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::{parse_xml, XmlConfig};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Info {
+///     username: String,
+/// }
+///
+/// let config = XmlConfig::default().limit(4096);
+/// let info: Info = parse_xml(b"<Info username=\"user\" />", &config).unwrap();
+/// assert_eq!(info.username, "user");
+/// ```
+pub fn parse_xml<T>(bytes: &[u8], config: &XmlConfig) -> Result<T, XMLPayloadError>
+where
+    T: DeserializeOwned,
+{
+    if let Some(limit) = config.limit.filter(|&limit| bytes.len() > limit) {
+        return Err(XMLPayloadError::Overflow {
+            limit,
+            length: Some(bytes.len()),
+            decompressed: false,
+        });
+    }
+    parse(bytes, None, config)
+}
+
+/// Deserialize a buffered payload, applying the checks enabled in `config` beforehand.
+///
+/// `charset` is the charset parameter of the request's content type, if any.
+pub(crate) fn parse<T>(
+    body: &[u8],
+    charset: Option<&str>,
+    config: &XmlConfig,
+) -> Result<T, XMLPayloadError>
+where
+    T: DeserializeOwned,
+{
+    parse_with_root(body, charset, config).map(|(_, value)| value)
+}
+
+/// Deserialize a buffered payload like [`parse`], also returning its size in bytes.
+pub(crate) fn parse_with_len<T>(
+    body: &[u8],
+    charset: Option<&str>,
+    config: &XmlConfig,
+) -> Result<(usize, T), XMLPayloadError>
+where
+    T: DeserializeOwned,
+{
+    parse(body, charset, config).map(|value| (body.len(), value))
+}
+
+/// Deserialize a buffered payload like [`parse`], also returning the qualified name of the root
+/// element (empty if there's none).
+pub(crate) fn parse_with_root<T>(
+    body: &[u8],
+    charset: Option<&str>,
+    config: &XmlConfig,
+) -> Result<(String, T), XMLPayloadError>
+where
+    T: DeserializeOwned,
+{
+    let prepared = prepare(body, charset, config)?;
+    let value = deserialize(&prepared.text, prepared.rewritten, config)?;
+    Ok((prepared.root.unwrap_or_default(), value))
+}
+
+/// A buffered payload decoded and rewritten by [`prepare`].
+pub(crate) struct Prepared<'a> {
+    /// Qualified name of the root element, if any
+    pub(crate) root: Option<String>,
+    /// Text ready to be deserialized
+    pub(crate) text: Cow<'a, str>,
+    /// Whether `text` was rewritten, so its offsets no longer match the decoded document
+    pub(crate) rewritten: bool,
+}
+
+/// Decode a buffered payload and apply the checks and rewrites enabled in `config`.
+pub(crate) fn prepare<'a>(
+    body: &'a [u8],
+    charset: Option<&str>,
+    config: &XmlConfig,
+) -> Result<Prepared<'a>, XMLPayloadError> {
+    if config.warns() {
+        check_charset(body, charset, config);
+    }
+    let mut text = decode(body, charset, config)?;
+    if config.wrap_root.is_none() {
+        sniff(&text)?;
+    }
+    let root = prepass(text.as_bytes(), config)?;
+    if config.check_well_formed {
+        check_well_formed(&text, config)?;
+    }
+    if let Some(namespace) = &config.required_namespace {
+        check_root_namespace(&text, namespace)?;
+    }
+    if let Some(schema) = &config.schema {
+        schema
+            .validate(&text)
+            .map_err(XMLPayloadError::SchemaValidation)?;
+    }
+
+    let mut rewritten = false;
+    if let Some(resolver) = &config.entity_resolver {
+        text = Cow::Owned(resolve_entities(&text, resolver.as_ref())?);
+        rewritten = true;
+    }
+    if let Some(name) = &config.wrap_root {
+        text = Cow::Owned(wrap_root(&text, name)?);
+        rewritten = true;
+    }
+    if !config.namespaces.is_empty() {
+        text = Cow::Owned(normalize_namespaces(&text, &config.namespaces)?);
+        rewritten = true;
+    }
+    if !config.trim_text || !config.check_end_names || config.lenient {
+        text = Cow::Owned(apply_reader_config(&text, config)?);
+        rewritten = true;
+    }
+    if !config.expand_empty_elements {
+        text = Cow::Owned(drop_empty_elements(&text)?);
+        rewritten = true;
+    }
+    if config.mixed_content {
+        text = Cow::Owned(wrap_mixed_text(&text)?);
+        rewritten = true;
+    }
+    if text.contains("<![CDATA[") {
+        text = Cow::Owned(merge_cdata(&text, config)?);
+        rewritten = true;
+    }
+    if config.prefix_attributes {
+        text = Cow::Owned(prefix_attributes(&text)?);
+        rewritten = true;
+    }
+    Ok(Prepared {
+        root,
+        text,
+        rewritten,
+    })
+}
+
+/// Deserialize a prepared `text`, borrowing from it where possible, with the deserializer
+/// options of `config`.
+///
+/// Errors are only located in `text` if it wasn't `rewritten`, as their position would point
+/// to markup the decoded document doesn't have otherwise.
+///
+/// Types that can't be deserialized from an empty (or whitespace-only) document fail with
+/// [`XMLPayloadError::Empty`] instead of a deserialize error.
+pub(crate) fn deserialize<'de, T>(
+    text: &'de str,
+    rewritten: bool,
+    config: &XmlConfig,
+) -> Result<T, XMLPayloadError>
+where
+    T: Deserialize<'de>,
+{
+    let mut deserializer = match &config.deserializer_builder {
+        Some(builder) => builder(text),
+        None => quick_xml::de::Deserializer::from_str(text),
+    };
+    // The path is only tracked when needed, as it's recorded for every field
+    let path = if config.verbose_errors {
+        Some(FieldPath::default())
+    } else {
+        None
+    };
+    let tracking = Tracking {
+        path: path.as_ref(),
+        deny_unknown: config.deny_unknown_elements,
+    };
+    let res = if config.lenient_scalars {
+        deserialize_tracked(text, LenientScalars(&mut deserializer), tracking)
+    } else {
+        deserialize_tracked(text, &mut deserializer, tracking)
+    };
+    res.map_err(|source| {
+        if text.bytes().all(|b| b.is_ascii_whitespace()) {
+            XMLPayloadError::Empty
+        } else if is_truncated(text, &source) {
+            XMLPayloadError::Incomplete {
+                source,
+                position: (!rewritten).then_some(text.len()),
+            }
+        } else {
+            let position = if rewritten {
+                None
+            } else {
+                error_position(text)
+            };
+            XMLPayloadError::Deserialize {
+                context: path.map(|path| error_context(text, &path, position)),
+                position,
+                source,
+            }
+        }
+    })
+}
+
+/// Deserialize `T` from `deserializer`, recording the path of the field being deserialized and
+/// checking the entries of structs as set by `tracking`.
+fn deserialize_tracked<'de, T, D>(
+    text: &'de str,
+    deserializer: D,
+    tracking: Tracking,
+) -> Result<T, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    if tracking.path.is_some() || tracking.deny_unknown {
+        T::deserialize(with_document(
+            text,
+            Tracked {
+                inner: deserializer,
+                tracking,
+            },
+        ))
+    } else {
+        T::deserialize(with_document(text, deserializer))
+    }
+}
+
+/// Let an [`XmlValue`](crate::XmlValue) read the whole `text` deserialized by `deserializer`.
+#[cfg(feature = "value")]
+fn with_document<'de, D>(text: &'de str, deserializer: D) -> crate::value::Document<'de, D> {
+    crate::value::Document {
+        text,
+        inner: deserializer,
+    }
+}
+
+#[cfg(not(feature = "value"))]
+fn with_document<D>(_text: &str, deserializer: D) -> D {
+    deserializer
+}
+
+/// Read the whole of the decoded `text`, before it's rewritten, checking that it's
+/// well-formed: tags, attributes and references are valid, and elements are properly nested
+/// and closed.
+///
+/// What `config` accepts otherwise isn't rejected: entities of its resolver, mismatched end
+/// tags if it doesn't check them, and what lenient parsing recovers from.
+fn check_well_formed(text: &str, config: &XmlConfig) -> Result<(), XMLPayloadError> {
+    let mut reader = Reader::from_str(text);
+    reader.check_end_names(config.check_end_names && !config.lenient);
+    let resolve = |entity: &str| {
+        config
+            .entity_resolver
+            .as_ref()
+            .and_then(|resolver| resolver.resolve(entity))
+    };
+    let mut depth = 0usize;
+    loop {
+        let checked = match reader.read_event() {
+            Ok(Event::Start(element)) => {
+                depth += 1;
+                check_attributes(&element, &reader, resolve)
+            }
+            Ok(Event::Empty(element)) => check_attributes(&element, &reader, resolve),
+            Ok(Event::End(_)) => {
+                depth = depth.saturating_sub(1);
+                Ok(())
+            }
+            Ok(Event::Text(content)) => content.unescape_with(resolve).map(drop),
+            // Elements left open mean the document was cut
+            Ok(Event::Eof) if depth > 0 && !config.lenient => {
+                return Err(XMLPayloadError::Incomplete {
+                    source: XMLError::UnexpectedEof,
+                    position: Some(text.len()),
+                })
+            }
+            Ok(Event::Eof) => return Ok(()),
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        };
+        match checked {
+            Ok(()) => {}
+            // Stray ampersands are taken literally
+            Err(quick_xml::Error::EscapeError(_)) if config.lenient => {}
+            Err(e @ quick_xml::Error::UnexpectedEof(_)) => {
+                return Err(XMLPayloadError::Incomplete {
+                    source: XMLError::InvalidXml(e),
+                    position: Some(text.len()),
+                })
+            }
+            Err(e) => {
+                return Err(XMLPayloadError::NotWellFormed {
+                    source: XMLError::InvalidXml(e),
+                    position: reader.buffer_position(),
+                })
+            }
+        }
+    }
+}
+
+/// Check that the attributes of `element` are valid and unique, and their values unescape with
+/// `resolve`.
+fn check_attributes<'entity, B>(
+    element: &BytesStart,
+    reader: &Reader<B>,
+    resolve: impl Fn(&str) -> Option<&'entity str> + Copy,
+) -> Result<(), quick_xml::Error> {
+    for attribute in element.attributes() {
+        attribute?.decode_and_unescape_value_with(reader, resolve)?;
+    }
+    Ok(())
+}
+
+/// Whether deserializing `text` failed with `error` because the document ends early.
+fn is_truncated(text: &str, error: &XMLError) -> bool {
+    match error {
+        XMLError::UnexpectedEof | XMLError::InvalidXml(quick_xml::Error::UnexpectedEof(_)) => true,
+        // The name of an end tag cut right after `</` is empty
+        XMLError::InvalidXml(quick_xml::Error::EndEventMismatch { found, .. }) => {
+            found.is_empty() && text.trim_end().ends_with("</")
+        }
+        _ => false,
+    }
+}
+
+/// Locate where deserializing a malformed `text` failed, as a byte offset into it.
+///
+/// The deserializer doesn't expose its position, so the text is scanned again with a reader
+/// configured the same way to find the first syntax error.
+fn error_position(text: &str) -> Option<usize> {
+    let mut reader = Reader::from_str(text);
+    reader
+        .expand_empty_elements(true)
+        .check_end_names(true)
+        .trim_text(true);
+    loop {
+        match reader.read_event() {
+            Err(_) => return Some(reader.buffer_position()),
+            // Well-formed documents fail because of their shape, which has no single position
+            Ok(Event::Eof) => return None,
+            _ => (),
+        }
+    }
+}
+
+/// Number of bytes of markup shown around the position of verbose errors.
+const CONTEXT_BYTES: usize = 40;
+
+/// Describe where deserializing `text` failed, for verbose errors: the path of the field that
+/// failed and the markup around it.
+///
+/// Shape errors have no position, so the markup shown is that of the element `path` leads to.
+fn error_context(text: &str, path: &FieldPath, position: Option<usize>) -> String {
+    let mut context = Vec::new();
+    let segments = path.segments();
+    if !segments.is_empty() {
+        context.push(format!("field `{}`", path));
+    }
+    // Syntax errors are shown with the markup before them, elements from their start tag
+    let start = match position {
+        Some(position) => Some(position.saturating_sub(CONTEXT_BYTES / 2)),
+        None => locate(text, &segments),
+    };
+    if let Some(start) = start {
+        let mut start = start.min(text.len());
+        while !text.is_char_boundary(start) {
+            start -= 1;
+        }
+        let mut end = (start + CONTEXT_BYTES).min(text.len());
+        while !text.is_char_boundary(end) {
+            end += 1;
+        }
+        let snippet: String = text[start..end]
+            .chars()
+            .map(|c| if c.is_whitespace() { ' ' } else { c })
+            .collect();
+        context.push(format!("near `{}`", snippet.trim()));
+    }
+    context.join(", ")
+}
+
+/// Find the position of the element a field path leads to, or of its closest ancestor.
+///
+/// Fields are matched against child elements, and indices count repeated elements of the same
+/// name. The search stops at fields matching attributes or text content.
+fn locate(text: &str, segments: &[Segment]) -> Option<usize> {
+    let mut reader = Reader::from_str(text);
+    reader.trim_text(true);
+    let mut segments = segments.iter().peekable();
+    let mut expected: Option<(&str, usize)> = None;
+    let mut found = None;
+    // Depth of the current element, and of the element matched last
+    let (mut depth, mut level) = (0, 0);
+    loop {
+        let position = reader.buffer_position();
+        let (element, empty) = match reader.read_event().ok()? {
+            Event::Start(element) => (element, false),
+            Event::Empty(element) => (element, true),
+            Event::End(_) if depth == level => return found,
+            Event::End(_) => {
+                depth -= 1;
+                continue;
+            }
+            Event::Eof => return found,
+            _ => continue,
+        };
+        let element_depth = depth + 1;
+        if !empty {
+            depth += 1;
+        }
+        if element_depth != level + 1 {
+            continue;
+        }
+        match &mut expected {
+            // The root element
+            None if level == 0 => {}
+            Some((name, skip)) if element.name().as_ref() == name.as_bytes() => {
+                if *skip > 0 {
+                    *skip -= 1;
+                    continue;
+                }
+            }
+            _ => continue,
+        }
+        level = element_depth;
+        found = text[position..].find('<').map(|offset| position + offset);
+
+        expected = match segments.next() {
+            Some(Segment::Field(name)) => {
+                let attribute = name.strip_prefix('@').unwrap_or(name);
+                let is_attribute = element
+                    .attributes()
+                    .flatten()
+                    .any(|attr| attr.key.as_ref() == attribute.as_bytes());
+                if is_attribute || name.starts_with('$') {
+                    return found;
+                }
+                let skip = match segments.peek() {
+                    Some(Segment::Index(index)) => {
+                        segments.next();
+                        *index
+                    }
+                    _ => 0,
+                };
+                Some((name.as_str(), skip))
+            }
+            // Elements of a sequence at the root
+            Some(Segment::Index(_)) | None => return found,
+        };
+        if empty {
+            return found;
+        }
+    }
+}
+
+/// Unicode byte order mark encoded as utf-8
+#[cfg(not(feature = "encoding"))]
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Decode the payload into utf-8.
+///
+/// Unless an encoding is forced by `config`, a leading byte order mark decides the encoding and
+/// is stripped. Otherwise the encoding is taken from the `charset` of the request first, then
+/// from the `encoding` attribute of the XML declaration, and falls back to utf-8.
+#[cfg(feature = "encoding")]
+pub(crate) fn decode<'a>(
+    body: &'a [u8],
+    charset: Option<&str>,
+    config: &XmlConfig,
+) -> Result<Cow<'a, str>, XMLPayloadError> {
+    let (encoding, body) = match (config.force_encoding, Encoding::for_bom(body)) {
+        (Some(forced), Some((encoding, bom_length))) if encoding == forced => {
+            (forced, &body[bom_length..])
+        }
+        (Some(forced), _) => (forced, body),
+        (None, Some((encoding, bom_length))) => (encoding, &body[bom_length..]),
+        (None, None) => (
+            charset
+                .and_then(|label| Encoding::for_label(label.as_bytes()))
+                .or_else(|| declared_encoding(body))
+                .or(config.default_encoding)
+                .unwrap_or(UTF_8),
+            body,
+        ),
+    };
+
+    encoding
+        .decode_without_bom_handling_and_without_replacement(body)
+        .ok_or_else(|| XMLError::InvalidXml(quick_xml::Error::NonDecodable(None)).into())
+}
+
+/// Decode the payload into utf-8, stripping a leading byte order mark.
+#[cfg(not(feature = "encoding"))]
+pub(crate) fn decode<'a>(
+    body: &'a [u8],
+    _charset: Option<&str>,
+    _config: &XmlConfig,
+) -> Result<Cow<'a, str>, XMLPayloadError> {
+    let body = body.strip_prefix(UTF8_BOM).unwrap_or(body);
+    Ok(Cow::Borrowed(
+        std::str::from_utf8(body).map_err(|e| XMLError::InvalidXml(e.into()))?,
+    ))
+}
+
+/// Read the encoding declared in the XML declaration, if any.
+#[cfg(feature = "encoding")]
+fn declared_encoding(body: &[u8]) -> Option<&'static Encoding> {
+    Encoding::for_label(declared_label(body)?.as_bytes())
+}
+
+/// Read the label of the encoding declared in the XML declaration, if any.
+fn declared_label(body: &[u8]) -> Option<String> {
+    let mut reader = Reader::from_reader(body);
+    let mut buf = Vec::new();
+    match reader.read_event_into(&mut buf) {
+        Ok(Event::Decl(decl)) => String::from_utf8(decl.encoding()?.ok()?.into_owned()).ok(),
+        _ => None,
+    }
+}
+
+/// Warn if `charset` and the encoding of the XML declaration name different encodings.
+fn check_charset(body: &[u8], charset: Option<&str>, config: &XmlConfig) {
+    let (charset, declared) = match (charset, declared_label(body)) {
+        (Some(charset), Some(declared)) => (charset, declared),
+        _ => return,
+    };
+    #[cfg(feature = "encoding")]
+    let same = match (
+        Encoding::for_label(charset.as_bytes()),
+        Encoding::for_label(declared.as_bytes()),
+    ) {
+        (Some(charset), Some(declared)) => charset == declared,
+        _ => charset.eq_ignore_ascii_case(&declared),
+    };
+    #[cfg(not(feature = "encoding"))]
+    let same = charset.eq_ignore_ascii_case(&declared);
+    if !same {
+        config.warn(XmlWarning::EncodingMismatch {
+            charset: charset.to_owned(),
+            declared,
+        });
+    }
+}
+
+/// Reject payloads starting like JSON sent with an XML content type, before they fail to
+/// deserialize in a more obscure way.
+///
+/// Other text is left to the prepass, which rejects it as [`XMLPayloadError::NotAnElement`].
+fn sniff(text: &str) -> Result<(), XMLPayloadError> {
+    match text.trim_start().chars().next() {
+        Some(found @ ('{' | '[')) => Err(XMLPayloadError::NotXml { found }),
+        _ => Ok(()),
+    }
+}
+
+/// Scan the document for structures rejected by `config` without deserializing it.
+///
+/// Syntax errors are left to the deserializer to report, but text in place of the root element
+/// is rejected. Returns the qualified name of the root element, if any.
+fn prepass(body: &[u8], config: &XmlConfig) -> Result<Option<String>, XMLPayloadError> {
+    // Only the prolog needs to be scanned unless a check on elements is enabled
+    let scan_elements = config.max_depth.is_some()
+        || config.max_elements.is_some()
+        || config.max_attributes.is_some()
+        || config.max_attribute_length.is_some();
+
+    let mut reader = Reader::from_reader(body);
+    // Mismatched end tags must not stop the scan if they are accepted later on
+    reader.check_end_names(false);
+    let mut buf = Vec::new();
+    let mut depth = 0;
+    let mut elements = 0;
+    let mut root = None;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::DocType(_)) if config.forbid_doctype => {
+                return Err(XMLPayloadError::DoctypeForbidden);
+            }
+            // Internal subsets are where the nested entities of "billion laughs" style attacks
+            // are declared
+            Ok(Event::DocType(doctype))
+                if !config.allow_internal_subset && doctype.contains(&b'[') =>
+            {
+                return Err(XMLPayloadError::EntityExpansionLimit);
+            }
+            // Text before the root element is most likely plain text sent as XML, unless it's
+            // to be wrapped in a root element
+            Ok(Event::Text(text))
+                if root.is_none()
+                    && config.wrap_root.is_none()
+                    && !text.iter().all(u8::is_ascii_whitespace) =>
+            {
+                return Err(XMLPayloadError::NotAnElement);
+            }
+            Ok(Event::CData(_)) if root.is_none() && config.wrap_root.is_none() => {
+                return Err(XMLPayloadError::NotAnElement);
+            }
+            Ok(Event::Start(element)) => {
+                depth += 1;
+                elements += 1;
+                check_element(&element, depth, elements, config)?;
+                root.get_or_insert_with(|| element_name(&element));
+                if !scan_elements {
+                    return Ok(root);
+                }
+            }
+            Ok(Event::Empty(element)) => {
+                elements += 1;
+                check_element(&element, depth + 1, elements, config)?;
+                root.get_or_insert_with(|| element_name(&element));
+                if !scan_elements {
+                    return Ok(root);
+                }
+            }
+            Ok(Event::End(_)) => depth = depth.saturating_sub(1),
+            Ok(Event::Eof) | Err(_) => return Ok(root),
+            _ => (),
+        }
+        buf.clear();
+    }
+}
+
+/// Qualified name of the root element of a document starting with `prefix`, if `prefix` is
+/// long enough to contain its start tag.
+pub(crate) fn peek_root(prefix: &[u8]) -> Option<String> {
+    let mut reader = Reader::from_reader(prefix);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            // The reader takes a start tag cut by the end of `prefix` for a whole one
+            Ok(Event::Start(element)) | Ok(Event::Empty(element)) => {
+                let end = reader.buffer_position();
+                return Some(element_name(&element)).filter(|_| prefix[..end].ends_with(b">"));
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => (),
+        }
+        buf.clear();
+    }
+}
+
+/// Rewrite the document so that the deserializer, whose reader always trims text and checks end
+/// names, reads it the way `config` asks for.
+///
+/// Whitespace around text content is kept by turning it into character references, and end tags
+/// are renamed after the element they close. In lenient mode, unmatched end tags are dropped,
+/// unclosed elements are closed and stray ampersands are escaped.
+fn apply_reader_config(text: &str, config: &XmlConfig) -> Result<String, XMLError> {
+    let mut reader = Reader::from_str(text);
+    reader.check_end_names(config.check_end_names && !config.lenient);
+    let mut writer = Writer::new(Vec::with_capacity(text.len()));
+    let mut open = Vec::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                let element = lenient_attributes(element, config)?;
+                open.push(element.name().as_ref().to_vec());
+                writer.write_event(Event::Start(element))?;
+            }
+            Event::Empty(element) => {
+                writer.write_event(Event::Empty(lenient_attributes(element, config)?))?;
+            }
+            Event::End(element) if config.lenient => {
+                let name = element.name();
+                match open.iter().rposition(|open| &open[..] == name.as_ref()) {
+                    Some(position) => {
+                        for name in open.drain(position..).rev() {
+                            if config.warns() && &name[..] != element.name().as_ref() {
+                                config.warn(XmlWarning::UnclosedElement {
+                                    name: String::from_utf8_lossy(&name).into_owned(),
+                                });
+                            }
+                            writer.write_event(Event::End(end_tag(name)?))?;
+                        }
+                    }
+                    None => config.warn(XmlWarning::DanglingEndTag {
+                        name: String::from_utf8_lossy(name.as_ref()).into_owned(),
+                    }),
+                }
+            }
+            Event::End(element) => match open.pop() {
+                Some(name) => {
+                    if config.warns() && &name[..] != element.name().as_ref() {
+                        config.warn(XmlWarning::MismatchedEndTag {
+                            expected: String::from_utf8_lossy(&name).into_owned(),
+                            found: String::from_utf8_lossy(element.name().as_ref()).into_owned(),
+                        });
+                    }
+                    writer.write_event(Event::End(end_tag(name)?))?
+                }
+                None => writer.write_event(Event::End(element))?,
+            },
+            // Text outside of the root element and whitespace between elements are
+            // insignificant
+            Event::Text(content)
+                if !config.trim_text
+                    && !open.is_empty()
+                    && !content.iter().all(u8::is_ascii_whitespace) =>
+            {
+                let content = lenient_ampersands(&content, config);
+                writer.write_event(Event::Text(BytesText::from_escaped(escape_whitespace(
+                    std::str::from_utf8(&content).map_err(quick_xml::Error::from)?,
+                ))))?;
+            }
+            Event::Text(content) if config.lenient => {
+                let content = lenient_ampersands(&content, config);
+                writer.write_event(Event::Text(BytesText::from_escaped(
+                    std::str::from_utf8(&content).map_err(quick_xml::Error::from)?,
+                )))?;
+            }
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+    }
+    if config.lenient {
+        for name in open.into_iter().rev() {
+            config.warn(XmlWarning::UnclosedElement {
+                name: String::from_utf8_lossy(&name).into_owned(),
+            });
+            writer.write_event(Event::End(end_tag(name)?))?;
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+fn end_tag(name: Vec<u8>) -> Result<BytesEnd<'static>, quick_xml::Error> {
+    Ok(BytesEnd::new(
+        String::from_utf8(name).map_err(|e| quick_xml::Error::from(e.utf8_error()))?,
+    ))
+}
+
+/// Escape stray ampersands and double quotes in the attribute values of `element` in lenient
+/// mode.
+fn lenient_attributes<'a>(
+    element: BytesStart<'a>,
+    config: &XmlConfig,
+) -> Result<BytesStart<'a>, quick_xml::Error> {
+    if !config.lenient {
+        return Ok(element);
+    }
+    map_attributes(element, |key, value| {
+        (key.to_vec(), escape_ampersands(value).into_owned())
+    })
+}
+
+/// Rewrite the attributes of `element` with `f`, which is given the raw key and escaped value
+/// of each attribute.
+fn map_attributes<'a, F>(element: BytesStart<'a>, f: F) -> Result<BytesStart<'a>, quick_xml::Error>
+where
+    F: Fn(&[u8], &[u8]) -> (Vec<u8>, Vec<u8>),
+{
+    let attributes = element
+        .attributes()
+        .with_checks(false)
+        .map(|attribute| {
+            let attribute = attribute?;
+            let (key, mut value) = f(attribute.key.as_ref(), &attribute.value);
+            // Single quoted values may contain double quotes
+            if value.contains(&b'"') {
+                value = String::from_utf8_lossy(&value)
+                    .replace('"', "&quot;")
+                    .into_bytes();
+            }
+            Ok((key, value))
+        })
+        .collect::<Result<Vec<_>, quick_xml::Error>>()?;
+
+    let mut element = element;
+    element.clear_attributes();
+    for (key, value) in &attributes {
+        element.push_attribute((&key[..], &value[..]));
+    }
+    Ok(element)
+}
+
+/// Wrap the content of `text` following its prolog in a `name` element.
+fn wrap_root(text: &str, name: &str) -> Result<String, XMLError> {
+    let mut reader = Reader::from_str(text);
+    let start = loop {
+        let position = reader.buffer_position();
+        match reader.read_event()? {
+            Event::Decl(_) | Event::DocType(_) | Event::Comment(_) | Event::PI(_) => {}
+            Event::Text(content) if content.iter().all(u8::is_ascii_whitespace) => {}
+            _ => break position,
+        }
+    };
+
+    let (prolog, content) = text.split_at(start);
+    let mut wrapped = String::with_capacity(text.len() + 2 * name.len() + 5);
+    wrapped.push_str(prolog);
+    wrapped.push('<');
+    wrapped.push_str(name);
+    wrapped.push('>');
+    wrapped.push_str(content);
+    wrapped.push_str("</");
+    wrapped.push_str(name);
+    wrapped.push('>');
+    Ok(wrapped)
+}
+
+/// Drop the self-closing elements without attributes, except the root element, so that they
+/// read as absent rather than empty.
+fn drop_empty_elements(text: &str) -> Result<String, XMLError> {
+    let mut reader = Reader::from_str(text);
+    reader.check_end_names(false);
+    let mut writer = Writer::new(Vec::with_capacity(text.len()));
+    let mut depth = 0usize;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                depth += 1;
+                writer.write_event(Event::Start(element))?;
+            }
+            Event::End(element) => {
+                depth = depth.saturating_sub(1);
+                writer.write_event(Event::End(element))?;
+            }
+            Event::Empty(element) if depth > 0 && element.attributes().next().is_none() => {}
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+/// Rename attributes to `@name`, except namespace bindings, so that they can't be confused with
+/// child elements of the same name.
+fn prefix_attributes(text: &str) -> Result<String, XMLError> {
+    let prefix = |key: &[u8], value: &[u8]| {
+        let key = if key == b"xmlns" || key.starts_with(b"xmlns:") {
+            key.to_vec()
+        } else {
+            [&b"@"[..], key].concat()
+        };
+        (key, value.to_vec())
+    };
+
+    let mut reader = Reader::from_str(text);
+    reader.check_end_names(false);
+    let mut writer = Writer::new(Vec::with_capacity(text.len()));
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                writer.write_event(Event::Start(map_attributes(element, prefix)?))?
+            }
+            Event::Empty(element) => {
+                writer.write_event(Event::Empty(map_attributes(element, prefix)?))?
+            }
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+/// Escape stray ampersands of escaped `content` in lenient mode.
+fn lenient_ampersands<'a>(content: &'a [u8], config: &XmlConfig) -> Cow<'a, [u8]> {
+    if config.lenient {
+        escape_ampersands(content)
+    } else {
+        Cow::Borrowed(content)
+    }
+}
+
+/// Replace ampersands of escaped `content` that don't start a character or entity reference
+/// with `&amp;`.
+fn escape_ampersands(content: &[u8]) -> Cow<'_, [u8]> {
+    let is_reference = |rest: &[u8]| {
+        let end = match rest.iter().position(|&b| b == b';') {
+            Some(end) => end,
+            None => return false,
+        };
+        match &rest[..end] {
+            [b'#', b'x', digits @ ..] if !digits.is_empty() => {
+                digits.iter().all(u8::is_ascii_hexdigit)
+            }
+            [b'#', digits @ ..] if !digits.is_empty() => digits.iter().all(u8::is_ascii_digit),
+            [first, name @ ..] => {
+                (first.is_ascii_alphabetic() || *first == b'_' || *first == b':')
+                    && name.iter().all(|&b| {
+                        b.is_ascii_alphanumeric() || matches!(b, b'_' | b':' | b'-' | b'.')
+                    })
+            }
+            [] => false,
+        }
+    };
+
+    if !content
+        .iter()
+        .enumerate()
+        .any(|(i, &b)| b == b'&' && !is_reference(&content[i + 1..]))
+    {
+        return Cow::Borrowed(content);
+    }
+
+    let mut escaped = Vec::with_capacity(content.len() + 8);
+    for (i, &b) in content.iter().enumerate() {
+        if b == b'&' && !is_reference(&content[i + 1..]) {
+            escaped.extend_from_slice(b"&amp;");
+        } else {
+            escaped.push(b);
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Merge each run of text and CDATA sections into a single text node, since the deserializer
+/// only reads the first one of them.
+///
+/// CDATA sections are kept verbatim, including their leading and trailing whitespace, while
+/// text around them is trimmed like the deserializer would unless `config` says otherwise.
+fn merge_cdata(text: &str, config: &XmlConfig) -> Result<String, XMLError> {
+    let mut reader = Reader::from_str(text);
+    reader.check_end_names(false);
+    let mut writer = Writer::new(Vec::with_capacity(text.len()));
+    let mut run = Vec::new();
+    // Whether the run is the content of an element wrapping mixed text, kept verbatim
+    let mut in_mixed_text = false;
+    loop {
+        match reader.read_event()? {
+            event @ (Event::Text(_) | Event::CData(_)) => run.push(event),
+            event => {
+                if run.iter().any(|event| matches!(event, Event::CData(_))) {
+                    let content = merge_run(&run, config.trim_text && !in_mixed_text)?;
+                    writer.write_event(Event::Text(BytesText::from_escaped(escape_whitespace(
+                        &escape(&content),
+                    ))))?;
+                } else {
+                    for event in run.iter() {
+                        writer.write_event(event.borrow())?;
+                    }
+                }
+                run.clear();
+
+                in_mixed_text = matches!(
+                    &event,
+                    Event::Start(element) if element.name().as_ref() == MIXED_TEXT.as_bytes()
+                );
+                match event {
+                    Event::Eof => break,
+                    event => writer.write_event(event)?,
+                }
+            }
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+/// Name of the elements wrapping the text runs of mixed content.
+const MIXED_TEXT: &str = "$text";
+
+/// Wrap each run of text and CDATA sections of the elements that also have child elements in a
+/// `$text` element, so that the deserializer reads mixed content as a sequence of elements.
+///
+/// Whitespace around the wrapped runs is kept as character references, and whitespace-only runs
+/// are dropped.
+fn wrap_mixed_text(text: &str) -> Result<String, XMLError> {
+    // Whether each element, in document order, has child elements
+    let mut mixed = Vec::new();
+    let mut open = Vec::new();
+    let mut reader = Reader::from_str(text);
+    reader.check_end_names(false);
+    loop {
+        match reader.read_event()? {
+            Event::Start(_) => {
+                if let Some(&parent) = open.last() {
+                    mixed[parent] = true;
+                }
+                open.push(mixed.len());
+                mixed.push(false);
+            }
+            Event::Empty(_) => {
+                if let Some(&parent) = open.last() {
+                    mixed[parent] = true;
+                }
+            }
+            Event::End(_) => {
+                open.pop();
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+
+    let mut reader = Reader::from_str(text);
+    reader.check_end_names(false);
+    let mut writer = Writer::new(Vec::with_capacity(text.len()));
+    let mut elements = mixed.into_iter();
+    let mut open = Vec::new();
+    let mut run = Vec::new();
+    loop {
+        match reader.read_event()? {
+            event @ (Event::Text(_) | Event::CData(_)) if open.last() == Some(&true) => {
+                run.push(event)
+            }
+            event => {
+                let significant = run.iter().any(|event| match event {
+                    Event::Text(content) => !content.iter().all(u8::is_ascii_whitespace),
+                    _ => true,
+                });
+                if significant {
+                    writer.write_event(Event::Start(BytesStart::new(MIXED_TEXT)))?;
+                    for event in run.iter() {
+                        match event {
+                            Event::Text(content) => {
+                                let content =
+                                    std::str::from_utf8(content).map_err(quick_xml::Error::from)?;
+                                writer.write_event(Event::Text(BytesText::from_escaped(
+                                    escape_whitespace(content),
+                                )))?;
+                            }
+                            event => writer.write_event(event.borrow())?,
+                        }
+                    }
+                    writer.write_event(Event::End(BytesEnd::new(MIXED_TEXT)))?;
+                }
+                run.clear();
+
+                match event {
+                    Event::Start(element) => {
+                        open.push(elements.next().unwrap_or(false));
+                        writer.write_event(Event::Start(element))?;
+                    }
+                    Event::End(element) => {
+                        open.pop();
+                        writer.write_event(Event::End(element))?;
+                    }
+                    Event::Eof => break,
+                    event => writer.write_event(event)?,
+                }
+            }
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+/// Concatenate the unescaped content of a run of text and CDATA sections.
+fn merge_run(run: &[Event], trim_text: bool) -> Result<String, XMLError> {
+    let mut content = String::new();
+    for (i, event) in run.iter().enumerate() {
+        match event {
+            Event::Text(text) => {
+                let text = text.unescape()?;
+                let text = match (i == 0, i == run.len() - 1) {
+                    _ if !trim_text => &text[..],
+                    (true, true) => text.trim(),
+                    (true, false) => text.trim_start(),
+                    (false, true) => text.trim_end(),
+                    (false, false) => &text[..],
+                };
+                content.push_str(text);
+            }
+            Event::CData(cdata) => {
+                content.push_str(std::str::from_utf8(cdata).map_err(quick_xml::Error::from)?)
+            }
+            _ => (),
+        }
+    }
+    Ok(content)
+}
+
+/// Replace leading and trailing whitespace of escaped `content` with character references.
+fn escape_whitespace(content: &str) -> String {
+    let is_whitespace = |c: char| matches!(c, ' ' | '\t' | '\r' | '\n');
+    let trimmed = content.trim_matches(is_whitespace);
+    let start = content.len() - content.trim_start_matches(is_whitespace).len();
+    let end = start + trimmed.len();
+
+    let reference = |c: char| format!("&#{};", c as u32);
+    let mut escaped: String = content[..start].chars().map(reference).collect();
+    escaped.push_str(trimmed);
+    escaped.extend(content[end..].chars().map(reference));
+    escaped
+}
+
+fn element_name(element: &BytesStart) -> String {
+    String::from_utf8_lossy(element.name().as_ref()).into_owned()
+}
+
+/// Check an element found at `depth`, where the root element is at depth 1, and which is the
+/// `count`th element of the document.
+fn check_element(
+    element: &BytesStart,
+    depth: usize,
+    count: usize,
+    config: &XmlConfig,
+) -> Result<(), XMLPayloadError> {
+    if let Some(expected) = config.root.as_ref().filter(|_| depth == 1) {
+        let found = element.name();
+        if found.as_ref() != expected.as_bytes() {
+            return Err(XMLPayloadError::UnexpectedRoot {
+                expected: expected.clone(),
+                found: element_name(element),
+            });
+        }
+    }
+
+    if let Some(limit) = config.max_depth.filter(|&limit| depth > limit) {
+        return Err(XMLPayloadError::DepthLimitExceeded { limit });
+    }
+    if let Some(limit) = config.max_elements.filter(|&limit| count > limit) {
+        return Err(XMLPayloadError::ElementLimitExceeded { limit });
+    }
+    if config.max_attributes.is_some() || config.max_attribute_length.is_some() {
+        check_attribute_limits(element, config)?;
+    }
+    Ok(())
+}
+
+/// Check the number of attributes of `element` and the length of their values.
+///
+/// Malformed attributes end the check, and are left to the deserializer to report.
+fn check_attribute_limits(element: &BytesStart, config: &XmlConfig) -> Result<(), XMLPayloadError> {
+    let mut attributes = element.attributes();
+    attributes.with_checks(false);
+    for (i, attribute) in attributes.map_while(Result::ok).enumerate() {
+        if let Some(limit) = config.max_attributes.filter(|&limit| i >= limit) {
+            return Err(XMLPayloadError::AttributeLimitExceeded {
+                limit,
+                value_length: false,
+            });
+        }
+        if let Some(limit) = config
+            .max_attribute_length
+            .filter(|&limit| attribute.value.len() > limit)
+        {
+            return Err(XMLPayloadError::AttributeLimitExceeded {
+                limit,
+                value_length: true,
+            });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use actix_web::dev;
+use actix_web::Error as ActixError;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use serde::de::DeserializeOwned;
+
+use crate::{Xml, XmlMeta};
+
+/// Information about the request an [`XmlParts`](struct.XmlParts.html) was extracted from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlInfo {
+    pub(crate) content_type: Option<mime::Mime>,
+    pub(crate) bytes: usize,
+    pub(crate) decompressed: bool,
+}
+
+impl XmlInfo {
+    fn from_req(req: &HttpRequest) -> Self {
+        XmlInfo {
+            content_type: req.mime_type().ok().flatten(),
+            bytes: req
+                .extensions()
+                .get::<XmlMeta>()
+                .map_or(0, XmlMeta::byte_len),
+            decompressed: decompressed(req),
+        }
+    }
+
+    /// Content type of the request, parameters included
+    pub fn content_type(&self) -> Option<&mime::Mime> {
+        self.content_type.as_ref()
+    }
+
+    /// Size of the payload in bytes, once decompressed
+    pub fn byte_len(&self) -> usize {
+        self.bytes
+    }
+
+    /// Whether the payload was sent compressed and decompressed before parsing
+    pub fn decompressed(&self) -> bool {
+        self.decompressed
+    }
+}
+
+/// Whether the payload of `req` is decompressed before parsing, following the
+/// `Content-Encoding` header.
+pub(crate) fn decompressed(req: &HttpRequest) -> bool {
+    #[cfg(feature = "__compress")]
+    {
+        use std::convert::TryFrom;
+
+        use actix_web::http::header::{self, ContentEncoding};
+
+        req.headers()
+            .get(header::CONTENT_ENCODING)
+            .and_then(|encoding| encoding.to_str().ok())
+            .and_then(|encoding| ContentEncoding::try_from(encoding).ok())
+            .is_some_and(|encoding| encoding != ContentEncoding::Identity)
+    }
+    #[cfg(all(feature = "self-decompress", not(feature = "__compress")))]
+    {
+        crate::decompress::ContentEncoding::from_req(req).is_some()
+    }
+    #[cfg(not(any(feature = "__compress", feature = "self-decompress")))]
+    {
+        let _ = req;
+        false
+    }
+}
+
+/// Xml extractor yielding the payload along with information about the request
+///
+/// Extraction works the same way as with [`Xml`](struct.Xml.html), but also records the
+/// content type received, the payload size and whether it was decompressed, e.g. for auditing.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::XmlParts;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Info {
+///     username: String,
+/// }
+///
+/// async fn index(xml: XmlParts<Info>) -> String {
+///     let (info, meta) = xml.into_parts();
+///     format!("Received {} bytes from {}", meta.byte_len(), info.username)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct XmlParts<T>(pub T, pub XmlInfo);
+
+impl<T> XmlParts<T> {
+    /// Deconstruct to the inner value and the request information
+    pub fn into_parts(self) -> (T, XmlInfo) {
+        (self.0, self.1)
+    }
+
+    /// Deconstruct to the inner value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+
+    /// Get a reference to the request information
+    pub fn info(&self) -> &XmlInfo {
+        &self.1
+    }
+}
+
+impl<T> FromRequest for XmlParts<T>
+where
+    T: DeserializeOwned + 'static,
+{
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self, ActixError>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        // Xml records the payload size in the request extensions once extracted
+        let req = req.clone();
+        Xml::<T>::from_request(&req, payload)
+            .map(move |res| res.map(|Xml(value)| XmlParts(value, XmlInfo::from_req(&req))))
+            .boxed_local()
+    }
+}
@@ -0,0 +1,679 @@
+use std::cell::RefCell;
+use std::fmt;
+
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+
+/// Step of a [`FieldPath`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Segment {
+    /// Field of a struct or key of a map
+    Field(String),
+    /// Element of a sequence
+    Index(usize),
+}
+
+/// Path of the field being deserialized, left pointing at the field that failed on error.
+#[derive(Debug, Default)]
+pub(crate) struct FieldPath(RefCell<Vec<Segment>>);
+
+impl FieldPath {
+    pub(crate) fn segments(&self) -> Vec<Segment> {
+        self.0.borrow().clone()
+    }
+
+    /// Run `f` with `segment` appended to the path, which is removed again unless `f` fails.
+    fn with<T, E>(&self, segment: Segment, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let len = {
+            let mut segments = self.0.borrow_mut();
+            let len = segments.len();
+            segments.push(segment);
+            len
+        };
+        let res = f();
+        if res.is_ok() {
+            self.0.borrow_mut().truncate(len);
+        }
+        res
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.0.borrow().iter().enumerate() {
+            match segment {
+                Segment::Field(name) if i == 0 => f.write_str(name)?,
+                Segment::Field(name) => write!(f, ".{}", name)?,
+                Segment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a [`Tracked`] deserializer does besides forwarding to the one it wraps.
+#[derive(Clone, Copy)]
+pub(crate) struct Tracking<'a> {
+    /// Path recording the field being deserialized
+    pub(crate) path: Option<&'a FieldPath>,
+    /// Reject the entries of structs not matching any of their fields
+    pub(crate) deny_unknown: bool,
+}
+
+impl<'a> Tracking<'a> {
+    /// Run `f` with `segment` appended to the path, if recorded.
+    fn with<T, E>(
+        &self,
+        segment: impl FnOnce() -> Segment,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        match self.path {
+            Some(path) => path.with(segment(), f),
+            None => f(),
+        }
+    }
+}
+
+/// Deserializer recording the path of the field being deserialized and checking the entries of
+/// structs, as set by `tracking`, and forwarding everything else.
+///
+/// Nested values are deserialized the same way.
+pub(crate) struct Tracked<'a, D> {
+    pub(crate) inner: D,
+    pub(crate) tracking: Tracking<'a>,
+}
+
+macro_rules! forward {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: Visitor<'de>,
+        {
+            self.inner.$method($($arg,)* Wrap::new(visitor, self.tracking))
+        }
+    )*};
+}
+
+impl<'a, 'de, D> Deserializer<'de> for Tracked<'a, D>
+where
+    D: Deserializer<'de>,
+{
+    type Error = D::Error;
+
+    forward! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let visitor = Wrap {
+            inner: visitor,
+            tracking: self.tracking,
+            fields: Some(fields),
+        };
+        self.inner.deserialize_struct(name, fields, visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+/// Visitor, seed or access handing nested deserializers to `inner` wrapped in [`Tracked`].
+struct Wrap<'a, T> {
+    inner: T,
+    tracking: Tracking<'a>,
+    /// Fields of the struct visited, if any
+    fields: Option<&'static [&'static str]>,
+}
+
+impl<'a, T> Wrap<'a, T> {
+    fn new(inner: T, tracking: Tracking<'a>) -> Self {
+        Wrap {
+            inner,
+            tracking,
+            fields: None,
+        }
+    }
+
+    fn wrap<U>(&self, inner: U) -> Wrap<'a, U> {
+        Wrap::new(inner, self.tracking)
+    }
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty);)*) => {$(
+        fn $method<E>(self, v: $ty) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            self.inner.$method(v)
+        }
+    )*};
+}
+
+impl<'a, 'de, V> Visitor<'de> for Wrap<'a, V>
+where
+    V: Visitor<'de>,
+{
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool);
+        visit_i8(i8);
+        visit_i16(i16);
+        visit_i32(i32);
+        visit_i64(i64);
+        visit_i128(i128);
+        visit_u8(u8);
+        visit_u16(u16);
+        visit_u32(u32);
+        visit_u64(u64);
+        visit_u128(u128);
+        visit_f32(f32);
+        visit_f64(f64);
+        visit_char(char);
+        visit_str(&str);
+        visit_borrowed_str(&'de str);
+        visit_string(String);
+        visit_bytes(&[u8]);
+        visit_borrowed_bytes(&'de [u8]);
+        visit_byte_buf(Vec<u8>);
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.inner.visit_none()
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.inner.visit_some(Tracked {
+            inner: deserializer,
+            tracking: self.tracking,
+        })
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.inner.visit_unit()
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.inner.visit_newtype_struct(Tracked {
+            inner: deserializer,
+            tracking: self.tracking,
+        })
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let seq = Elements {
+            inner: seq,
+            tracking: self.tracking,
+            index: 0,
+        };
+        self.inner.visit_seq(seq)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let map = Entries {
+            inner: map,
+            tracking: self.tracking,
+            fields: self.fields,
+            key: None,
+        };
+        self.inner.visit_map(map)
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        let data = self.wrap(data);
+        self.inner.visit_enum(data)
+    }
+}
+
+impl<'a, 'de, S> DeserializeSeed<'de> for Wrap<'a, S>
+where
+    S: DeserializeSeed<'de>,
+{
+    type Value = S::Value;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.inner.deserialize(Tracked {
+            inner: deserializer,
+            tracking: self.tracking,
+        })
+    }
+}
+
+/// Sequence recording the index of the element being deserialized.
+struct Elements<'a, A> {
+    inner: A,
+    tracking: Tracking<'a>,
+    index: usize,
+}
+
+impl<'a, 'de, A> SeqAccess<'de> for Elements<'a, A>
+where
+    A: SeqAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let (inner, tracking, index) = (&mut self.inner, self.tracking, self.index);
+        let element = tracking.with(
+            || Segment::Index(index),
+            || inner.next_element_seed(Wrap::new(seed, tracking)),
+        )?;
+        self.index += 1;
+        Ok(element)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+/// Map recording the key of the value being deserialized, and checking it against `fields`.
+struct Entries<'a, A> {
+    inner: A,
+    tracking: Tracking<'a>,
+    fields: Option<&'static [&'static str]>,
+    key: Option<String>,
+}
+
+impl<'a, 'de, A> MapAccess<'de> for Entries<'a, A>
+where
+    A: MapAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        self.key = None;
+        let key = self.inner.next_key_seed(CaptureKey {
+            inner: seed,
+            key: &mut self.key,
+        })?;
+        if let (Some(fields), Some(name), true) =
+            (self.fields, &self.key, self.tracking.deny_unknown)
+        {
+            if is_unknown(name, fields) {
+                // Leave the path pointing at the unknown entry
+                return self.tracking.with(
+                    || Segment::Field(name.clone()),
+                    || Err(de::Error::unknown_field(name, fields)),
+                );
+            }
+        }
+        Ok(key)
+    }
+
+    fn next_value_seed<T>(&mut self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let (inner, tracking) = (&mut self.inner, self.tracking);
+        let key = self.key.take();
+        tracking.with(
+            || Segment::Field(key.unwrap_or_else(|| "?".to_string())),
+            || inner.next_value_seed(Wrap::new(seed, tracking)),
+        )
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, 'de, A> EnumAccess<'de> for Wrap<'a, A>
+where
+    A: EnumAccess<'de>,
+{
+    type Error = A::Error;
+    type Variant = Wrap<'a, A::Variant>;
+
+    fn variant_seed<T>(self, seed: T) -> Result<(T::Value, Self::Variant), Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let tracking = self.tracking;
+        self.inner
+            .variant_seed(seed)
+            .map(|(value, variant)| (value, Wrap::new(variant, tracking)))
+    }
+}
+
+impl<'a, 'de, A> VariantAccess<'de> for Wrap<'a, A>
+where
+    A: VariantAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let seed = self.wrap(seed);
+        self.inner.newtype_variant_seed(seed)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let visitor = self.wrap(visitor);
+        self.inner.tuple_variant(len, visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let visitor = Wrap {
+            inner: visitor,
+            tracking: self.tracking,
+            fields: Some(fields),
+        };
+        self.inner.struct_variant(fields, visitor)
+    }
+}
+
+/// Whether the entry named `name` doesn't match any of `fields`.
+///
+/// Text content and namespace bindings are not entries a struct is expected to declare.
+fn is_unknown(name: &str, fields: &[&str]) -> bool {
+    let binding = name == "xmlns"
+        || name.starts_with("xmlns:")
+        || name == "@xmlns"
+        || name.starts_with("@xmlns:");
+    !binding && name != "$value" && !fields.contains(&name)
+}
+
+/// Seed recording the name of a map key, as the deserializer passes it to `inner`.
+struct CaptureKey<'k, S> {
+    inner: S,
+    key: &'k mut Option<String>,
+}
+
+impl<'k, 'de, S> DeserializeSeed<'de> for CaptureKey<'k, S>
+where
+    S: DeserializeSeed<'de>,
+{
+    type Value = S::Value;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.inner.deserialize(KeyDeserializer {
+            inner: deserializer,
+            key: self.key,
+        })
+    }
+}
+
+/// Deserializer of a map key recording its name.
+struct KeyDeserializer<'k, D> {
+    inner: D,
+    key: &'k mut Option<String>,
+}
+
+macro_rules! forward_key {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: Visitor<'de>,
+        {
+            self.inner.$method($($arg,)* KeyVisitor { inner: visitor, key: self.key })
+        }
+    )*};
+}
+
+impl<'k, 'de, D> Deserializer<'de> for KeyDeserializer<'k, D>
+where
+    D: Deserializer<'de>,
+{
+    type Error = D::Error;
+
+    forward_key! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+/// Visitor of a map key recording its name.
+struct KeyVisitor<'k, V> {
+    inner: V,
+    key: &'k mut Option<String>,
+}
+
+macro_rules! record_visit {
+    ($($method:ident($ty:ty);)*) => {$(
+        fn $method<E>(self, v: $ty) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            *self.key = Some(v.to_string());
+            self.inner.$method(v)
+        }
+    )*};
+}
+
+impl<'k, 'de, V> Visitor<'de> for KeyVisitor<'k, V>
+where
+    V: Visitor<'de>,
+{
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    record_visit! {
+        visit_bool(bool);
+        visit_i8(i8);
+        visit_i16(i16);
+        visit_i32(i32);
+        visit_i64(i64);
+        visit_i128(i128);
+        visit_u8(u8);
+        visit_u16(u16);
+        visit_u32(u32);
+        visit_u64(u64);
+        visit_u128(u128);
+        visit_f32(f32);
+        visit_f64(f64);
+        visit_char(char);
+        visit_str(&str);
+        visit_borrowed_str(&'de str);
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        *self.key = Some(v.clone());
+        self.inner.visit_string(v)
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        *self.key = Some(String::from_utf8_lossy(v).into_owned());
+        self.inner.visit_bytes(v)
+    }
+
+    fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        *self.key = Some(String::from_utf8_lossy(v).into_owned());
+        self.inner.visit_borrowed_bytes(v)
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        *self.key = Some(String::from_utf8_lossy(&v).into_owned());
+        self.inner.visit_byte_buf(v)
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.inner.visit_none()
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.inner.visit_some(deserializer)
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.inner.visit_unit()
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.inner.visit_newtype_struct(deserializer)
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        self.inner.visit_seq(seq)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.inner.visit_map(map)
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        self.inner.visit_enum(data)
+    }
+}
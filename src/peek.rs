@@ -0,0 +1,192 @@
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::PayloadError;
+use actix_web::guard::{self, Guard};
+use actix_web::web::Bytes;
+use actix_web::{Error as ActixError, HttpMessage};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::{stream, Stream, StreamExt};
+
+use crate::config::XmlConfig;
+use crate::error::XMLPayloadError;
+use crate::parse::peek_root;
+
+/// Maximum number of bytes read ahead to find the root element
+const PEEK_LIMIT: usize = 4096;
+
+/// Name of the root element of a request's XML payload, read ahead by
+/// [`XmlRootMiddleware`](struct.XmlRootMiddleware.html)
+///
+/// Found in the request extensions, e.g. by guards with
+/// `ctx.req_data().get::<XmlRoot>()`, or use [`XmlRoot::guard`](#method.guard).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlRoot(String);
+
+impl XmlRoot {
+    /// Local name of the root element, without namespace prefix
+    pub fn name(&self) -> &str {
+        self.0.rsplit(':').next().unwrap_or_default()
+    }
+
+    /// Name of the root element, including its namespace prefix if any (e.g. `soap:Envelope`)
+    pub fn qualified_name(&self) -> &str {
+        &self.0
+    }
+
+    /// Guard matching requests whose root element has the local name `name`
+    pub fn guard(name: &'static str) -> impl Guard {
+        guard::fn_guard(move |ctx| {
+            ctx.req_data()
+                .get::<XmlRoot>()
+                .is_some_and(|root| root.name() == name)
+        })
+    }
+}
+
+/// Middleware reading the root element of XML payloads ahead of routing
+///
+/// Guards run before the payload is read, so they can't look into it. This middleware reads the
+/// start of the payload of each request it wraps up to the root element, inserts its name into
+/// the request extensions as an [`XmlRoot`](struct.XmlRoot.html), and puts the bytes read back
+/// in front of the rest of the payload, which the extractors of the handler then read as usual.
+///
+/// Payloads are only read ahead if their content type is accepted by the
+/// [`XmlConfig`](struct.XmlConfig.html) of the request and they're not compressed. The root
+/// element must start within the first 4Kb of the payload, or its limit if lower, which is all
+/// that's read ahead. Requests whose root element isn't found go on without an `XmlRoot`, while
+/// those not read ahead within the [`read_timeout`](struct.XmlConfig.html#method.read_timeout)
+/// of the config fail like their extractor would.
+///
+/// The config is resolved when the middleware runs, before the request is routed, so only the
+/// configs of the app, of enclosing scopes, and set by
+/// [`XmlConfigMiddleware`](struct.XmlConfigMiddleware.html) apply; the app data of the resource
+/// or route the middleware wraps isn't visible yet.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_web::{web, App};
+/// use actix_xml::{Xml, XmlRoot, XmlRootMiddleware};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Order {
+///     id: u32,
+/// }
+///
+/// async fn order(order: Xml<Order>) -> String {
+///     format!("Order {}", order.id)
+/// }
+///
+/// async fn ping() -> &'static str {
+///     "pong"
+/// }
+///
+/// fn main() {
+///     let app = App::new().service(
+///         web::resource("/messages")
+///             .wrap(XmlRootMiddleware)
+///             .route(web::post().guard(XmlRoot::guard("Order")).to(order))
+///             .route(web::post().guard(XmlRoot::guard("Ping")).to(ping)),
+///     );
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XmlRootMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for XmlRootMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Transform = XmlRootService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(XmlRootService {
+            service: Rc::new(service),
+        })
+    }
+}
+
+/// Service created by [`XmlRootMiddleware`](struct.XmlRootMiddleware.html)
+pub struct XmlRootService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for XmlRootService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let peek = {
+            let config = XmlConfig::from_req(req.request());
+            let peek = config.check_content_type(req.request()).is_ok()
+                && !crate::parts::decompressed(req.request());
+            peek.then(|| {
+                let limit = config
+                    .limit
+                    .map_or(PEEK_LIMIT, |limit| limit.min(PEEK_LIMIT));
+                (limit, XmlConfig::clone(&config))
+            })
+        };
+
+        Box::pin(async move {
+            if let Some((limit, config)) = peek {
+                let mut payload = req.take_payload();
+                let mut chunks = Vec::new();
+                let mut buffer = Vec::new();
+                let mut root = None;
+                // The bytes read are kept as they came, errors included, to be read again
+                let read = async {
+                    while let Some(chunk) = payload.next().await {
+                        let failed = match &chunk {
+                            Ok(bytes) => {
+                                let bytes = &bytes[..bytes.len().min(limit - buffer.len())];
+                                buffer.extend_from_slice(bytes);
+                                // A start tag only ends with a `>`, so the prefix is only scanned
+                                // again once one arrives
+                                if bytes.contains(&b'>') {
+                                    root = peek_root(&buffer);
+                                }
+                                false
+                            }
+                            Err(_) => true,
+                        };
+                        chunks.push(chunk);
+                        if failed || root.is_some() || buffer.len() >= limit {
+                            break;
+                        }
+                    }
+                };
+                if let Some(timeout) = config.read_timeout {
+                    if actix_web::rt::time::timeout(timeout, read).await.is_err() {
+                        let e = XMLPayloadError::Timeout { timeout };
+                        return Err(config.reject(e, req.request()));
+                    }
+                } else {
+                    read.await;
+                }
+
+                let replay: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> =
+                    Box::pin(stream::iter(chunks).chain(payload));
+                req.set_payload(Payload::from(replay));
+                if let Some(root) = root {
+                    req.extensions_mut().insert(XmlRoot(root));
+                }
+            }
+            service.call(req).await
+        })
+    }
+}
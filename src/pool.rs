@@ -0,0 +1,144 @@
+use std::ops;
+use std::sync::{Arc, Mutex};
+
+use actix_web::web::BytesMut;
+
+/// Initial capacity of the buffers payloads are read into.
+pub(crate) const BUFFER_CAPACITY: usize = 8192;
+
+/// Pool of the buffers payloads are read into, reused across requests to save allocations.
+///
+/// Use [`XmlConfig::buffer_pool`](struct.XmlConfig.html#method.buffer_pool) to register one.
+/// A buffer is taken for each payload read, and put back once the payload is parsed, or as soon
+/// as reading it fails or is cancelled. [`BytesPool`](struct.BytesPool.html) is a simple
+/// implementation.
+///
+/// # Example
+///
+/// ```rust
+/// use std::cell::RefCell;
+///
+/// use actix_web::web::BytesMut;
+/// use actix_xml::{BufferPool, XmlConfig};
+///
+/// thread_local! {
+///     static BUFFERS: RefCell<Vec<BytesMut>> = RefCell::new(Vec::new());
+/// }
+///
+/// /// Pool keeping a buffer per worker thread
+/// struct ThreadLocalPool;
+///
+/// impl BufferPool for ThreadLocalPool {
+///     fn take(&self) -> BytesMut {
+///         BUFFERS
+///             .with(|buffers| buffers.borrow_mut().pop())
+///             .unwrap_or_else(|| BytesMut::with_capacity(8192))
+///     }
+///
+///     fn put(&self, buffer: BytesMut) {
+///         BUFFERS.with(|buffers| buffers.borrow_mut().push(buffer));
+///     }
+/// }
+///
+/// let config = XmlConfig::default().buffer_pool(ThreadLocalPool);
+/// ```
+pub trait BufferPool {
+    /// Take an empty buffer to read a payload into.
+    fn take(&self) -> BytesMut;
+
+    /// Give back a buffer taken from the pool, cleared but keeping its capacity.
+    fn put(&self, buffer: BytesMut);
+}
+
+impl<P> BufferPool for Arc<P>
+where
+    P: BufferPool + ?Sized,
+{
+    fn take(&self) -> BytesMut {
+        (**self).take()
+    }
+
+    fn put(&self, buffer: BytesMut) {
+        (**self).put(buffer)
+    }
+}
+
+/// [`BufferPool`](trait.BufferPool.html) keeping up to a fixed number of buffers
+///
+/// Buffers keep the capacity they grew to, so the memory held by the pool is bounded by the
+/// number of buffers times the payload limit.
+#[derive(Debug)]
+pub struct BytesPool {
+    buffers: Mutex<Vec<BytesMut>>,
+    max_buffers: usize,
+}
+
+impl BytesPool {
+    /// Create an empty pool keeping up to `max_buffers` buffers, e.g. one per concurrent
+    /// request
+    pub fn new(max_buffers: usize) -> Self {
+        BytesPool {
+            buffers: Mutex::new(Vec::new()),
+            max_buffers,
+        }
+    }
+}
+
+impl BufferPool for BytesPool {
+    fn take(&self) -> BytesMut {
+        self.buffers
+            .lock()
+            .ok()
+            .and_then(|mut buffers| buffers.pop())
+            .unwrap_or_else(|| BytesMut::with_capacity(BUFFER_CAPACITY))
+    }
+
+    fn put(&self, buffer: BytesMut) {
+        if let Ok(mut buffers) = self.buffers.lock() {
+            if buffers.len() < self.max_buffers {
+                buffers.push(buffer);
+            }
+        }
+    }
+}
+
+/// Buffer taken from the pool of a config, if any, which is put back when dropped.
+pub(crate) struct PooledBuffer {
+    buffer: BytesMut,
+    pool: Option<Arc<dyn BufferPool + Send + Sync>>,
+}
+
+impl PooledBuffer {
+    /// Take a buffer from `pool`, or allocate one without a pool
+    pub(crate) fn take(pool: Option<Arc<dyn BufferPool + Send + Sync>>) -> Self {
+        let buffer = match &pool {
+            Some(pool) => pool.take(),
+            None => BytesMut::with_capacity(BUFFER_CAPACITY),
+        };
+        PooledBuffer { buffer, pool }
+    }
+}
+
+impl ops::Deref for PooledBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &BytesMut {
+        &self.buffer
+    }
+}
+
+impl ops::DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut BytesMut {
+        &mut self.buffer
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let Some(pool) = &self.pool {
+            let mut buffer = std::mem::take(&mut self.buffer);
+            buffer.clear();
+            pool.put(buffer);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::{fmt, ops};
+
+use actix_web::dev;
+use actix_web::Error as ActixError;
+use actix_web::{FromRequest, HttpRequest};
+use serde::de::DeserializeOwned;
+
+use crate::config::XmlConfig;
+use crate::error::XMLPayloadError;
+use crate::extract::{extract_body, ExtractFuture};
+use crate::parse;
+
+/// Xml extractor that also yields the name of the root element
+///
+/// Useful for dispatching over documents of several kinds (e.g. SOAP envelopes) without
+/// deserializing into an enum first. The root element name is read before deserialization,
+/// and extraction works the same way as with [`Xml`](struct.Xml.html) otherwise.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::XmlWithRoot;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Envelope {
+///     body: String,
+/// }
+///
+/// async fn index(envelope: XmlWithRoot<Envelope>) -> String {
+///     format!("Received {}", envelope.root())
+/// }
+/// ```
+pub struct XmlWithRoot<T> {
+    root: String,
+    value: T,
+}
+
+impl<T> XmlWithRoot<T> {
+    /// Local name of the root element, without namespace prefix
+    pub fn root(&self) -> &str {
+        self.root.rsplit(':').next().unwrap_or_default()
+    }
+
+    /// Name of the root element, including its namespace prefix if any (e.g. `soap:Envelope`)
+    pub fn qualified_root(&self) -> &str {
+        &self.root
+    }
+
+    /// Deconstruct into the local name of the root element and the inner value
+    pub fn into_inner(self) -> (String, T) {
+        let root = self.root().to_string();
+        (root, self.value)
+    }
+}
+
+impl<T> ops::Deref for XmlWithRoot<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> ops::DerefMut for XmlWithRoot<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T> fmt::Debug for XmlWithRoot<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "XmlWithRoot({}): {:?}", self.root, self.value)
+    }
+}
+
+/// Deserialize a buffered payload along with the name of its root element, and its size.
+fn parse_with_root<T>(
+    body: &[u8],
+    charset: Option<&str>,
+    config: &XmlConfig,
+) -> Result<(usize, (String, T)), XMLPayloadError>
+where
+    T: DeserializeOwned,
+{
+    parse::parse_with_root(body, charset, config).map(|document| (body.len(), document))
+}
+
+impl<T> FromRequest for XmlWithRoot<T>
+where
+    T: DeserializeOwned + 'static,
+{
+    type Error = ActixError;
+    type Future = ExtractFuture<Self>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        extract_body(req, payload, parse_with_root::<T>, |(root, value)| {
+            XmlWithRoot { root, value }
+        })
+    }
+}
@@ -0,0 +1,450 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+
+/// Deserializer parsing numbers and booleans leniently, and forwarding everything else.
+///
+/// Numbers may be surrounded by whitespace, and booleans may also be spelled `yes`/`no`,
+/// `y`/`n` and `on`/`off`, in any case. Nested values are deserialized the same way.
+pub(crate) struct LenientScalars<D>(pub(crate) D);
+
+/// Parse `text` as a boolean, accepting common spellings.
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "y" | "on" => Some(true),
+        "false" | "0" | "no" | "n" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Deserialize a `bool` from any of its common spellings
+///
+/// Use it with `#[serde(deserialize_with = "de_bool_flexible")]` on `bool` fields read from
+/// attributes or text written by other tools, e.g. `active="1"` or `<active>Yes</active>`.
+/// `true`/`false`, `1`/`0`, `yes`/`no`, `y`/`n` and `on`/`off` are accepted in any case and
+/// surrounded by whitespace. To read all the booleans of a document this way, see
+/// [`XmlConfig::lenient_scalars`](struct.XmlConfig.html#method.lenient_scalars).
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::{de_bool_flexible, Xml};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct User {
+///     #[serde(deserialize_with = "de_bool_flexible")]
+///     active: bool,
+/// }
+///
+/// let user: Xml<User> = "<User active=\"Yes\"/>".parse().unwrap();
+/// assert!(user.active);
+/// ```
+pub fn de_bool_flexible<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = deserializer.deserialize_string(TextVisitor)?;
+    parse_bool(&text).ok_or_else(|| de::Error::custom(format_args!("invalid boolean `{}`", text)))
+}
+
+/// Deserialize a number written with digit grouping or a decimal comma
+///
+/// Use it with `#[serde(deserialize_with = "de_number_locale")]` on fields of any type parsed
+/// with `FromStr`, like `f64` or `u32`, read from values such as `price="1,234.56"` or
+/// `price="1.234,56"`. Spaces, no-break spaces, apostrophes and underscores are dropped as
+/// group separators, then:
+///
+/// * if both `,` and `.` appear, the last one is the decimal separator and the other one is
+///   dropped, e.g. `1,234.56` and `1.234,56` both read `1234.56`
+/// * if either appears several times, it's dropped as a group separator, e.g. `1.234.567`
+/// * a single `,` is a group separator if exactly three digits follow it, e.g. `1,234` reads
+///   `1234`, and a decimal separator otherwise, e.g. `12,5` reads `12.5`
+/// * a single `.` is always a decimal separator
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::{de_number_locale, Xml};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Item {
+///     #[serde(deserialize_with = "de_number_locale")]
+///     price: f64,
+/// }
+///
+/// let item: Xml<Item> = "<Item price=\"1,234.56\"/>".parse().unwrap();
+/// assert_eq!(item.price, 1234.56);
+/// ```
+pub fn de_number_locale<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let text = deserializer.deserialize_string(TextVisitor)?;
+    normalize_number(&text)
+        .parse()
+        .map_err(|e| de::Error::custom(format_args!("invalid number `{}`: {}", text, e)))
+}
+
+/// Rewrite a number with digit grouping or a decimal comma the way `FromStr` reads it, see
+/// [`de_number_locale`].
+fn normalize_number(text: &str) -> String {
+    let digits: String = text
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '\u{a0}' | '\u{202f}' | '\'' | '_'))
+        .collect();
+    let decimal = match (digits.rfind(','), digits.rfind('.')) {
+        (Some(comma), Some(dot)) => Some(comma.max(dot)),
+        (Some(comma), None) if digits.matches(',').count() == 1 => {
+            let fraction = &digits[comma + 1..];
+            let grouping = fraction.len() == 3 && fraction.bytes().all(|b| b.is_ascii_digit());
+            (!grouping).then_some(comma)
+        }
+        (None, Some(dot)) if digits.matches('.').count() == 1 => Some(dot),
+        _ => None,
+    };
+    digits
+        .char_indices()
+        .filter_map(|(i, c)| match c {
+            ',' | '.' if Some(i) == decimal => Some('.'),
+            ',' | '.' => None,
+            c => Some(c),
+        })
+        .collect()
+}
+
+macro_rules! lenient_numbers {
+    ($($method:ident => $ty:ty, $visit:ident;)*) => {$(
+        fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: Visitor<'de>,
+        {
+            let text = self.0.deserialize_string(TextVisitor)?;
+            match text.trim().parse::<$ty>() {
+                Ok(value) => visitor.$visit(value),
+                Err(e) => Err(de::Error::custom(format_args!("invalid number `{}`: {}", text, e))),
+            }
+        }
+    )*};
+}
+
+macro_rules! forward {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: Visitor<'de>,
+        {
+            self.0.$method($($arg,)* Wrap(visitor))
+        }
+    )*};
+}
+
+impl<'de, D> Deserializer<'de> for LenientScalars<D>
+where
+    D: Deserializer<'de>,
+{
+    type Error = D::Error;
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let text = self.0.deserialize_string(TextVisitor)?;
+        match parse_bool(&text) {
+            Some(value) => visitor.visit_bool(value),
+            None => Err(de::Error::custom(format_args!(
+                "invalid boolean `{}`",
+                text
+            ))),
+        }
+    }
+
+    lenient_numbers! {
+        deserialize_i8 => i8, visit_i8;
+        deserialize_i16 => i16, visit_i16;
+        deserialize_i32 => i32, visit_i32;
+        deserialize_i64 => i64, visit_i64;
+        deserialize_i128 => i128, visit_i128;
+        deserialize_u8 => u8, visit_u8;
+        deserialize_u16 => u16, visit_u16;
+        deserialize_u32 => u32, visit_u32;
+        deserialize_u64 => u64, visit_u64;
+        deserialize_u128 => u128, visit_u128;
+        deserialize_f32 => f32, visit_f32;
+        deserialize_f64 => f64, visit_f64;
+    }
+
+    forward! {
+        deserialize_any();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+/// Visitor reading a scalar as text.
+struct TextVisitor;
+
+impl<'de> Visitor<'de> for TextVisitor {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("text")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+        Ok(v)
+    }
+}
+
+/// Visitor handing nested deserializers to `V` wrapped in [`LenientScalars`].
+struct Wrap<V>(V);
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty);)*) => {$(
+        fn $method<E>(self, v: $ty) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            self.0.$method(v)
+        }
+    )*};
+}
+
+impl<'de, V> Visitor<'de> for Wrap<V>
+where
+    V: Visitor<'de>,
+{
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool);
+        visit_i8(i8);
+        visit_i16(i16);
+        visit_i32(i32);
+        visit_i64(i64);
+        visit_i128(i128);
+        visit_u8(u8);
+        visit_u16(u16);
+        visit_u32(u32);
+        visit_u64(u64);
+        visit_u128(u128);
+        visit_f32(f32);
+        visit_f64(f64);
+        visit_char(char);
+        visit_str(&str);
+        visit_borrowed_str(&'de str);
+        visit_string(String);
+        visit_bytes(&[u8]);
+        visit_borrowed_bytes(&'de [u8]);
+        visit_byte_buf(Vec<u8>);
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.0.visit_none()
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0.visit_some(LenientScalars(deserializer))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.0.visit_unit()
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0.visit_newtype_struct(LenientScalars(deserializer))
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        self.0.visit_seq(Wrap(seq))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.0.visit_map(Wrap(map))
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        self.0.visit_enum(Wrap(data))
+    }
+}
+
+impl<'de, S> DeserializeSeed<'de> for Wrap<S>
+where
+    S: DeserializeSeed<'de>,
+{
+    type Value = S::Value;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0.deserialize(LenientScalars(deserializer))
+    }
+}
+
+impl<'de, A> SeqAccess<'de> for Wrap<A>
+where
+    A: SeqAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.0.next_element_seed(Wrap(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+impl<'de, A> MapAccess<'de> for Wrap<A>
+where
+    A: MapAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        self.0.next_key_seed(seed)
+    }
+
+    fn next_value_seed<T>(&mut self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.0.next_value_seed(Wrap(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+impl<'de, A> EnumAccess<'de> for Wrap<A>
+where
+    A: EnumAccess<'de>,
+{
+    type Error = A::Error;
+    type Variant = Wrap<A::Variant>;
+
+    fn variant_seed<T>(self, seed: T) -> Result<(T::Value, Self::Variant), Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.0
+            .variant_seed(seed)
+            .map(|(value, variant)| (value, Wrap(variant)))
+    }
+}
+
+impl<'de, A> VariantAccess<'de> for Wrap<A>
+where
+    A: VariantAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.0.unit_variant()
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.0.newtype_variant_seed(Wrap(seed))
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.0.tuple_variant(len, Wrap(visitor))
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.0.struct_variant(fields, Wrap(visitor))
+    }
+}
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+/// Schema that documents are validated against before deserialization, e.g. a compiled XSD.
+///
+/// No schema language is bundled, so implement this trait on top of a validator of your
+/// choice. Compile the schema once at startup and share it between workers, e.g. in an
+/// `Arc`. Use [`XmlConfig::schema`](struct.XmlConfig.html#method.schema) to register one.
+///
+/// # Example
+///
+/// ```rust
+/// use actix_xml::{Schema, XmlConfig};
+///
+/// struct RequireBody;
+///
+/// impl Schema for RequireBody {
+///     fn validate(&self, document: &str) -> Result<(), String> {
+///         if document.contains("<body>") {
+///             Ok(())
+///         } else {
+///             Err("missing element `body`".to_string())
+///         }
+///     }
+/// }
+///
+/// let config = XmlConfig::default().schema(RequireBody);
+/// ```
+pub trait Schema {
+    /// Validate the decoded `document`, returning a description of the violation if it's
+    /// invalid.
+    fn validate(&self, document: &str) -> Result<(), String>;
+}
+
+impl<S> Schema for Arc<S>
+where
+    S: Schema + ?Sized,
+{
+    fn validate(&self, document: &str) -> Result<(), String> {
+        (**self).validate(document)
+    }
+}
@@ -0,0 +1,78 @@
+use std::{fmt, ops};
+
+use actix_web::dev;
+use actix_web::Error as ActixError;
+use actix_web::{FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use serde::de::DeserializeOwned;
+
+use crate::Xml;
+
+/// Xml extractor for a sequence of top-level elements
+///
+/// Parses payloads made of repeated records without a wrapping root element, e.g.
+/// `<Record/><Record/><Record/>`, into a `Vec<T>`. The payload limit applies to the whole
+/// payload, and extraction works the same way as with [`Xml`](struct.Xml.html) otherwise.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::XmlSeq;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Record {
+///     id: u32,
+/// }
+///
+/// async fn index(records: XmlSeq<Record>) -> String {
+///     format!("Received {} records", records.len())
+/// }
+/// ```
+pub struct XmlSeq<T>(pub Vec<T>);
+
+impl<T> XmlSeq<T> {
+    /// Deconstruct to the inner records
+    pub fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T> ops::Deref for XmlSeq<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T> ops::DerefMut for XmlSeq<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        &mut self.0
+    }
+}
+
+impl<T> fmt::Debug for XmlSeq<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "XmlSeq: {:?}", self.0)
+    }
+}
+
+impl<T> FromRequest for XmlSeq<T>
+where
+    T: DeserializeOwned + 'static,
+{
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self, ActixError>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        // A sequence deserializes from repeated top-level elements
+        Xml::<Vec<T>>::from_request(req, payload)
+            .map(|res| res.map(|Xml(records)| XmlSeq(records)))
+            .boxed_local()
+    }
+}
@@ -0,0 +1,345 @@
+use std::{fmt, io};
+
+use quick_xml::events::{BytesEnd, BytesStart, Event};
+use quick_xml::se::Serializer as XmlSerializer;
+use quick_xml::{DeError as XMLError, Writer};
+use serde::ser::{self, Impossible, Serialize, SerializeStruct, Serializer};
+
+/// Field names with a special meaning to the serializer of quick-xml.
+const INNER_VALUE: &str = "$value";
+const UNFLATTEN_PREFIX: &str = "$unflatten=";
+
+/// Serialize `value` into `out` like `quick_xml::se::to_writer`, except that the child elements
+/// of a root struct are written into `out` as they're serialized.
+///
+/// quick-xml holds the child elements of a struct until all its fields are serialized, so that
+/// the fields serialized as attributes are written first, which holds the whole document for a
+/// root struct. Its fields are probed first instead, stopping at the first byte of the child
+/// elements, so that the start tag is written before serializing them again into `out`.
+pub(crate) fn to_writer<T, W>(value: &T, mut out: W) -> Result<W, XMLError>
+where
+    T: Serialize + ?Sized,
+    W: io::Write,
+{
+    match value.serialize(Probe) {
+        Ok(fields) => value.serialize(Stream {
+            out: &mut out,
+            fields,
+        })?,
+        Err(ProbeError::NotStruct) => value.serialize(&mut XmlSerializer::new(&mut out))?,
+        Err(ProbeError::Xml(e)) => return Err(e),
+    }
+    Ok(out)
+}
+
+/// How a field of the root struct is serialized.
+enum Field {
+    /// Skipped, as it serializes to nothing
+    Skipped,
+    /// As an attribute, with its escaped value
+    Attribute(&'static str, Vec<u8>),
+    /// As child elements or text
+    Child,
+}
+
+#[derive(Debug)]
+enum ProbeError {
+    /// The value isn't a struct, so it's serialized by quick-xml as usual
+    NotStruct,
+    Xml(XMLError),
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::NotStruct => f.write_str("value isn't a struct"),
+            ProbeError::Xml(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl ser::Error for ProbeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ProbeError::Xml(XMLError::Custom(msg.to_string()))
+    }
+}
+
+/// Writer keeping what a field serializes to, until it turns out to be a child.
+struct Sniff {
+    buffer: Vec<u8>,
+    /// Whether the field is a child whatever it serializes to
+    child: bool,
+}
+
+impl io::Write for Sniff {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() && !buf.is_empty() && (self.child || buf[0] == b'<') {
+            self.child = true;
+            // Stop serializing the field, as the start of a child is enough to tell
+            return Err(io::Error::other("child element"));
+        }
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reject any value other than a struct in `Probe` and `Stream`.
+macro_rules! not_struct {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        fn $method(self, $(_: $ty),*) -> Result<Self::Ok, Self::Error> {
+            Err(self.not_struct())
+        }
+    )*};
+}
+
+macro_rules! not_struct_values {
+    () => {
+        not_struct! {
+            serialize_bool(v: bool);
+            serialize_i8(v: i8);
+            serialize_i16(v: i16);
+            serialize_i32(v: i32);
+            serialize_i64(v: i64);
+            serialize_u8(v: u8);
+            serialize_u16(v: u16);
+            serialize_u32(v: u32);
+            serialize_u64(v: u64);
+            serialize_f32(v: f32);
+            serialize_f64(v: f64);
+            serialize_char(v: char);
+            serialize_str(v: &str);
+            serialize_bytes(v: &[u8]);
+            serialize_none();
+            serialize_unit();
+            serialize_unit_struct(name: &'static str);
+            serialize_unit_variant(name: &'static str, index: u32, variant: &'static str);
+        }
+
+        fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<Self::Ok, Self::Error> {
+            Err(self.not_struct())
+        }
+
+        fn serialize_newtype_struct<T: ?Sized + Serialize>(
+            self,
+            _: &'static str,
+            _: &T,
+        ) -> Result<Self::Ok, Self::Error> {
+            Err(self.not_struct())
+        }
+
+        fn serialize_newtype_variant<T: ?Sized + Serialize>(
+            self,
+            _: &'static str,
+            _: u32,
+            _: &'static str,
+            _: &T,
+        ) -> Result<Self::Ok, Self::Error> {
+            Err(self.not_struct())
+        }
+
+        fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+            Err(self.not_struct())
+        }
+
+        fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Self::Error> {
+            Err(self.not_struct())
+        }
+
+        fn serialize_tuple_struct(
+            self,
+            _: &'static str,
+            _: usize,
+        ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+            Err(self.not_struct())
+        }
+
+        fn serialize_tuple_variant(
+            self,
+            _: &'static str,
+            _: u32,
+            _: &'static str,
+            _: usize,
+        ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+            Err(self.not_struct())
+        }
+
+        fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+            Err(self.not_struct())
+        }
+
+        fn serialize_struct_variant(
+            self,
+            _: &'static str,
+            _: u32,
+            _: &'static str,
+            _: usize,
+        ) -> Result<Self::SerializeStructVariant, Self::Error> {
+            Err(self.not_struct())
+        }
+    };
+}
+
+/// Serializer telling how the fields of a root struct are serialized.
+struct Probe;
+
+impl Probe {
+    fn not_struct(self) -> ProbeError {
+        ProbeError::NotStruct
+    }
+}
+
+impl Serializer for Probe {
+    type Ok = Vec<Field>;
+    type Error = ProbeError;
+    type SerializeSeq = Impossible<Self::Ok, Self::Error>;
+    type SerializeTuple = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeMap = Impossible<Self::Ok, Self::Error>;
+    type SerializeStruct = ProbeStruct;
+    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+    not_struct_values!();
+
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(ProbeStruct(Vec::with_capacity(len)))
+    }
+}
+
+struct ProbeStruct(Vec<Field>);
+
+impl SerializeStruct for ProbeStruct {
+    type Ok = Vec<Field>;
+    type Error = ProbeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        let mut sniff = Sniff {
+            buffer: Vec::new(),
+            child: key == INNER_VALUE || key.starts_with(UNFLATTEN_PREFIX),
+        };
+        let res = match key.strip_prefix(UNFLATTEN_PREFIX) {
+            Some(key) => XmlSerializer::with_root(Writer::new(&mut sniff), Some(key))
+                .serialize_newtype_struct(key, value),
+            None => value.serialize(&mut XmlSerializer::with_root(
+                Writer::new(&mut sniff),
+                Some(key),
+            )),
+        };
+        self.0.push(if sniff.child {
+            Field::Child
+        } else {
+            res.map_err(ProbeError::Xml)?;
+            match sniff.buffer.is_empty() {
+                true => Field::Skipped,
+                false => Field::Attribute(key, sniff.buffer),
+            }
+        });
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.0)
+    }
+}
+
+/// Serializer writing a root struct into `out`, its fields being serialized as `fields` tell.
+struct Stream<'a, W> {
+    out: &'a mut W,
+    fields: Vec<Field>,
+}
+
+impl<W> Stream<'_, W> {
+    fn not_struct(self) -> XMLError {
+        XMLError::Custom("value changed between serializations".to_string())
+    }
+}
+
+impl<'a, W: io::Write> Serializer for Stream<'a, W> {
+    type Ok = ();
+    type Error = XMLError;
+    type SerializeSeq = Impossible<(), XMLError>;
+    type SerializeTuple = Impossible<(), XMLError>;
+    type SerializeTupleStruct = Impossible<(), XMLError>;
+    type SerializeTupleVariant = Impossible<(), XMLError>;
+    type SerializeMap = Impossible<(), XMLError>;
+    type SerializeStruct = StreamStruct<'a, W>;
+    type SerializeStructVariant = Impossible<(), XMLError>;
+
+    not_struct_values!();
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        let mut start = BytesStart::new(name);
+        for field in &self.fields {
+            if let Field::Attribute(key, value) = field {
+                start.push_attribute((key.as_bytes(), value.as_slice()));
+            }
+        }
+        let children = self
+            .fields
+            .iter()
+            .any(|field| matches!(field, Field::Child));
+        let mut writer = Writer::new(&mut *self.out);
+        match children {
+            true => writer.write_event(Event::Start(start))?,
+            false => writer.write_event(Event::Empty(start))?,
+        }
+        Ok(StreamStruct {
+            out: self.out,
+            fields: self.fields.into_iter(),
+            end: children.then(|| BytesEnd::new(name)),
+        })
+    }
+}
+
+struct StreamStruct<'a, W> {
+    out: &'a mut W,
+    fields: std::vec::IntoIter<Field>,
+    /// End tag to write once the children are, if any
+    end: Option<BytesEnd<'static>>,
+}
+
+impl<W: io::Write> SerializeStruct for StreamStruct<'_, W> {
+    type Ok = ();
+    type Error = XMLError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        if let Some(Field::Child) = self.fields.next() {
+            let writer = Writer::new(&mut *self.out);
+            match key.strip_prefix(UNFLATTEN_PREFIX) {
+                Some(key) => XmlSerializer::with_root(writer, Some(key))
+                    .serialize_newtype_struct(key, value)?,
+                None => value.serialize(&mut XmlSerializer::with_root(writer, Some(key)))?,
+            }
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        if let Some(end) = self.end {
+            Writer::new(self.out).write_event(Event::End(end))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use crate::error::XMLPayloadError;
+
+/// Statistics of a buffered extraction, passed to the callback registered with
+/// [`XmlConfig::on_complete`](struct.XmlConfig.html#method.on_complete).
+///
+/// # Example
+///
+/// ```rust
+/// use actix_xml::{Outcome, XmlConfig, XmlStats};
+///
+/// let config = XmlConfig::default().on_complete(|stats: &XmlStats| {
+///     if stats.outcome == Outcome::Ok {
+///         println!("parsed {} bytes in {:?}", stats.bytes, stats.parse_duration);
+///     }
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XmlStats {
+    /// Size of the payload in bytes, as read from the stream before decompression by this
+    /// crate, or as declared by the content-length header if it was rejected before reading
+    pub bytes: usize,
+    /// Time spent decoding, checking and deserializing the payload once read, zero if it wasn't
+    /// reached
+    pub parse_duration: Duration,
+    /// How the extraction ended
+    pub outcome: Outcome,
+}
+
+/// How an extraction ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// The payload was deserialized
+    Ok,
+    /// The payload exceeded the size limit
+    Overflow,
+    /// The content type was rejected
+    ContentType,
+    /// The content length header was missing
+    LengthRequired,
+    /// Reading the payload took longer than allowed
+    Timeout,
+    /// The payload was empty
+    Empty,
+    /// Reading the payload failed
+    Payload,
+    /// The payload was rejected by a check of the config or couldn't be deserialized
+    Deserialize,
+}
+
+impl Outcome {
+    pub(crate) fn from_error(error: Option<&XMLPayloadError>) -> Self {
+        match error {
+            None => Outcome::Ok,
+            Some(XMLPayloadError::Overflow { .. }) => Outcome::Overflow,
+            Some(XMLPayloadError::ContentType { .. }) => Outcome::ContentType,
+            Some(XMLPayloadError::LengthRequired) => Outcome::LengthRequired,
+            Some(XMLPayloadError::Timeout { .. }) => Outcome::Timeout,
+            Some(XMLPayloadError::Empty) => Outcome::Empty,
+            Some(XMLPayloadError::Payload(_)) => Outcome::Payload,
+            Some(_) => Outcome::Deserialize,
+        }
+    }
+
+    /// Snake case name of the outcome, e.g. `content_type`, suitable as a metric label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Overflow => "overflow",
+            Outcome::ContentType => "content_type",
+            Outcome::LengthRequired => "length_required",
+            Outcome::Timeout => "timeout",
+            Outcome::Empty => "empty",
+            Outcome::Payload => "payload",
+            Outcome::Deserialize => "deserialize",
+        }
+    }
+}
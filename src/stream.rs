@@ -0,0 +1,287 @@
+use std::io::{self, BufRead, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::{fmt, ops};
+
+use actix_web::dev;
+use actix_web::error::PayloadError;
+use actix_web::rt::task::JoinHandle;
+use actix_web::web::Bytes;
+use actix_web::Error as ActixError;
+use actix_web::{FromRequest, HttpRequest};
+use futures::channel::mpsc;
+use futures::{stream, SinkExt, Stream, StreamExt};
+use quick_xml::DeError as XMLError;
+use serde::de::DeserializeOwned;
+
+use crate::config::XmlConfig;
+use crate::error::XMLPayloadError;
+use crate::extract::{extract, ExtractFuture};
+use crate::stats::{Outcome, XmlStats};
+
+/// Number of payload chunks buffered between the payload and the parser
+const CHANNEL_CAPACITY: usize = 4;
+
+/// Time allowed to read a payload without a configured read timeout
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Number of payloads of the process being parsed while they're read
+static STREAM_PARSERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Streaming Xml extractor
+///
+/// Unlike [`Xml`](struct.Xml.html), which buffers the whole payload before deserializing it,
+/// `XmlStream` feeds payload chunks to the parser as they arrive, so only a few chunks are held
+/// in memory at a time. Parsing happens on the blocking thread pool of the runtime, hence `T`
+/// must be `Send`. As the parser holds its thread while waiting for chunks, payloads must be
+/// read within 60 seconds unless a [`read_timeout`](struct.XmlConfig.html#method.read_timeout)
+/// is set, and only [`stream_parsers`](struct.XmlConfig.html#method.stream_parsers) payloads
+/// are parsed while they're read, others being read in full first.
+///
+/// The [`stream_limit`](struct.XmlConfig.html#method.stream_limit) of
+/// [`XmlConfig`](struct.XmlConfig.html) is enforced on the cumulative size of the chunks, while
+/// the content type and content length of the request are checked before reading it, like for
+/// `Xml`. The [`read_timeout`](struct.XmlConfig.html#method.read_timeout) and
+/// [`on_complete`](struct.XmlConfig.html#method.on_complete) callback apply too, the parse
+/// duration being the time spent waiting for the parser once the payload was read. Other
+/// checks of the config requiring the whole payload (e.g.
+/// [`max_depth`](struct.XmlConfig.html#method.max_depth)) are not applied, and the payload
+/// must be utf-8 unless the `encoding` feature is enabled.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::XmlStream;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Upload {
+///     item: Vec<String>,
+/// }
+///
+/// async fn index(upload: XmlStream<Upload>) -> String {
+///     format!("Received {} items", upload.item.len())
+/// }
+/// ```
+pub struct XmlStream<T>(pub T);
+
+impl<T> XmlStream<T> {
+    /// Deconstruct to an inner value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> ops::Deref for XmlStream<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> ops::DerefMut for XmlStream<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> fmt::Debug for XmlStream<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "XmlStream: {:?}", self.0)
+    }
+}
+
+impl<T> FromRequest for XmlStream<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    type Error = ActixError;
+    type Future = ExtractFuture<Self>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        extract(req, |config| {
+            config.check_headers(req)?;
+
+            #[cfg(feature = "__compress")]
+            let (payload, decompressed) = (
+                dev::Decompress::from_headers(payload.take(), req.headers()),
+                crate::parts::decompressed(req),
+            );
+            #[cfg(not(feature = "__compress"))]
+            let (payload, decompressed) = (payload.take(), false);
+
+            let config = config.clone();
+            Ok(async move {
+                let (bytes, value) = deserialize_stream(payload, decompressed, &config).await?;
+                Ok((bytes, XmlStream(value)))
+            })
+        })
+    }
+}
+
+/// Deserialize a `T` from `stream`, parsing chunks on a blocking thread as they arrive, and
+/// return it along with the size of the payload.
+///
+/// `decompressed` tells whether the chunks of `stream` are decompressed, for overflow errors.
+/// The stream limit and read timeout of `config` apply, and the extraction is reported to its
+/// `on_complete` callback, with the time spent waiting for the parser once the stream ended.
+async fn deserialize_stream<S, T>(
+    mut stream: S,
+    decompressed: bool,
+    config: &XmlConfig,
+) -> Result<(usize, T), XMLPayloadError>
+where
+    S: Stream<Item = Result<Bytes, PayloadError>> + Unpin,
+    T: DeserializeOwned + Send + 'static,
+{
+    // Without a free slot, the chunks are buffered and only handed to the parser once read
+    let mut tx = None;
+    let mut parser = ParserSlot::acquire(config.stream_parsers).map(|slot| {
+        let (sender, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        tx = Some(sender);
+        spawn_parser::<T, _>(rx, Some(slot))
+    });
+    let mut buffered = Vec::new();
+
+    let mut stats = XmlStats {
+        bytes: 0,
+        parse_duration: Duration::ZERO,
+        outcome: Outcome::Ok,
+    };
+    let res = async {
+        let limit = config.get_stream_limit();
+        let read = async {
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk?;
+                stats.bytes += chunk.len();
+                if let Some(limit) = limit.filter(|&limit| stats.bytes > limit) {
+                    // dropping the sender ends the input of the parser
+                    return Err(XMLPayloadError::Overflow {
+                        limit,
+                        length: Some(stats.bytes),
+                        decompressed,
+                    });
+                }
+                match &mut tx {
+                    Some(tx) => {
+                        if tx.send(chunk).await.is_err() {
+                            // the parser stopped early, e.g. on a syntax error
+                            break;
+                        }
+                    }
+                    None => buffered.push(chunk),
+                }
+            }
+            Ok(())
+        };
+        let timeout = config.read_timeout.unwrap_or(DEFAULT_READ_TIMEOUT);
+        actix_web::rt::time::timeout(timeout, read)
+            .await
+            .unwrap_or(Err(XMLPayloadError::Timeout { timeout }))?;
+        drop(tx);
+
+        let start = Instant::now();
+        let parser = match parser.take() {
+            Some(parser) => parser,
+            None => spawn_parser::<T, _>(stream::iter(std::mem::take(&mut buffered)), None),
+        };
+        let res = match parser.await {
+            Ok(res) => Ok(res?),
+            Err(e) => match e.try_into_panic() {
+                Ok(panic) => std::panic::resume_unwind(panic),
+                // the runtime is shutting down
+                Err(_) => Err(XMLPayloadError::Payload(PayloadError::Io(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "XML parser was cancelled",
+                )))),
+            },
+        };
+        stats.parse_duration = start.elapsed();
+        res.map(|value| (stats.bytes, value))
+    }
+    .await;
+
+    if let Some(on_complete) = &config.on_complete {
+        stats.outcome = Outcome::from_error(res.as_ref().err());
+        on_complete(&stats);
+    }
+    res
+}
+
+/// Parse a `T` from `chunks` on a blocking thread, which holds `slot` until it's done.
+fn spawn_parser<T, S>(chunks: S, slot: Option<ParserSlot>) -> JoinHandle<Result<T, XMLError>>
+where
+    T: DeserializeOwned + Send + 'static,
+    S: Stream<Item = Bytes> + Send + Unpin + 'static,
+{
+    actix_web::rt::task::spawn_blocking(move || {
+        let _slot = slot;
+        quick_xml::de::from_reader::<_, T>(ChannelReader {
+            rx: chunks,
+            chunk: Bytes::new(),
+        })
+    })
+}
+
+/// Slot among the [`stream_parsers`](struct.XmlConfig.html#method.stream_parsers) of the
+/// process, freed when dropped.
+struct ParserSlot;
+
+impl ParserSlot {
+    fn acquire(max: usize) -> Option<Self> {
+        STREAM_PARSERS
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |parsers| {
+                (parsers < max).then_some(parsers + 1)
+            })
+            .ok()
+            .map(|_| ParserSlot)
+    }
+}
+
+impl Drop for ParserSlot {
+    fn drop(&mut self) {
+        STREAM_PARSERS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Blocking reader over payload chunks, e.g. received from a channel.
+struct ChannelReader<S> {
+    rx: S,
+    chunk: Bytes,
+}
+
+impl<S> Read for ChannelReader<S>
+where
+    S: Stream<Item = Bytes> + Unpin,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<S> BufRead for ChannelReader<S>
+where
+    S: Stream<Item = Bytes> + Unpin,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.chunk.is_empty() {
+            match futures::executor::block_on(self.rx.next()) {
+                Some(chunk) => self.chunk = chunk,
+                None => break,
+            }
+        }
+        Ok(&self.chunk)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.chunk = self.chunk.slice(amt..);
+    }
+}
@@ -0,0 +1,34 @@
+//! Helpers for testing handlers extracting XML payloads.
+
+use actix_web::http::header;
+use actix_web::test::TestRequest;
+use actix_web::web::Bytes;
+
+/// Build a `TestRequest` with `body` as payload, an `application/xml` content type and the
+/// matching content length.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_web::FromRequest;
+/// use actix_xml::test_util::xml_test_request;
+/// use actix_xml::Xml;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Info {
+///     username: String,
+/// }
+///
+/// # actix_web::rt::System::new().block_on(async {
+/// let (req, mut payload) = xml_test_request(b"<Info username=\"user\" />").to_http_parts();
+/// let info = Xml::<Info>::from_request(&req, &mut payload).await.unwrap();
+/// assert_eq!(info.username, "user");
+/// # });
+/// ```
+pub fn xml_test_request(body: &[u8]) -> TestRequest {
+    TestRequest::default()
+        .insert_header((header::CONTENT_TYPE, "application/xml"))
+        .insert_header((header::CONTENT_LENGTH, body.len()))
+        .set_payload(Bytes::copy_from_slice(body))
+}
@@ -1,23 +1,58 @@
-use actix_web::http::header;
-use actix_web::test::TestRequest;
-use actix_web::web::Bytes;
-use actix_web::{web, FromRequest};
-use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::body::to_bytes;
+use actix_web::error::{InternalError, PayloadError};
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::test::{call_service, init_service, try_call_service, TestRequest};
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{
+    web, App, FromRequest, HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use futures::future::LocalBoxFuture;
+use futures::{FutureExt, StreamExt};
+use serde::de::{DeserializeSeed, Deserializer};
+use serde::{Deserialize, Serialize};
 
 use crate::error::XMLPayloadError;
-use crate::{Xml, XmlBody, XmlConfig};
+use crate::test_util::xml_test_request;
+use crate::{
+    de_bool_flexible, de_number_locale, keyed_by_attr, parse_xml, BufferPool, BytesPool, Cdata,
+    OptionalXml, Outcome, Schema, ValidatedXml, Xml, XmlBody, XmlBuf, XmlConfig,
+    XmlConfigMiddleware, XmlErrorKind, XmlExtra, XmlMeta, XmlPart, XmlRoot, XmlRootMiddleware,
+    XmlSeq, XmlStats, XmlStream, XmlValidator, XmlWarning, XmlWithRoot,
+};
 
-#[derive(Deserialize, Eq, PartialEq, Debug)]
+#[derive(Deserialize, Serialize, Clone, Eq, PartialEq, Hash, Debug)]
 struct MyObject {
     name: String,
 }
 
+struct Unserializable;
+
+impl Serialize for Unserializable {
+    fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom("unserializable"))
+    }
+}
+
 fn xml_eq(err: XMLPayloadError, other: XMLPayloadError) -> bool {
     match err {
-        XMLPayloadError::Overflow => matches!(other, XMLPayloadError::Overflow),
-        XMLPayloadError::ContentType => {
-            matches!(other, XMLPayloadError::ContentType)
-        }
+        XMLPayloadError::Overflow {
+            limit,
+            length,
+            decompressed,
+        } => matches!(
+            other,
+            XMLPayloadError::Overflow { limit: l, length: n, decompressed: d }
+                if l == limit && n == length && d == decompressed
+        ),
+        XMLPayloadError::ContentType { received } => matches!(
+            other,
+            XMLPayloadError::ContentType { received: r } if r == received
+        ),
         _ => false,
     }
 }
@@ -59,7 +94,7 @@ async fn test_extract() {
         .to_http_parts();
 
     let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
-    assert!(format!("{}", s.err().unwrap()).contains("Xml payload size is bigger than allowed"));
+    assert!(format!("{}", s.err().unwrap()).contains("Xml payload size 25 exceeds limit 10"));
 }
 
 #[actix_rt::test]
@@ -76,7 +111,14 @@ async fn test_xml_body() {
         .to_http_parts();
 
     let xml = XmlBody::<MyObject>::new(&req, &mut pl).limit(100).await;
-    assert!(xml_eq(xml.err().unwrap(), XMLPayloadError::Overflow));
+    assert!(xml_eq(
+        xml.err().unwrap(),
+        XMLPayloadError::Overflow {
+            limit: 100,
+            length: Some(10000),
+            decompressed: false,
+        }
+    ));
 
     let (req, mut pl) = TestRequest::default()
         .insert_header((
@@ -115,7 +157,12 @@ async fn test_with_xml_and_bad_content_type() {
         .to_http_parts();
 
     let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
-    assert!(s.is_err())
+    let err = s.err().unwrap();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::ContentType { received: Some(received) }) if received == &mime::TEXT_PLAIN
+    ));
+    assert_eq!(err.to_string(), "Unsupported content type: text/plain");
 }
 
 #[actix_rt::test]
@@ -179,5 +226,4149 @@ async fn test_with_config_in_data_wrapper() {
     assert!(s.is_err());
 
     let err_str = s.err().unwrap().to_string();
-    assert!(err_str.contains("Xml payload size is bigger than allowed"));
+    assert!(err_str.contains("Xml payload size 25 exceeds limit 10"));
+}
+
+#[actix_rt::test]
+async fn test_responder() {
+    let req = TestRequest::default().to_http_request();
+    let resp = Xml(MyObject {
+        name: "test".to_string(),
+    })
+    .respond_to(&req);
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        header::HeaderValue::from_static("application/xml")
+    );
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    assert_eq!(body, Bytes::from_static(b"<MyObject name=\"test\"/>"));
+}
+
+#[actix_rt::test]
+async fn test_responder_round_trip() {
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .insert_header((
+            header::CONTENT_LENGTH,
+            header::HeaderValue::from_static("25"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    let body = to_bytes(s.respond_to(&req).into_body()).await.unwrap();
+
+    let (req, mut pl) = xml_test_request(&body).to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(
+        s.into_inner(),
+        MyObject {
+            name: "test".to_string()
+        }
+    );
+}
+
+#[actix_rt::test]
+async fn test_responder_serialize_error() {
+    let req = TestRequest::default().to_http_request();
+    let resp = Xml(Unserializable).respond_to(&req);
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[test]
+fn test_to_xml_string() {
+    let xml = Xml(MyObject {
+        name: "a < b".to_string(),
+    });
+    assert_eq!(
+        xml.to_xml_string().unwrap(),
+        r#"<MyObject name="a &lt; b"/>"#
+    );
+    assert_eq!(
+        xml.to_xml_string()
+            .unwrap()
+            .parse::<Xml<MyObject>>()
+            .unwrap(),
+        xml
+    );
+
+    let err = Xml(Unserializable).to_xml_string().unwrap_err();
+    assert_eq!(err.kind(), XmlErrorKind::Serialize);
+}
+
+#[actix_rt::test]
+async fn test_responder_content_type() {
+    let req = TestRequest::default()
+        .app_data(XmlConfig::default().response_content_type("text/xml"))
+        .to_http_request();
+    let resp = Xml(MyObject {
+        name: "test".to_string(),
+    })
+    .respond_to(&req);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        header::HeaderValue::from_static("text/xml")
+    );
+
+    let req = TestRequest::default()
+        .app_data(
+            XmlConfig::default()
+                .response_content_type("application/soap+xml".parse::<mime::Mime>().unwrap()),
+        )
+        .to_http_request();
+    let resp = Xml(MyObject {
+        name: "test".to_string(),
+    })
+    .respond_to(&req);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        header::HeaderValue::from_static("application/soap+xml")
+    );
+}
+
+#[derive(Deserialize, Serialize)]
+struct Nested {
+    inner: MyObject,
+}
+
+#[actix_rt::test]
+async fn test_responder_pretty() {
+    let value = || Nested {
+        inner: MyObject {
+            name: "test".to_string(),
+        },
+    };
+
+    let req = TestRequest::default().to_http_request();
+    let body = to_bytes(Xml(value()).respond_to(&req).into_body())
+        .await
+        .unwrap();
+    assert!(!body.contains(&b'\n'));
+
+    let req = TestRequest::default()
+        .app_data(XmlConfig::default().pretty(b' ', 4))
+        .to_http_request();
+    let body = to_bytes(Xml(value()).respond_to(&req).into_body())
+        .await
+        .unwrap();
+    assert_eq!(
+        body,
+        Bytes::from_static(b"<Nested>\n    <inner name=\"test\"/>\n</Nested>")
+    );
+}
+
+#[actix_rt::test]
+async fn test_responder_stream() {
+    use std::pin::Pin;
+
+    use actix_web::body::{BodySize, MessageBody};
+    use actix_web::web::BytesMut;
+
+    #[derive(Serialize)]
+    struct Export {
+        item: Vec<MyObject>,
+    }
+
+    let value = || Export {
+        item: (0..5000)
+            .map(|i| MyObject {
+                name: format!("item {}", i),
+            })
+            .collect(),
+    };
+
+    for config in [XmlConfig::default(), XmlConfig::default().pretty(b' ', 2)] {
+        let req = TestRequest::default()
+            .app_data(config.clone())
+            .to_http_request();
+        let expected = to_bytes(Xml(value()).respond_to(&req).into_body())
+            .await
+            .unwrap();
+
+        let req = TestRequest::default()
+            .app_data(config.stream_response(true))
+            .to_http_request();
+        let resp = Xml(value()).respond_to(&req);
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            header::HeaderValue::from_static("application/xml")
+        );
+        assert_eq!(resp.body().size(), BodySize::Stream);
+
+        let mut body = resp.into_body();
+        let mut chunks = 0;
+        let mut streamed = BytesMut::new();
+        while let Some(chunk) =
+            futures::future::poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await
+        {
+            streamed.extend_from_slice(&chunk.unwrap());
+            chunks += 1;
+        }
+        assert!(chunks > 1);
+        assert!(expected.len() > 100_000);
+        assert_eq!(streamed.freeze(), expected);
+
+        // and so does `streaming`, whatever the config
+        let resp = Xml(value()).streaming().respond_to(&req);
+        assert_eq!(resp.body().size(), BodySize::Stream);
+        assert_eq!(to_bytes(resp.into_body()).await.unwrap(), expected);
+    }
+
+    let req = TestRequest::default()
+        .app_data(XmlConfig::default().stream_response(true))
+        .to_http_request();
+    let resp = Xml(Unserializable).respond_to(&req);
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    // `streaming` serializes the value while the body is sent, so errors abort it
+    let resp = Xml(Unserializable).streaming().respond_to(&req);
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(to_bytes(resp.into_body()).await.is_err());
+}
+
+#[actix_rt::test]
+async fn test_responder_not_send() {
+    use std::marker::PhantomData;
+
+    // responding doesn't require the value to be `Send` unless streamed with `Xml::streaming`
+    #[derive(Serialize)]
+    struct Local {
+        name: String,
+        #[serde(skip)]
+        _local: PhantomData<std::rc::Rc<()>>,
+    }
+
+    for config in [
+        XmlConfig::default(),
+        XmlConfig::default().stream_response(true),
+    ] {
+        let req = TestRequest::default().app_data(config).to_http_request();
+        let resp = Xml(Local {
+            name: "test".to_string(),
+            _local: PhantomData,
+        })
+        .respond_to(&req);
+        assert_eq!(
+            to_bytes(resp.into_body()).await.unwrap(),
+            "<Local name=\"test\"/>"
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn test_responder_stream_incremental() {
+    use std::pin::Pin;
+
+    use actix_web::body::MessageBody;
+
+    const ITEMS: usize = 100_000;
+
+    struct Counted {
+        serialized: Arc<AtomicUsize>,
+    }
+
+    impl Serialize for Counted {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.serialized.fetch_add(1, Ordering::SeqCst);
+            MyObject {
+                name: "test".to_string(),
+            }
+            .serialize(serializer)
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Export {
+        item: Vec<Counted>,
+    }
+
+    let serialized = Arc::new(AtomicUsize::new(0));
+    for config in [XmlConfig::default(), XmlConfig::default().pretty(b' ', 2)] {
+        serialized.store(0, Ordering::SeqCst);
+        let req = TestRequest::default().app_data(config).to_http_request();
+        let value = Export {
+            item: (0..ITEMS)
+                .map(|_| Counted {
+                    serialized: serialized.clone(),
+                })
+                .collect(),
+        };
+        let mut body = Xml(value).streaming().respond_to(&req).into_body();
+
+        let first = futures::future::poll_fn(|cx| Pin::new(&mut body).poll_next(cx))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!first.is_empty());
+        // the serializer waits for the body to be polled once a few chunks are buffered
+        actix_rt::time::sleep(Duration::from_millis(50)).await;
+        let ahead = serialized.load(Ordering::SeqCst);
+        assert!(ahead < ITEMS / 10, "{} items serialized", ahead);
+
+        let rest = to_bytes(body).await.unwrap();
+        assert!(rest.len() > first.len());
+        // the first item is serialized once more, to tell that the field is a child element
+        assert_eq!(serialized.load(Ordering::SeqCst), ITEMS + 1);
+    }
+}
+
+#[actix_rt::test]
+async fn test_xml_body_overflow_while_streaming() {
+    let (req, mut pl) = xml_test_request(b"<MyObject name=\"test\" />").to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).limit(10).await;
+    assert!(xml_eq(
+        xml.err().unwrap(),
+        XMLPayloadError::Overflow {
+            limit: 10,
+            length: Some(24),
+            decompressed: false,
+        }
+    ));
+}
+
+#[actix_rt::test]
+async fn test_xml_body_into_bytes() {
+    #[derive(Deserialize)]
+    struct Borrowed<'a> {
+        name: &'a str,
+    }
+
+    let request = || xml_test_request(b"<MyObject name=\"test\" />").to_http_parts();
+
+    let (req, mut pl) = request();
+    let bytes = XmlBody::<MyObject>::new(&req, &mut pl)
+        .into_bytes()
+        .await
+        .unwrap();
+    assert_eq!(bytes, Bytes::from_static(b"<MyObject name=\"test\" />"));
+    let text = std::str::from_utf8(&bytes).unwrap();
+    let mut deserializer = quick_xml::de::Deserializer::from_str(text);
+    let borrowed = Borrowed::deserialize(&mut deserializer).unwrap();
+    assert_eq!(borrowed.name, "test");
+
+    // the limit still applies
+    let (req, mut pl) = request();
+    let bytes = XmlBody::<MyObject>::new(&req, &mut pl)
+        .limit(10)
+        .into_bytes()
+        .await;
+    assert!(xml_eq(
+        bytes.err().unwrap(),
+        XMLPayloadError::Overflow {
+            limit: 10,
+            length: Some(24),
+            decompressed: false,
+        }
+    ));
+}
+
+#[actix_rt::test]
+async fn test_with_xml_suffixed_content_type() {
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/atom+xml"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(s.is_ok());
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(s.is_err());
+}
+
+#[actix_rt::test]
+async fn test_optional_xml() {
+    let (req, mut pl) = xml_test_request(b"<MyObject name=\"test\" />").to_http_parts();
+
+    let s = OptionalXml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert_eq!(
+        s.into_inner(),
+        Some(MyObject {
+            name: "test".to_string()
+        })
+    );
+}
+
+#[actix_rt::test]
+async fn test_optional_xml_empty_body() {
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .to_http_parts();
+
+    let s = OptionalXml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert!(s.is_none());
+
+    let (req, mut pl) = xml_test_request(b"  \n ").to_http_parts();
+
+    let s = OptionalXml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert!(s.is_none());
+}
+
+#[actix_rt::test]
+async fn test_optional_xml_bad_content_type() {
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("text/plain"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .to_http_parts();
+
+    let s = OptionalXml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert!(s.is_none());
+}
+
+#[actix_rt::test]
+async fn test_optional_xml_deserialize_error() {
+    let (req, mut pl) = xml_test_request(b"<MyObject />").to_http_parts();
+
+    let s = OptionalXml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(s.is_err());
+}
+
+#[actix_rt::test]
+async fn test_custom_error_handler() {
+    let (req, mut pl) = xml_test_request(b"<MyObject />")
+        .app_data(XmlConfig::default().error_handler(|err, _| {
+            let resp = match err {
+                XMLPayloadError::Deserialize { .. } => HttpResponse::UnprocessableEntity().finish(),
+                _ => HttpResponse::BadRequest().finish(),
+            };
+            InternalError::from_response(err, resp).into()
+        }))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    let resp = s.err().unwrap().error_response();
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_rt::test]
+async fn test_internal_subset() {
+    let body = r#"<?xml version="1.0"?>
+<!DOCTYPE MyObject [
+  <!ENTITY lol "lol">
+  <!ENTITY lol1 "&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;">
+  <!ENTITY lol2 "&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;">
+  <!ENTITY lol3 "&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;">
+]>
+<MyObject name="&lol3;" />"#;
+    let (req, mut pl) = xml_test_request(body.as_bytes()).to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).await;
+    assert!(matches!(
+        xml.err().unwrap(),
+        XMLPayloadError::EntityExpansionLimit
+    ));
+
+    let body = r#"<!DOCTYPE MyObject [ <!ELEMENT MyObject EMPTY> ]><MyObject name="test" />"#;
+    let (req, mut pl) = xml_test_request(body.as_bytes())
+        .app_data(XmlConfig::default().allow_internal_subset(true))
+        .to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).await;
+    assert_eq!(
+        xml.unwrap(),
+        MyObject {
+            name: "test".to_string()
+        }
+    );
+}
+
+#[actix_rt::test]
+async fn test_max_depth() {
+    let body = format!(
+        "<MyObject name=\"test\">{}{}</MyObject>",
+        "<a>".repeat(999),
+        "</a>".repeat(999)
+    );
+    let (req, mut pl) = xml_test_request(body.as_bytes())
+        .app_data(XmlConfig::default().max_depth(100))
+        .to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).await;
+    assert!(matches!(
+        xml.err().unwrap(),
+        XMLPayloadError::DepthLimitExceeded { limit: 100 }
+    ));
+
+    let (req, mut pl) = xml_test_request(body.as_bytes()).to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).await;
+    assert!(xml.is_ok());
+}
+
+#[actix_rt::test]
+async fn test_max_elements() {
+    let body = format!(
+        "<MyObject name=\"test\">{}</MyObject>",
+        "<a/>".repeat(100_000)
+    );
+    let (req, mut pl) = xml_test_request(body.as_bytes())
+        .app_data(XmlConfig::default().max_elements(10_000))
+        .to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).limit(1 << 20).await;
+    let err = xml.err().unwrap();
+    assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+    assert!(matches!(
+        err,
+        XMLPayloadError::ElementLimitExceeded { limit: 10_000 }
+    ));
+
+    let (req, mut pl) = xml_test_request(body.as_bytes()).to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).limit(1 << 20).await;
+    assert!(xml.is_ok());
+}
+
+#[actix_rt::test]
+async fn test_attribute_limits() {
+    let extract = |config: XmlConfig, body: String| {
+        let (req, mut pl) = xml_test_request(body.as_bytes())
+            .app_data(config)
+            .to_http_parts();
+        async move { XmlBody::<MyObject>::new(&req, &mut pl).limit(1 << 20).await }
+    };
+    let many = format!(
+        "<MyObject name=\"test\"><a {}/></MyObject>",
+        (0..1000)
+            .map(|i| format!("a{}=\"\"", i))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    let long = format!("<MyObject name=\"{}\" />", "x".repeat(100_000));
+
+    let err = extract(
+        XmlConfig::default().max_attributes_per_element(100),
+        many.clone(),
+    )
+    .await
+    .err()
+    .unwrap();
+    assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+    assert!(matches!(
+        err,
+        XMLPayloadError::AttributeLimitExceeded {
+            limit: 100,
+            value_length: false
+        }
+    ));
+
+    let err = extract(
+        XmlConfig::default().max_attribute_length(1024),
+        long.clone(),
+    )
+    .await
+    .err()
+    .unwrap();
+    assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+    assert!(matches!(
+        err,
+        XMLPayloadError::AttributeLimitExceeded {
+            limit: 1024,
+            value_length: true
+        }
+    ));
+
+    // both are unlimited by default, and documents within the limits pass
+    assert!(extract(XmlConfig::default(), many).await.is_ok());
+    assert!(extract(XmlConfig::default(), long).await.is_ok());
+    let config = XmlConfig::default()
+        .max_attributes_per_element(1)
+        .max_attribute_length(4);
+    let s = extract(config, "<MyObject name=\"test\" />".to_string()).await;
+    assert_eq!(s.unwrap().name, "test");
+}
+
+#[cfg(feature = "encoding")]
+#[actix_rt::test]
+async fn test_encoding_from_declaration() {
+    let mut body = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><MyObject name=\"".to_vec();
+    body.extend_from_slice(b"caf\xe9\" />");
+    let (req, mut pl) = xml_test_request(&body).to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).await;
+    assert_eq!(
+        xml.unwrap(),
+        MyObject {
+            name: "café".to_string()
+        }
+    );
+
+    // charset of the content type takes precedence
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml; charset=windows-1251"),
+        ))
+        .set_payload(body)
+        .to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).await;
+    assert_eq!(
+        xml.unwrap(),
+        MyObject {
+            name: "cafй".to_string()
+        }
+    );
+}
+
+#[cfg(feature = "encoding")]
+#[actix_rt::test]
+async fn test_utf16_charset() {
+    let body: Vec<u8> = "<?xml version=\"1.0\" encoding=\"UTF-16\"?><MyObject name=\"日本\" />"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml; charset=utf-16le"),
+        ))
+        .set_payload(body)
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "日本");
+}
+
+#[cfg(feature = "encoding")]
+#[actix_rt::test]
+async fn test_force_encoding() {
+    let (body, _, _) = encoding_rs::SHIFT_JIS
+        .encode("<?xml version=\"1.0\" encoding=\"UTF-8\"?><MyObject name=\"日本語テキスト\" />");
+    let body = body.into_owned();
+
+    // the partner claims utf-8 in both the content type and the declaration
+    let request = |config: XmlConfig| {
+        TestRequest::default()
+            .insert_header((
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/xml; charset=utf-8"),
+            ))
+            .set_payload(body.clone())
+            .app_data(config)
+            .to_http_parts()
+    };
+
+    let (req, mut pl) = request(XmlConfig::default().force_encoding(encoding_rs::SHIFT_JIS));
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "日本語テキスト");
+
+    let (req, mut pl) = request(XmlConfig::default());
+    assert!(Xml::<MyObject>::from_request(&req, &mut pl).await.is_err());
+}
+
+#[cfg(feature = "encoding")]
+#[actix_rt::test]
+async fn test_default_encoding() {
+    let request = |config: XmlConfig, content_type: &'static str, body: &'static [u8]| {
+        TestRequest::default()
+            .insert_header((
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static(content_type),
+            ))
+            .set_payload(Bytes::from_static(body))
+            .app_data(config)
+            .to_http_parts()
+    };
+    let config = || XmlConfig::default().default_encoding(encoding_rs::WINDOWS_1252);
+
+    // "café €" in windows-1252, with no charset anywhere
+    let body: &'static [u8] = b"<MyObject name=\"caf\xE9 \x80\" />";
+    let (req, mut pl) = request(config(), "application/xml", body);
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "café €");
+
+    let (req, mut pl) = request(XmlConfig::default(), "application/xml", body);
+    assert!(Xml::<MyObject>::from_request(&req, &mut pl).await.is_err());
+
+    // the charset of the content type and the declaration still come first
+    let body: &'static [u8] = "<MyObject name=\"café\" />".as_bytes();
+    let (req, mut pl) = request(config(), "application/xml; charset=utf-8", body);
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "café");
+
+    let body: &'static [u8] =
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?><MyObject name=\"café\" />".as_bytes();
+    let (req, mut pl) = request(config(), "application/xml", body);
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "café");
+}
+
+#[actix_rt::test]
+async fn test_utf8_bom() {
+    let (req, mut pl) = xml_test_request(b"\xEF\xBB\xBF<MyObject name=\"test\" />").to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+}
+
+#[cfg(feature = "encoding")]
+#[actix_rt::test]
+async fn test_utf16_bom() {
+    let mut body = b"\xFF\xFE".to_vec();
+    for unit in "<MyObject name=\"test\" />".encode_utf16() {
+        body.extend_from_slice(&unit.to_le_bytes());
+    }
+    let (req, mut pl) = xml_test_request(&body).to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+}
+
+#[actix_rt::test]
+async fn test_require_root() {
+    let (req, mut pl) = xml_test_request(b"<MyObject name=\"test\" />")
+        .app_data(XmlConfig::default().require_root("MyObject"))
+        .to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).await;
+    assert!(xml.is_ok());
+
+    let (req, mut pl) = xml_test_request(b"<MyObjectV2 name=\"test\" />")
+        .app_data(XmlConfig::default().require_root("MyObject"))
+        .to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).await;
+    match xml.err().unwrap() {
+        XMLPayloadError::UnexpectedRoot { expected, found } => {
+            assert_eq!(expected, "MyObject");
+            assert_eq!(found, "MyObjectV2");
+        }
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[actix_rt::test]
+async fn test_require_namespace() {
+    #[derive(Deserialize, Debug)]
+    struct Order {
+        id: u32,
+    }
+
+    let request = |body: &'static [u8]| {
+        xml_test_request(body)
+            .app_data(XmlConfig::default().require_namespace("urn:example:v2"))
+            .to_http_parts()
+    };
+
+    for body in [
+        &b"<Order xmlns=\"urn:example:v2\" id=\"1\"/>"[..],
+        b"<v2:Order xmlns:v2=\"urn:example:v2\" id=\"1\"/>",
+    ] {
+        let (req, mut pl) = request(body);
+        let s = Xml::<Order>::from_request(&req, &mut pl).await.unwrap();
+        assert_eq!(s.id, 1);
+    }
+
+    for (body, found) in [
+        (
+            &b"<Order xmlns=\"urn:example:v1\" id=\"1\"/>"[..],
+            Some("urn:example:v1"),
+        ),
+        (b"<Order id=\"1\"/>", None),
+        // the namespace must be bound to the root element itself
+        (
+            b"<Order id=\"1\"><v2:a xmlns:v2=\"urn:example:v2\"/></Order>",
+            None,
+        ),
+    ] {
+        let (req, mut pl) = request(body);
+        let err = Xml::<Order>::from_request(&req, &mut pl).await.unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        match err.as_error::<XMLPayloadError>() {
+            Some(XMLPayloadError::NamespaceMismatch {
+                expected,
+                found: actual,
+            }) => {
+                assert_eq!(expected, "urn:example:v2");
+                assert_eq!(actual.as_deref(), found);
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn test_xml_meta() {
+    async fn index(_: Xml<MyObject>, req: HttpRequest) -> String {
+        let meta = req.extensions().get::<XmlMeta>().copied();
+        meta.unwrap().byte_len().to_string()
+    }
+
+    let app =
+        init_service(App::new().service(web::resource("/").route(web::post().to(index)))).await;
+
+    let req = xml_test_request(b"<MyObject name=\"test\" />")
+        .method(Method::POST)
+        .uri("/")
+        .to_request();
+    let resp = call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(to_bytes(resp.into_body()).await.unwrap(), "24");
+}
+
+#[actix_rt::test]
+async fn test_extractors_share_extraction() {
+    async fn meta<E: FromRequest>(config: XmlConfig, body: &[u8]) -> Result<usize, StatusCode> {
+        let (req, mut pl) = xml_test_request(body).app_data(config).to_http_parts();
+        match E::from_request(&req, &mut pl).await {
+            Ok(_) => Ok(req.extensions().get::<XmlMeta>().unwrap().byte_len()),
+            Err(e) => Err(e.into().as_response_error().status_code()),
+        }
+    }
+
+    let config = XmlConfig::default()
+        .error_handler(|err, _| InternalError::new(err, StatusCode::IM_A_TEAPOT).into());
+    let body = b"<MyObject name=\"test\" />";
+    for res in [
+        meta::<XmlWithRoot<MyObject>>(config.clone(), body).await,
+        meta::<XmlBuf>(config.clone(), body).await,
+        meta::<XmlStream<MyObject>>(config.clone(), body).await,
+    ] {
+        assert_eq!(res, Ok(24));
+    }
+
+    // rejected headers and payloads both go through the error handler
+    for config in [config.clone().limit(10), config.clone().stream_limit(10)] {
+        for res in [
+            meta::<XmlWithRoot<MyObject>>(config.clone(), body).await,
+            meta::<XmlBuf>(config.clone(), body).await,
+            meta::<XmlStream<MyObject>>(config.clone(), body).await,
+        ] {
+            assert_eq!(res, Err(StatusCode::IM_A_TEAPOT));
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn test_app_level_config() {
+    async fn index(info: Xml<MyObject>) -> String {
+        info.into_inner().name
+    }
+
+    let app = init_service(
+        App::new()
+            .app_data(XmlConfig::with_limit(10))
+            .service(web::resource("/").route(web::post().to(index)))
+            .service(
+                web::resource("/own")
+                    .app_data(XmlConfig::default())
+                    .route(web::post().to(index)),
+            ),
+    )
+    .await;
+
+    let req = xml_test_request(b"<MyObject name=\"test\" />")
+        .method(Method::POST)
+        .uri("/")
+        .to_request();
+    let resp = call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let req = xml_test_request(b"<MyObject name=\"test\" />")
+        .method(Method::POST)
+        .uri("/own")
+        .to_request();
+    let resp = call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn test_config_middleware() {
+    async fn index(info: Xml<MyObject>) -> String {
+        info.into_inner().name
+    }
+
+    let app = init_service(
+        App::new()
+            .app_data(XmlConfig::default())
+            .service(
+                web::scope("/limited")
+                    .wrap(XmlConfigMiddleware::new(XmlConfig::with_limit(10)))
+                    .route("/", web::post().to(index))
+                    .service(
+                        web::scope("/nested")
+                            .wrap(XmlConfigMiddleware::new(XmlConfig::default()))
+                            .route("/", web::post().to(index)),
+                    ),
+            )
+            .route("/", web::post().to(index)),
+    )
+    .await;
+
+    let status = |uri: &'static str| {
+        let req = xml_test_request(b"<MyObject name=\"test\" />")
+            .method(Method::POST)
+            .uri(uri)
+            .to_request();
+        let app = &app;
+        async move { call_service(app, req).await.status() }
+    };
+
+    // the config of the scope applies over the app-level one, without per-resource config
+    assert_eq!(status("/limited/").await, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(status("/").await, StatusCode::OK);
+    // and can be overridden by a nested scope
+    assert_eq!(status("/limited/nested/").await, StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn test_root_middleware() {
+    async fn index(info: Xml<MyObject>) -> String {
+        info.into_inner().name
+    }
+
+    async fn ping(root: web::ReqData<XmlRoot>) -> String {
+        format!("pong {}", root.qualified_name())
+    }
+
+    let app = init_service(
+        App::new().service(
+            web::resource("/")
+                .wrap(XmlRootMiddleware)
+                .route(web::post().guard(XmlRoot::guard("MyObject")).to(index))
+                .route(web::post().guard(XmlRoot::guard("Ping")).to(ping)),
+        ),
+    )
+    .await;
+
+    let call = |content_type: &'static str, chunks: Vec<&'static [u8]>| {
+        let mut req = TestRequest::post()
+            .uri("/")
+            .insert_header((
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static(content_type),
+            ))
+            .to_request();
+        let stream = futures::stream::iter(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok::<_, PayloadError>(Bytes::from_static(chunk))),
+        );
+        *req.payload() = actix_web::dev::Payload::from(stream.boxed_local());
+        let app = &app;
+        async move {
+            let resp = call_service(app, req).await;
+            let status = resp.status();
+            (status, to_bytes(resp.into_body()).await.unwrap())
+        }
+    };
+
+    let (status, body) = call("application/xml", vec![b"<ns:Ping xmlns:ns=\"urn:a\"/>"]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "pong ns:Ping");
+
+    // the root element spans several chunks, and the handler reads them all again
+    let (status, body) = call(
+        "application/xml",
+        vec![
+            b"<?xml version=\"1.0\"?><My",
+            b"Object name=\"te",
+            b"st\" />",
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "test");
+
+    // the root element must start within the bytes read ahead, which are still all read again
+    let commented = |chunks: usize| {
+        let mut comment = vec![&b"<!--"[..]];
+        comment.extend(std::iter::repeat_n(&b"0123456789"[..], chunks));
+        comment.push(b"--><Ping/>");
+        comment
+    };
+    let (status, body) = call("application/xml", commented(300)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "pong Ping");
+    let (status, _) = call("application/xml", commented(500)).await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+
+    // payloads that aren't XML are left alone
+    let (status, _) = call("text/plain", vec![b"<Ping/>"]).await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[actix_rt::test]
+async fn test_root_middleware_read_timeout() {
+    async fn ping() -> &'static str {
+        "pong"
+    }
+
+    let app = init_service(
+        App::new()
+            .app_data(XmlConfig::default().read_timeout(Duration::from_millis(50)))
+            .service(
+                web::resource("/")
+                    .wrap(XmlRootMiddleware)
+                    .route(web::post().guard(XmlRoot::guard("Ping")).to(ping)),
+            ),
+    )
+    .await;
+
+    // the client sends the start of the payload, then stalls
+    let mut req = TestRequest::post()
+        .uri("/")
+        .insert_header((header::CONTENT_TYPE, "application/xml"))
+        .to_request();
+    let stalled =
+        futures::stream::once(async { Ok::<_, PayloadError>(Bytes::from_static(b"<Pi")) })
+            .chain(futures::stream::pending());
+    *req.payload() = actix_web::dev::Payload::from(stalled.boxed_local());
+
+    let err = try_call_service(&app, req).await.err().unwrap();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Timeout { .. })
+    ));
+    assert_eq!(
+        err.as_response_error().status_code(),
+        StatusCode::REQUEST_TIMEOUT
+    );
+}
+
+#[actix_rt::test]
+async fn test_config_in_extensions() {
+    let request = || {
+        xml_test_request(b"<MyObject name=\"test\" />")
+            .app_data(XmlConfig::default())
+            .to_http_parts()
+    };
+
+    let (req, mut pl) = request();
+    req.extensions_mut().insert(XmlConfig::with_limit(10));
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(matches!(
+        s.err().unwrap().as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Overflow { limit: 10, .. })
+    ));
+
+    // app data applies otherwise
+    let (req, mut pl) = request();
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+}
+
+#[test]
+fn test_error_status_code() {
+    assert_eq!(
+        XMLPayloadError::ContentType { received: None }
+            .error_response()
+            .status(),
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+    assert_eq!(
+        XMLPayloadError::Overflow {
+            limit: 10,
+            length: None,
+            decompressed: false,
+        }
+        .error_response()
+        .status(),
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+    assert_eq!(
+        XMLPayloadError::EntityExpansionLimit
+            .error_response()
+            .status(),
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[test]
+fn test_error_kind() {
+    use quick_xml::DeError;
+
+    let errors = vec![
+        (
+            XMLPayloadError::Overflow {
+                limit: 10,
+                length: None,
+                decompressed: false,
+            },
+            XmlErrorKind::Overflow,
+        ),
+        (XMLPayloadError::Empty, XmlErrorKind::Empty),
+        (
+            XMLPayloadError::ContentType { received: None },
+            XmlErrorKind::ContentType,
+        ),
+        (
+            XMLPayloadError::LengthRequired,
+            XmlErrorKind::LengthRequired,
+        ),
+        (XMLPayloadError::InvalidLength, XmlErrorKind::InvalidLength),
+        (
+            XMLPayloadError::Timeout {
+                timeout: Duration::from_secs(1),
+            },
+            XmlErrorKind::Timeout,
+        ),
+        (
+            XMLPayloadError::Deserialize {
+                source: DeError::Custom("missing field".to_string()),
+                position: None,
+                context: None,
+            },
+            XmlErrorKind::Deserialize,
+        ),
+        (
+            XMLPayloadError::NotWellFormed {
+                source: DeError::Custom("mismatched end tag".to_string()),
+                position: 0,
+            },
+            XmlErrorKind::NotWellFormed,
+        ),
+        (
+            XMLPayloadError::Incomplete {
+                source: DeError::UnexpectedEof,
+                position: None,
+            },
+            XmlErrorKind::Incomplete,
+        ),
+        (
+            XMLPayloadError::Serialize(DeError::Unsupported("map".into())),
+            XmlErrorKind::Serialize,
+        ),
+        (
+            XMLPayloadError::EntityExpansionLimit,
+            XmlErrorKind::EntityExpansionLimit,
+        ),
+        (
+            XMLPayloadError::DoctypeForbidden,
+            XmlErrorKind::DoctypeForbidden,
+        ),
+        (
+            XMLPayloadError::DepthLimitExceeded { limit: 1 },
+            XmlErrorKind::DepthLimitExceeded,
+        ),
+        (
+            XMLPayloadError::ElementLimitExceeded { limit: 1 },
+            XmlErrorKind::ElementLimitExceeded,
+        ),
+        (
+            XMLPayloadError::AttributeLimitExceeded {
+                limit: 1,
+                value_length: false,
+            },
+            XmlErrorKind::AttributeLimitExceeded,
+        ),
+        (XMLPayloadError::NotXml { found: '{' }, XmlErrorKind::NotXml),
+        (XMLPayloadError::NoXmlPart, XmlErrorKind::NoXmlPart),
+        (XMLPayloadError::NotAnElement, XmlErrorKind::NotAnElement),
+        (
+            XMLPayloadError::UnexpectedRoot {
+                expected: "a".to_string(),
+                found: "b".to_string(),
+            },
+            XmlErrorKind::UnexpectedRoot,
+        ),
+        (
+            XMLPayloadError::NamespaceMismatch {
+                expected: "urn:a".to_string(),
+                found: None,
+            },
+            XmlErrorKind::NamespaceMismatch,
+        ),
+        (
+            XMLPayloadError::SchemaValidation("invalid".to_string()),
+            XmlErrorKind::SchemaValidation,
+        ),
+        (
+            XMLPayloadError::Payload(PayloadError::Incomplete(None)),
+            XmlErrorKind::Payload,
+        ),
+    ];
+
+    let mut codes = std::collections::HashSet::new();
+    for (error, expected) in &errors {
+        let kind = error.kind();
+        let copy = kind;
+        #[allow(clippy::clone_on_copy)]
+        let clone = kind.clone();
+        assert_eq!((kind, copy, clone), (*expected, *expected, *expected));
+        assert!(codes.insert(kind.as_str()), "duplicate {}", kind.as_str());
+    }
+    assert_eq!(codes.len(), errors.len());
+    assert_eq!(XmlErrorKind::ContentType.as_str(), "content_type");
+}
+
+#[actix_rt::test]
+async fn test_deserialize_error_position() {
+    let (req, mut pl) = xml_test_request(b"<MyObject><a></b></MyObject>").to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).await;
+    let err = xml.err().unwrap();
+    assert!(matches!(
+        err,
+        XMLPayloadError::Deserialize {
+            position: Some(15),
+            ..
+        }
+    ));
+    assert!(err.to_string().ends_with("at byte 15"));
+
+    // truncated documents are located at their end
+    let (req, mut pl) = xml_test_request(b"<MyObject name=\"test\"><inner>").to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).await;
+    let err = xml.err().unwrap();
+    assert!(matches!(
+        err,
+        XMLPayloadError::Incomplete {
+            position: Some(29),
+            ..
+        }
+    ));
+    assert!(err.to_string().ends_with("at byte 29"));
+
+    // the position would point into the rewritten document, so it isn't reported
+    let err = parse_xml::<MyObject>(
+        b"<MyObject><![CDATA[x]]><a></b></MyObject>",
+        &XmlConfig::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        XMLPayloadError::Deserialize { position: None, .. }
+    ));
+}
+
+#[actix_rt::test]
+async fn test_check_well_formed() {
+    let config = XmlConfig::default().check_well_formed(true);
+
+    for (body, position) in [
+        (&b"<MyObject name=\"test\"><a></b></MyObject>"[..], 27),
+        (b"<MyObject name=\"test\" name=\"again\"/>", 36),
+        (b"<MyObject name=\"test\"><a>&bogus;</a></MyObject>", 32),
+    ] {
+        let err = parse_xml::<MyObject>(body, &config).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                XMLPayloadError::NotWellFormed { position: p, .. } if p == position
+            ),
+            "{:?}",
+            err
+        );
+        // without the check, the document deserializes or fails later
+        assert!(!matches!(
+            parse_xml::<MyObject>(body, &XmlConfig::default()),
+            Err(XMLPayloadError::NotWellFormed { .. })
+        ));
+    }
+
+    let err = parse_xml::<MyObject>(b"<MyObject name=\"test\"><a>", &config).unwrap_err();
+    assert!(matches!(
+        err,
+        XMLPayloadError::Incomplete {
+            position: Some(25),
+            ..
+        }
+    ));
+
+    // well-formed documents of the wrong shape fail to deserialize
+    let err = parse_xml::<MyObject>(b"<MyObject><a/></MyObject>", &config).unwrap_err();
+    assert!(matches!(err, XMLPayloadError::Deserialize { .. }));
+
+    // the document is checked before the config rewrites it, which would fail on its own
+    let mut entities = HashMap::new();
+    entities.insert("custom".to_string(), "test".to_string());
+    for rewriting in [
+        config.clone().trim_text(false),
+        config.clone().namespace("ns", "urn:test"),
+        config.clone().mixed_content(true),
+        config.clone().entity_resolver(entities.clone()),
+    ] {
+        let err = parse_xml::<MyObject>(b"<MyObject name=\"test\"><a>x</b></MyObject>", &rewriting)
+            .unwrap_err();
+        assert!(
+            matches!(err, XMLPayloadError::NotWellFormed { position: 28, .. }),
+            "{:?}",
+            err
+        );
+    }
+    // entities of the resolver are well-formed
+    let s: MyObject = parse_xml(
+        b"<MyObject name=\"&custom;\"/>",
+        &config.clone().entity_resolver(entities),
+    )
+    .unwrap();
+    assert_eq!(s.name, "test");
+
+    // recovered documents are well-formed
+    let s: MyObject = parse_xml(
+        b"<MyObject name=\"test\"><a></b></MyObject>",
+        &config.clone().lenient(true),
+    )
+    .unwrap();
+    assert_eq!(s.name, "test");
+}
+
+#[actix_rt::test]
+async fn test_incomplete_payload() {
+    #[derive(Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct Outer {
+        name: String,
+        inner: Option<MyObject>,
+    }
+
+    for body in [
+        &b"<Outer name=\"test\"><inner>"[..],
+        b"<Outer name=\"te",
+        b"<Outer name=\"test\"><inner name=\"a\"/></",
+        b"<Outer name=\"test\"><!-- inner",
+    ] {
+        let (req, mut pl) = xml_test_request(body).to_http_parts();
+
+        let err = Xml::<Outer>::from_request(&req, &mut pl).await.unwrap_err();
+        assert!(
+            matches!(
+                err.as_error::<XMLPayloadError>(),
+                Some(XMLPayloadError::Incomplete { .. })
+            ),
+            "{:?}",
+            err
+        );
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    // complete documents not matching the target type are not incomplete
+    let (req, mut pl) = xml_test_request(b"<Outer><inner name=\"a\"/></Outer>").to_http_parts();
+
+    let err = Xml::<Outer>::from_request(&req, &mut pl).await.unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Deserialize { .. })
+    ));
+}
+
+#[test]
+fn test_limit_units() {
+    assert_eq!(XmlConfig::default().limit_kb(256).limit, Some(262_144));
+    assert_eq!(XmlConfig::default().limit_mb(1).limit, Some(1_048_576));
+    assert_eq!(
+        XmlConfig::default().limit_mb(usize::MAX).limit,
+        Some(usize::MAX)
+    );
+    assert_eq!(
+        XmlConfig::default().limit_kb(usize::MAX / 1000).limit,
+        Some(usize::MAX)
+    );
+}
+
+#[actix_rt::test]
+async fn test_config_presets() {
+    let strict = XmlConfig::strict();
+    assert_eq!(strict.limit, Some(65_536));
+    assert!(strict.forbid_doctype);
+    assert!(!strict.lenient);
+
+    let permissive = XmlConfig::permissive();
+    assert_eq!(permissive.limit, Some(4_194_304));
+    assert!(!permissive.forbid_doctype);
+    assert!(permissive.lenient);
+
+    let request = |config: &XmlConfig, content_type: &'static str, body: &'static [u8]| {
+        TestRequest::default()
+            .insert_header((
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static(content_type),
+            ))
+            .set_payload(Bytes::from_static(body))
+            .app_data(config.clone())
+            .to_http_parts()
+    };
+
+    // only strict rejects doctypes and +xml suffixes
+    let doctype = b"<!DOCTYPE MyObject><MyObject name=\"test\" />";
+    let (req, mut pl) = request(&strict, "application/xml", doctype);
+    let err = Xml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::DoctypeForbidden)
+    ));
+    let (req, mut pl) = request(&permissive, "application/xml", doctype);
+    assert!(Xml::<MyObject>::from_request(&req, &mut pl).await.is_ok());
+
+    let body = b"<MyObject name=\"test\" />";
+    let (req, mut pl) = request(&strict, "application/atom+xml", body);
+    let err = Xml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::ContentType { .. })
+    ));
+    let (req, mut pl) = request(&strict, "text/xml; charset=utf-8", body);
+    assert!(Xml::<MyObject>::from_request(&req, &mut pl).await.is_ok());
+    let (req, mut pl) = request(&permissive, "application/atom+xml", body);
+    assert!(Xml::<MyObject>::from_request(&req, &mut pl).await.is_ok());
+
+    // only permissive recovers malformed documents
+    let sloppy = b"<MyObject name=\"a & b\"><unclosed></MyObject>";
+    let (req, mut pl) = request(&permissive, "application/xml", sloppy);
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "a & b");
+    let (req, mut pl) = request(&strict, "application/xml", sloppy);
+    assert!(Xml::<MyObject>::from_request(&req, &mut pl).await.is_err());
+}
+
+#[actix_rt::test]
+async fn test_accept_content_types() {
+    let config = XmlConfig::default().accept_content_types(vec![
+        "application/vnd.myapp+xml".parse().unwrap(),
+        "text/plain".parse().unwrap(),
+    ]);
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("text/plain; charset=utf-8"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(config.clone())
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(s.is_ok());
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/vnd.myapp+xml"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(config.clone())
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(s.is_ok());
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("text/html"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(config)
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(s.is_err());
+}
+
+#[actix_rt::test]
+async fn test_accept_content_type_wildcard() {
+    let config = XmlConfig::default().accept_content_types(vec!["application/*".parse().unwrap()]);
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/foobar"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(config.clone())
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(s.is_ok());
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("text/plain"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(config)
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(matches!(
+        s.err().unwrap().as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::ContentType { .. })
+    ));
+}
+
+#[actix_rt::test]
+async fn test_entity_resolver() {
+    let mut entities = HashMap::new();
+    entities.insert("company".to_string(), "Example & Co.".to_string());
+
+    let (req, mut pl) = xml_test_request(b"<Nested><inner name=\"&company; &lt;3\" /></Nested>")
+        .app_data(XmlConfig::default().entity_resolver(entities))
+        .to_http_parts();
+
+    let xml = XmlBody::<Nested>::new(&req, &mut pl).await;
+    assert_eq!(xml.unwrap().inner.name, "Example & Co. <3");
+
+    let (req, mut pl) =
+        xml_test_request(b"<MyObject><name>&company;</name></MyObject>").to_http_parts();
+
+    let xml = XmlBody::<MyObject>::new(&req, &mut pl).await;
+    assert!(xml.is_err());
+}
+
+#[derive(Deserialize)]
+struct Upload {
+    item: Vec<MyObject>,
+}
+
+#[actix_rt::test]
+async fn test_xml_stream() {
+    let body = format!(
+        "<Upload>{}</Upload>",
+        "<item name=\"test\" />".repeat(100_000)
+    );
+    let (req, mut pl) = xml_test_request(body.as_bytes())
+        .app_data(XmlConfig::default().limit(4_194_304))
+        .to_http_parts();
+
+    let s = XmlStream::<Upload>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert_eq!(s.item.len(), 100_000);
+    assert!(s.item.iter().all(|item| item.name == "test"));
+
+    let (req, mut pl) = xml_test_request(b"<MyObject name=\"test\" />")
+        .app_data(XmlConfig::default().limit(10))
+        .to_http_parts();
+
+    let s = XmlStream::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(s
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("Xml payload size 24 exceeds limit 10"));
+
+    let (req, mut pl) = xml_test_request(b"<MyObject><a></b></MyObject>").to_http_parts();
+
+    let s = XmlStream::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(s.is_err());
+}
+
+#[actix_rt::test]
+async fn test_xml_stream_checks_headers() {
+    let request = |length: &'static str| {
+        TestRequest::default()
+            .insert_header((header::CONTENT_TYPE, "application/xml"))
+            .insert_header((header::CONTENT_LENGTH, length))
+            .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+            .app_data(XmlConfig::default().limit(10))
+            .to_http_parts()
+    };
+
+    let (req, mut pl) = request("24");
+    let s = XmlStream::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(matches!(
+        s.err().unwrap().as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Overflow {
+            limit: 10,
+            length: Some(24),
+            decompressed: false,
+        })
+    ));
+    // the payload wasn't taken from the request
+    assert!(pl.next().await.is_some());
+
+    let (req, mut pl) = request("abc");
+    let s = XmlStream::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(matches!(
+        s.err().unwrap().as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::InvalidLength)
+    ));
+    assert!(pl.next().await.is_some());
+}
+
+#[test]
+fn test_from_slice() {
+    let s = Xml::<MyObject>::from_slice(b"<MyObject name=\"test\" />").unwrap();
+    assert_eq!(s.name, "test");
+
+    let s: Xml<MyObject> = "<MyObject name=\"test\" />".parse().unwrap();
+    assert_eq!(s.name, "test");
+
+    let s = Xml::<MyObject>::from_slice(b"garbage");
+    assert!(matches!(
+        s.err().unwrap(),
+        XMLPayloadError::Deserialize { .. }
+    ));
+
+    let s = "<MyObject><a></b></MyObject>".parse::<Xml<MyObject>>();
+    assert!(matches!(
+        s.err().unwrap(),
+        XMLPayloadError::Deserialize { .. }
+    ));
+}
+
+#[actix_rt::test]
+async fn test_responder_declaration() {
+    let value = || MyObject {
+        name: "test".to_string(),
+    };
+
+    let req = TestRequest::default()
+        .app_data(XmlConfig::default().emit_declaration(true))
+        .to_http_request();
+    let body = to_bytes(Xml(value()).respond_to(&req).into_body())
+        .await
+        .unwrap();
+    assert_eq!(
+        body,
+        Bytes::from_static(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?><MyObject name=\"test\"/>")
+    );
+
+    let req = TestRequest::default()
+        .app_data(
+            XmlConfig::default()
+                .declaration("1.1", "utf-8")
+                .pretty(b' ', 2),
+        )
+        .to_http_request();
+    let body = to_bytes(Xml(value()).respond_to(&req).into_body())
+        .await
+        .unwrap();
+    assert_eq!(
+        body,
+        Bytes::from_static(
+            b"<?xml version=\"1.1\" encoding=\"utf-8\"?>\n<MyObject name=\"test\"/>"
+        )
+    );
+}
+
+#[actix_rt::test]
+async fn test_responder_accept() {
+    async fn content_type(accept: Option<&'static str>) -> header::HeaderValue {
+        let mut req = TestRequest::default()
+            .app_data(XmlConfig::default().response_content_type("application/soap+xml"));
+        if let Some(accept) = accept {
+            req = req.insert_header((header::ACCEPT, accept));
+        }
+        let resp = Xml(MyObject {
+            name: "test".to_string(),
+        })
+        .respond_to(&req.to_http_request());
+        resp.headers().get(header::CONTENT_TYPE).unwrap().clone()
+    }
+
+    assert_eq!(content_type(None).await, "application/soap+xml");
+    assert_eq!(content_type(Some("*/*")).await, "application/soap+xml");
+    assert_eq!(content_type(Some("text/xml")).await, "text/xml");
+    assert_eq!(
+        content_type(Some("text/xml;q=0.5, application/xml")).await,
+        "application/xml"
+    );
+    assert_eq!(
+        content_type(Some("application/json, text/xml;q=0.8, */*;q=0.1")).await,
+        "text/xml"
+    );
+    assert_eq!(
+        content_type(Some("*/*, text/xml;q=0.5")).await,
+        "application/soap+xml"
+    );
+
+    // refused types are skipped
+    assert_eq!(
+        content_type(Some("application/json, text/xml;q=0")).await,
+        "application/soap+xml"
+    );
+    assert_eq!(
+        content_type(Some("text/xml;q=0, application/xml;q=0.1")).await,
+        "application/xml"
+    );
+
+    // ranges match the xml type of their kind
+    assert_eq!(content_type(Some("text/*")).await, "text/xml");
+    assert_eq!(
+        content_type(Some("application/json, application/*;q=0.5")).await,
+        "application/xml"
+    );
+    assert_eq!(
+        content_type(Some("text/*;q=0, */*")).await,
+        "application/soap+xml"
+    );
+}
+
+#[derive(Deserialize)]
+struct Extensible {
+    name: String,
+    #[serde(flatten)]
+    extra: XmlExtra,
+}
+
+#[actix_rt::test]
+async fn test_xml_extra() {
+    let (req, mut pl) = xml_test_request(
+        b"<Extensible><name>test</name><note>hello</note>\
+            <meta><author>me</author></meta></Extensible>",
+    )
+    .to_http_parts();
+
+    let s = Xml::<Extensible>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert_eq!(s.name, "test");
+    assert_eq!(
+        s.extra,
+        XmlExtra(vec![
+            ("note".to_string(), "hello".to_string()),
+            ("meta".to_string(), "<author>me</author>".to_string()),
+        ])
+    );
+}
+
+#[actix_rt::test]
+async fn test_xml_extra_attributes() {
+    let (req, mut pl) = xml_test_request(
+        b"<Extensible version=\"2\"><name>test</name><note lang=\"en\">hi</note>\
+            <meta><a x=\"1&amp;2\">v</a><lang>en</lang></meta></Extensible>",
+    )
+    .to_http_parts();
+
+    let s = Xml::<Extensible>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert_eq!(
+        s.extra,
+        XmlExtra(vec![
+            ("@version".to_string(), "2".to_string()),
+            ("note/@lang".to_string(), "en".to_string()),
+            ("note".to_string(), "hi".to_string()),
+            (
+                "meta".to_string(),
+                "<a x=\"1&amp;2\">v</a><lang>en</lang>".to_string()
+            ),
+        ])
+    );
+}
+
+#[cfg(feature = "tracing")]
+#[actix_rt::test]
+async fn test_tracing_span() {
+    use std::sync::atomic::AtomicU64;
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    #[derive(Default)]
+    struct Recorder {
+        next_id: AtomicU64,
+        entered: AtomicUsize,
+        spans: Mutex<Vec<(&'static str, &'static str)>>,
+        fields: Mutex<Vec<(String, String)>>,
+    }
+
+    impl Visit for &Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.fields
+                .lock()
+                .unwrap()
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    struct TestSubscriber(Arc<Recorder>);
+
+    impl Subscriber for TestSubscriber {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut recorder = &*self.0;
+            recorder
+                .spans
+                .lock()
+                .unwrap()
+                .push((span.metadata().name(), span.metadata().target()));
+            span.record(&mut recorder);
+            Id::from_u64(recorder.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut &*self.0);
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, _: &Id) {
+            self.0.entered.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    let recorder = Arc::new(Recorder::default());
+    let _guard = tracing::subscriber::set_default(TestSubscriber(recorder.clone()));
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .insert_header((
+            header::CONTENT_LENGTH,
+            header::HeaderValue::from_static("24"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(XmlConfig::default().limit(4096))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+
+    assert_eq!(
+        *recorder.spans.lock().unwrap(),
+        vec![("xml_extract", "actix_xml")]
+    );
+    assert!(recorder.entered.load(Ordering::SeqCst) > 0);
+    let fields = recorder.fields.lock().unwrap();
+    for (name, value) in [
+        ("content_length", "24"),
+        ("limit", "4096"),
+        ("outcome", "\"ok\""),
+    ] {
+        assert!(
+            fields.contains(&(name.to_string(), value.to_string())),
+            "{:?}",
+            fields
+        );
+    }
+    assert!(fields.iter().any(|(name, _)| name == "elapsed_us"));
+}
+
+#[actix_rt::test]
+async fn test_accept_any_content_type() {
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/octet-stream"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(XmlConfig::default().accept_any_content_type(true))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+}
+
+#[actix_rt::test]
+async fn test_missing_content_type() {
+    let (req, mut pl) = TestRequest::default()
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    let err = s.err().unwrap();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::ContentType { received: None })
+    ));
+    assert_eq!(err.to_string(), "missing content type");
+
+    let (req, mut pl) = TestRequest::default()
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(XmlConfig::default().require_content_type(false))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("text/plain"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(XmlConfig::default().require_content_type(false))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(matches!(
+        s.err().unwrap().as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::ContentType { received: Some(_) })
+    ));
+}
+
+#[actix_rt::test]
+async fn test_content_type_required() {
+    let request = |content_type: Option<&'static str>, config: XmlConfig| {
+        let mut req = TestRequest::default()
+            .insert_header((
+                header::CONTENT_LENGTH,
+                header::HeaderValue::from_static("24"),
+            ))
+            .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+            .app_data(config);
+        if let Some(content_type) = content_type {
+            req = req.insert_header((header::CONTENT_TYPE, content_type));
+        }
+        req.to_http_parts()
+    };
+
+    // with no content type
+    let (req, mut pl) = request(None, XmlConfig::default().content_type_required(false));
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+
+    // ignoring the content type
+    let (req, mut pl) = request(
+        Some("invalid/value"),
+        XmlConfig::default().content_type_required(false),
+    );
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+
+    for content_type in [None, Some("invalid/value"), Some("garbage")] {
+        let (req, mut pl) = request(
+            content_type,
+            XmlConfig::default()
+                .content_type_required(false)
+                .content_type_required(true),
+        );
+        let err = Xml::<MyObject>::from_request(&req, &mut pl)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.error_response().status(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn test_duplicate_content_type() {
+    let request = |first: &'static str, second: &'static str, config: XmlConfig| {
+        TestRequest::default()
+            .append_header((header::CONTENT_TYPE, first))
+            .append_header((header::CONTENT_TYPE, second))
+            .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+            .app_data(config)
+            .to_http_parts()
+    };
+
+    // the first header decides
+    let (req, mut pl) = request("application/xml", "garbage", XmlConfig::default());
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+
+    let (req, mut pl) = request("garbage", "application/xml", XmlConfig::default());
+    let err = Xml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::ContentType { received: None })
+    ));
+    assert_eq!(
+        err.error_response().status(),
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+
+    let (req, mut pl) = request("text/plain", "application/xml", XmlConfig::default());
+    let err = Xml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::ContentType { received: Some(mime) }) if mime == &mime::TEXT_PLAIN
+    ));
+
+    // an invalid header isn't a missing one
+    let config = XmlConfig::default().require_content_type(false);
+    let (req, mut pl) = request("garbage", "application/xml", config);
+    let err = Xml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::ContentType { received: None })
+    ));
+}
+
+#[actix_rt::test]
+async fn test_trim_text() {
+    let payload = Bytes::from_static(b"<MyObject><name>  test\n</name></MyObject>");
+
+    let (req, mut pl) = xml_test_request(&payload).to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+
+    let (req, mut pl) = xml_test_request(&payload)
+        .app_data(XmlConfig::default().trim_text(false))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "  test\n");
+}
+
+#[actix_rt::test]
+async fn test_check_end_names() {
+    let payload = Bytes::from_static(b"<MyObject><name>test</nmae></MyObject>");
+
+    let (req, mut pl) = xml_test_request(&payload).to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(s.is_err());
+
+    let (req, mut pl) = xml_test_request(&payload)
+        .app_data(XmlConfig::default().check_end_names(false))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+}
+
+#[actix_rt::test]
+async fn test_schema() {
+    struct RequireName;
+
+    impl Schema for RequireName {
+        fn validate(&self, document: &str) -> Result<(), String> {
+            if document.contains("<name>") {
+                Ok(())
+            } else {
+                Err("missing element `name`".to_string())
+            }
+        }
+    }
+
+    let (req, mut pl) = xml_test_request(b"<MyObject><name>test</name></MyObject>")
+        .app_data(XmlConfig::default().schema(RequireName))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+
+    let (req, mut pl) = xml_test_request(b"<MyObject name=\"test\" />")
+        .app_data(XmlConfig::default().schema(RequireName))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    let err = s.err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Xml schema validation error: missing element `name`"
+    );
+    assert_eq!(err.error_response().status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn test_xml_accessors() {
+    let mut xml = Xml(MyObject {
+        name: "test".to_string(),
+    });
+    assert_eq!(xml.get_ref().name, "test");
+    xml.get_mut().name = "changed".to_string();
+    assert_eq!(xml.into_inner().name, "changed");
+}
+
+#[test]
+fn test_xml_constructors() {
+    let xml = Xml::new(MyObject {
+        name: "new".to_string(),
+    });
+    assert_eq!(xml.name, "new");
+
+    let xml: Xml<MyObject> = MyObject {
+        name: "from".to_string(),
+    }
+    .into();
+    assert_eq!(xml.name, "from");
+}
+
+#[test]
+fn test_xml_clone_eq() {
+    let xml = Xml(MyObject {
+        name: "test".to_string(),
+    });
+    let cloned = xml.clone();
+    assert_eq!(xml, cloned);
+    assert_ne!(
+        xml,
+        Xml(MyObject {
+            name: "other".to_string()
+        })
+    );
+
+    let set: std::collections::HashSet<_> = vec![Xml(1), Xml(1), Xml(2)].into_iter().collect();
+    assert_eq!(set.len(), 2);
+}
+
+#[actix_rt::test]
+async fn test_content_length_overflow_leaves_payload() {
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .insert_header((
+            header::CONTENT_LENGTH,
+            header::HeaderValue::from_static("24"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(XmlConfig::default().limit(10))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(matches!(
+        s.err().unwrap().as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Overflow {
+            limit: 10,
+            length: Some(24),
+            decompressed: false,
+        })
+    ));
+
+    // the payload wasn't taken from the request
+    let chunk = pl.next().await.unwrap().unwrap();
+    assert_eq!(chunk, Bytes::from_static(b"<MyObject name=\"test\" />"));
+}
+
+#[actix_rt::test]
+async fn test_stream_limit() {
+    // chunked payload without content length
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(XmlConfig::default().stream_limit(10))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(matches!(
+        s.err().unwrap().as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Overflow {
+            limit: 10,
+            length: Some(24),
+            decompressed: false,
+        })
+    ));
+
+    // content length is checked against limit only
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .insert_header((
+            header::CONTENT_LENGTH,
+            header::HeaderValue::from_static("24"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(XmlConfig::default().limit(10).stream_limit(4096))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(matches!(
+        s.err().unwrap().as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Overflow {
+            limit: 10,
+            length: Some(24),
+            decompressed: false,
+        })
+    ));
+}
+
+#[actix_rt::test]
+async fn test_require_content_length() {
+    // chunked payload without content length
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(XmlConfig::default().require_content_length(true))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    let err = s.err().unwrap();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::LengthRequired)
+    ));
+    assert_eq!(
+        err.as_response_error().status_code(),
+        StatusCode::LENGTH_REQUIRED
+    );
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .insert_header((
+            header::CONTENT_LENGTH,
+            header::HeaderValue::from_static("24"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(XmlConfig::default().require_content_length(true))
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert_eq!(s.unwrap().name, "test");
+}
+
+#[actix_rt::test]
+async fn test_invalid_content_length() {
+    let request = |length: &'static str| {
+        TestRequest::default()
+            .insert_header((
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/xml"),
+            ))
+            .insert_header((
+                header::CONTENT_LENGTH,
+                header::HeaderValue::from_static(length),
+            ))
+            .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+            .to_http_parts()
+    };
+
+    // neither non-numeric nor overflowing lengths are taken for missing ones
+    for length in &["abc", "-1", "99999999999999999999999999"] {
+        let (req, mut pl) = request(length);
+        let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+        let err = s.err().unwrap();
+        assert!(matches!(
+            err.as_error::<XMLPayloadError>(),
+            Some(XMLPayloadError::InvalidLength)
+        ));
+        assert_eq!(err.error_response().status(), StatusCode::BAD_REQUEST);
+
+        let (req, mut pl) = request(length);
+        let s = XmlBody::<MyObject>::new(&req, &mut pl).await;
+        assert!(matches!(s, Err(XMLPayloadError::InvalidLength)));
+    }
+}
+
+#[actix_rt::test]
+async fn test_on_complete() {
+    let stats = Arc::new(Mutex::new(Vec::new()));
+    let recorded = stats.clone();
+    let config = XmlConfig::default()
+        .limit(10)
+        .on_complete(move |s: &XmlStats| recorded.lock().unwrap().push(*s));
+
+    let (req, mut pl) = xml_test_request(b"<MyObject name=\"test\" />")
+        .app_data(config.clone().limit(100))
+        .to_http_parts();
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert_eq!(s.unwrap().name, "test");
+
+    let (req, mut pl) = xml_test_request(b"<MyObject name=\"test\" />")
+        .app_data(config)
+        .to_http_parts();
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(s.is_err());
+
+    let stats = stats.lock().unwrap();
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[0].bytes, 24);
+    assert_eq!(stats[0].outcome, Outcome::Ok);
+    assert_eq!(stats[1].bytes, 24);
+    assert_eq!(stats[1].outcome, Outcome::Overflow);
+    assert_eq!(stats[1].parse_duration, Duration::ZERO);
+}
+
+#[actix_rt::test]
+async fn test_no_limit() {
+    let name = "a".repeat(300_000);
+    let body = format!("<MyObject name=\"{}\" />", name);
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .insert_header((header::CONTENT_LENGTH, body.len()))
+        .set_payload(body)
+        .app_data(XmlConfig::default().no_limit())
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    assert_eq!(s.unwrap().name, name);
+}
+
+#[test]
+fn test_lenient() {
+    #[derive(Deserialize)]
+    struct Item {
+        name: String,
+        value: String,
+        #[serde(rename = "href")]
+        link: String,
+    }
+
+    let config = XmlConfig::default().lenient(true);
+
+    let dangling = b"<Item href=\"/\"><name>test</name></extra><value>a</value></Item>";
+    assert!(parse_xml::<Item>(dangling, &XmlConfig::default()).is_err());
+    let item: Item = parse_xml(dangling, &config).unwrap();
+    assert_eq!(item.name, "test");
+    assert_eq!(item.value, "a");
+
+    // unclosed element and stray ampersands
+    let malformed = b"<Item href='/?a=1&b=\"2\"'><name>Tom & Jerry &amp; co</name><value>a</Item>";
+    assert!(parse_xml::<Item>(malformed, &XmlConfig::default()).is_err());
+    let item: Item = parse_xml(malformed, &config).unwrap();
+    assert_eq!(item.name, "Tom & Jerry & co");
+    assert_eq!(item.value, "a");
+    assert_eq!(item.link, "/?a=1&b=\"2\"");
+}
+
+#[actix_rt::test]
+async fn test_cdata() {
+    #[derive(Deserialize)]
+    struct Page {
+        script: Cdata,
+        title: String,
+    }
+
+    let (req, mut pl) = xml_test_request(
+        b"<Page>\n  <script><![CDATA[ if (a < b && c) { d = '&amp;'; } ]]></script>\n  \
+              <title>Tom &amp; <![CDATA[<Jerry> ]]]]><![CDATA[> ]]></title>\n</Page>",
+    )
+    .to_http_parts();
+
+    let page = Xml::<Page>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(page.script.as_str(), " if (a < b && c) { d = '&amp;'; } ");
+    assert_eq!(page.title, "Tom & <Jerry> ]]> ");
+}
+
+#[actix_rt::test]
+async fn test_deserializer_builder() {
+    let built = Arc::new(AtomicUsize::new(0));
+    let counter = built.clone();
+    let config = XmlConfig::default().deserializer_builder(move |text| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        // trims text like the default deserializer
+        quick_xml::de::Deserializer::from_str(text)
+    });
+
+    let (req, mut pl) = xml_test_request(b"<MyObject>\n  <name>  test  </name>\n</MyObject>")
+        .app_data(config)
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+    assert_eq!(built.load(Ordering::SeqCst), 1);
+}
+
+/// Logger capturing the level and message of every record
+struct CapturingLogger(Mutex<Vec<(log::Level, String, String)>>);
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0.lock().unwrap().push((
+            record.level(),
+            record.target().to_string(),
+            record.args().to_string(),
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger(Mutex::new(Vec::new()));
+
+#[actix_rt::test]
+async fn test_log_errors_at() {
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(log::LevelFilter::Trace);
+
+    for (path, level, target) in [
+        ("/log-warn", Some(log::Level::Warn), "actix_xml::config"),
+        ("/log-default", Some(log::Level::Debug), "actix_xml::config"),
+        ("/log-none", None, ""),
+        ("/log-target", Some(log::Level::Info), "xml"),
+        ("/log-no-path", Some(log::Level::Info), "xml-no-path"),
+    ] {
+        let config = match path {
+            "/log-default" => XmlConfig::default(),
+            "/log-none" | "/log-warn" => XmlConfig::default().log_errors_at(level),
+            _ => XmlConfig::default().log_errors_at(level).log_target(target),
+        };
+        let config = config.log_path(path != "/log-no-path");
+        let (req, mut pl) = TestRequest::with_uri(path)
+            .insert_header((
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/xml"),
+            ))
+            .set_payload(Bytes::from_static(b"<MyObject>"))
+            .app_data(config)
+            .to_http_parts();
+        assert!(Xml::<MyObject>::from_request(&req, &mut pl).await.is_err());
+
+        let logged: Vec<_> = LOGGER
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, logged_target, message)| {
+                message.ends_with(path) || (path == "/log-no-path" && logged_target == target)
+            })
+            .map(|(level, target, message)| (*level, target.clone(), message.contains(path)))
+            .collect();
+        let expected = level.map(|level| (level, target.to_string(), path != "/log-no-path"));
+        assert_eq!(logged, expected.into_iter().collect::<Vec<_>>());
+    }
+}
+
+#[actix_rt::test]
+async fn test_prolog() {
+    let body = "\u{feff}<?xml version=\"1.0\"?>\n<!-- generated -->\n\
+                <?xml-stylesheet href=\"style.xsl\"?>\n  <MyObject name=\"test\" />\n<!-- end -->\n";
+    for config in [
+        XmlConfig::default(),
+        XmlConfig::default().trim_text(false),
+        XmlConfig::default().lenient(true),
+        XmlConfig::default().require_root("MyObject"),
+    ] {
+        let (req, mut pl) = xml_test_request(body.as_bytes())
+            .app_data(config)
+            .to_http_parts();
+
+        let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+        assert_eq!(s.name, "test");
+    }
+}
+
+#[test]
+fn test_verbose_errors() {
+    #[derive(Deserialize, Debug)]
+    struct Item {
+        name: String,
+        price: u32,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Order {
+        item: Vec<Item>,
+    }
+
+    let terse = XmlConfig::default();
+    let verbose = XmlConfig::default().verbose_errors(true);
+
+    let assert_messages = |payload: &[u8], message: &str, context: &str| {
+        let err = parse_xml::<Order>(payload, &terse).unwrap_err();
+        assert_eq!(err.to_string(), message);
+        let err = parse_xml::<Order>(payload, &verbose).unwrap_err();
+        assert_eq!(err.to_string(), format!("{} ({})", message, context));
+    };
+    assert_messages(
+        b"<Order><item><name>Tea</name><price>2</price></item>\n<item><name>Milk</name><price>abc</price></item></Order>",
+        "Xml deserialize error: invalid digit found in string",
+        "field `item[1].price`, near `<price>abc</price></item></Order>`",
+    );
+    assert_messages(
+        b"<Order><item name=\"Tea\" price=\"-1\"/></Order>",
+        "Xml deserialize error: invalid digit found in string",
+        "field `item[0].price`, near `<item name=\"Tea\" price=\"-1\"/></Order>`",
+    );
+    assert_messages(
+        b"<Order><item><name>Tea</name></item></Order>",
+        "Xml deserialize error: missing field `price`",
+        "field `item[0]`, near `<item><name>Tea</name></item></Order>`",
+    );
+    assert_messages(
+        b"<Order><item><name>Tea</nam></item></Order>",
+        "Xml deserialize error: Expecting </name> found </nam> at byte 24",
+        "field `item[0].name`, near `er><item><name>Tea</nam></item></Order>`",
+    );
+
+    // values are deserialized the same way
+    let order: Order = parse_xml(
+        b"<Order><item><name>Tea</name><price>2</price></item></Order>",
+        &verbose,
+    )
+    .unwrap();
+    assert_eq!(order.item[0].name, "Tea");
+    assert_eq!(order.item[0].price, 2);
+}
+
+#[actix_rt::test]
+async fn test_lenient_scalars() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Item {
+        count: i32,
+        size: Option<u64>,
+        enabled: bool,
+        visible: bool,
+        flags: Vec<bool>,
+    }
+
+    let body: &[u8] = b"<Item count=\" 42 \" size=\"7\t\" enabled=\"1\" visible=\" On \">\
+          <flags>yes</flags><flags>N</flags></Item>";
+    let request = |config: XmlConfig| xml_test_request(body).app_data(config).to_http_parts();
+
+    let (req, mut pl) = request(XmlConfig::default().lenient_scalars(true));
+    let item = Xml::<Item>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(
+        item.into_inner(),
+        Item {
+            count: 42,
+            size: Some(7),
+            enabled: true,
+            visible: true,
+            flags: vec![true, false],
+        }
+    );
+
+    // strict by default
+    let (req, mut pl) = request(XmlConfig::default());
+    let err = Xml::<Item>::from_request(&req, &mut pl).await.unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Deserialize { .. })
+    ));
+
+    // whitespace kept in text, and invalid values are still rejected
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Text {
+        n: u8,
+    }
+    let config = XmlConfig::default().trim_text(false).lenient_scalars(true);
+    let text: Text = parse_xml(b"<Text><n>\n  8\n</n></Text>", &config).unwrap();
+    assert_eq!(text, Text { n: 8 });
+    assert!(parse_xml::<Text>(b"<Text><n>300</n></Text>", &config).is_err());
+}
+
+#[test]
+fn test_de_bool_flexible() {
+    #[derive(Deserialize, Debug)]
+    struct User {
+        #[serde(deserialize_with = "de_bool_flexible")]
+        active: bool,
+    }
+
+    let config = XmlConfig::default();
+    for (value, expected) in [
+        ("true", true),
+        ("1", true),
+        ("Yes", true),
+        ("y", true),
+        ("ON", true),
+        (" true ", true),
+        ("false", false),
+        ("0", false),
+        ("no", false),
+        ("N", false),
+        ("off", false),
+    ] {
+        let xml = format!("<User active=\"{}\"/>", value);
+        let user: User = parse_xml(xml.as_bytes(), &config).unwrap();
+        assert_eq!(user.active, expected, "{}", value);
+    }
+
+    // text content is read the same way
+    let user: User = parse_xml(b"<User><active>yes</active></User>", &config).unwrap();
+    assert!(user.active);
+
+    let err = parse_xml::<User>(b"<User active=\"maybe\"/>", &config).unwrap_err();
+    assert!(
+        err.to_string().contains("invalid boolean `maybe`"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_de_number_locale() {
+    #[derive(Deserialize, Debug)]
+    struct Item {
+        #[serde(deserialize_with = "de_number_locale")]
+        price: f64,
+        #[serde(deserialize_with = "de_number_locale")]
+        count: u32,
+    }
+
+    let config = XmlConfig::default();
+    for (price, expected) in [
+        ("1234.56", 1234.56),
+        ("1,234.56", 1234.56),
+        ("1.234,56", 1234.56),
+        ("1 234,56", 1234.56),
+        ("1'234.56", 1234.56),
+        ("1\u{a0}234,56", 1234.56),
+        ("12,5", 12.5),
+        ("1.234", 1.234),
+        ("-1,234,567.5", -1234567.5),
+        ("1.234.567,5", 1234567.5),
+        (" 42 ", 42.0),
+    ] {
+        let xml = format!("<Item price=\"{}\" count=\"1\"/>", price);
+        let item: Item = parse_xml(xml.as_bytes(), &config).unwrap();
+        assert_eq!(item.price, expected, "{}", price);
+    }
+
+    for (count, expected) in [("1,234", 1234), ("1.234.567", 1_234_567), ("1 000", 1000)] {
+        let xml = format!("<Item price=\"0\" count=\"{}\"/>", count);
+        let item: Item = parse_xml(xml.as_bytes(), &config).unwrap();
+        assert_eq!(item.count, expected, "{}", count);
+    }
+
+    let err = parse_xml::<Item>(b"<Item price=\"0\" count=\"1,5\"/>", &config).unwrap_err();
+    assert!(err.to_string().contains("invalid number `1,5`"), "{}", err);
+    let err = parse_xml::<Item>(b"<Item price=\"abc\" count=\"1\"/>", &config).unwrap_err();
+    assert!(err.to_string().contains("invalid number `abc`"), "{}", err);
+}
+
+#[test]
+fn test_keyed_by_attr() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Item {
+        name: String,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Items {
+        #[serde(rename = "item", deserialize_with = "by_id")]
+        items: HashMap<String, Item>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct LastWins {
+        #[serde(rename = "item", deserialize_with = "by_id_last_wins")]
+        items: HashMap<u32, Item>,
+    }
+
+    fn by_id<'de, D: Deserializer<'de>>(d: D) -> Result<HashMap<String, Item>, D::Error> {
+        keyed_by_attr("id").deserialize(d)
+    }
+
+    fn by_id_last_wins<'de, D: Deserializer<'de>>(d: D) -> Result<HashMap<u32, Item>, D::Error> {
+        keyed_by_attr("id").last_wins(true).deserialize(d)
+    }
+
+    let config = XmlConfig::default();
+    let items: Items = parse_xml(
+        b"<items><item id=\"a\"><name>Tea</name></item><item id=\"b\"><name>Milk</name></item></items>",
+        &config,
+    )
+    .unwrap();
+    assert_eq!(items.items.len(), 2);
+    assert_eq!(items.items["a"].name, "Tea");
+    assert_eq!(items.items["b"].name, "Milk");
+
+    // the key may also follow the fields of the value
+    let items: Items = parse_xml(b"<items><item name=\"Tea\" id=\"a\"/></items>", &config).unwrap();
+    assert_eq!(items.items["a"].name, "Tea");
+
+    let duplicate = b"<items><item id=\"1\" name=\"Tea\"/><item id=\"1\" name=\"Milk\"/></items>";
+    let err = parse_xml::<Items>(duplicate, &config).unwrap_err();
+    assert!(err.to_string().contains("duplicate `id`"), "{}", err);
+    let items: LastWins = parse_xml(duplicate, &config).unwrap();
+    assert_eq!(items.items.len(), 1);
+    assert_eq!(items.items[&1].name, "Milk");
+
+    let err = parse_xml::<Items>(b"<items><item name=\"Tea\"/></items>", &config).unwrap_err();
+    assert!(err.to_string().contains("missing field `id`"), "{}", err);
+}
+
+#[cfg(feature = "value")]
+#[actix_rt::test]
+async fn test_xml_value() {
+    use crate::XmlValue;
+
+    let (req, mut pl) = xml_test_request(
+        b"<?xml version=\"1.0\"?>\
+              <library name=\"main\">\
+                <!-- shelves -->\
+                <shelf id=\"1\"><book lang=\"en\"><title>Dune &amp; more</title></book></shelf>\
+                <shelf id=\"2\"><book><title><![CDATA[<b>Emma</b>]]></title></book></shelf>\
+              </library>",
+    )
+    .to_http_parts();
+
+    let value = Xml::<XmlValue>::from_request(&req, &mut pl)
+        .await
+        .unwrap()
+        .into_inner();
+    let library = value.as_element().unwrap();
+    assert_eq!(library.name, "library");
+    assert_eq!(library.attribute("name"), Some("main"));
+    assert_eq!(library.children.len(), 2);
+
+    let titles: Vec<_> = library
+        .children_named("shelf")
+        .filter_map(|shelf| shelf.child("book"))
+        .filter_map(|book| book.child("title"))
+        .map(|title| title.text())
+        .collect();
+    assert_eq!(titles, ["Dune & more", "<b>Emma</b>"]);
+
+    let book = library.child("shelf").and_then(|shelf| shelf.child("book"));
+    assert_eq!(book.and_then(|book| book.attribute("lang")), Some("en"));
+    assert_eq!(
+        book.and_then(|book| book.children.first())
+            .and_then(XmlValue::as_text),
+        None
+    );
+
+    // only whole payloads deserialize into a tree
+    #[derive(Deserialize, Debug)]
+    struct Wrapper {
+        #[allow(dead_code)]
+        shelf: XmlValue,
+    }
+    assert!(parse_xml::<Wrapper>(b"<library><shelf/></library>", &XmlConfig::default()).is_err());
+}
+
+#[actix_rt::test]
+async fn test_wrap_root() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Item {
+        id: u32,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Items {
+        #[serde(rename = "item")]
+        items: Vec<Item>,
+    }
+
+    let (req, mut pl) = xml_test_request(
+        b"<?xml version=\"1.0\"?>\n<!-- items -->\n\
+              <item id=\"1\"/><item id=\"2\"/><item><id>3</id></item>",
+    )
+    .app_data(XmlConfig::default().wrap_root("items"))
+    .to_http_parts();
+
+    let s = Xml::<Items>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(
+        s.into_inner(),
+        Items {
+            items: vec![Item { id: 1 }, Item { id: 2 }, Item { id: 3 }]
+        }
+    );
+}
+
+#[actix_rt::test]
+async fn test_prefix_attributes() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Item {
+        #[serde(rename = "@id")]
+        id: u32,
+        #[serde(rename = "@name")]
+        label: Option<String>,
+        name: String,
+        #[serde(rename = "@soap:mustUnderstand")]
+        must_understand: bool,
+    }
+
+    let (req, mut pl) = xml_test_request(
+        b"<Item xmlns:soap=\"urn:soap\" id=\"1\" name='\"a\"' soap:mustUnderstand=\"true\">\
+              <name>b</name></Item>",
+    )
+    .app_data(XmlConfig::default().prefix_attributes(true))
+    .to_http_parts();
+
+    let item = Xml::<Item>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(
+        item.into_inner(),
+        Item {
+            id: 1,
+            label: Some("\"a\"".to_string()),
+            name: "b".to_string(),
+            must_understand: true,
+        }
+    );
+
+    // child elements are still matched by plain name
+    let (req, mut pl) = xml_test_request(b"<MyObject><name>b</name></MyObject>")
+        .app_data(XmlConfig::default().prefix_attributes(true))
+        .to_http_parts();
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "b");
+}
+
+#[test]
+fn test_parse_xml() {
+    let config = XmlConfig::default().limit(100);
+
+    let s: MyObject = parse_xml(b"<MyObject name=\"test\" />", &config).unwrap();
+    assert_eq!(s.name, "test");
+
+    let s = parse_xml::<MyObject>(b"<MyObject name=\"test\" />", &config.clone().limit(10));
+    assert!(xml_eq(
+        s.err().unwrap(),
+        XMLPayloadError::Overflow {
+            limit: 10,
+            length: Some(24),
+            decompressed: false,
+        }
+    ));
+
+    let s = parse_xml::<MyObject>(b"<MyObject name=\"test\">", &config);
+    assert!(matches!(
+        s.err().unwrap(),
+        XMLPayloadError::Incomplete { .. }
+    ));
+}
+
+#[cfg(feature = "self-decompress")]
+#[actix_rt::test]
+async fn test_compressed_payload() {
+    use std::io::Write;
+
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+
+    let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+    gzip.write_all(b"<MyObject name=\"test\" />").unwrap();
+    let mut deflate = ZlibEncoder::new(Vec::new(), Compression::default());
+    deflate.write_all(b"<MyObject name=\"test\" />").unwrap();
+
+    for (encoding, payload) in [
+        ("gzip", gzip.finish().unwrap()),
+        ("deflate", deflate.finish().unwrap()),
+    ] {
+        let (req, mut pl) = TestRequest::default()
+            .insert_header((
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/xml"),
+            ))
+            .insert_header((
+                header::CONTENT_ENCODING,
+                header::HeaderValue::from_static(encoding),
+            ))
+            .set_payload(payload)
+            .to_http_parts();
+
+        let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+        assert_eq!(s.name, "test");
+    }
+}
+
+#[cfg(any(feature = "compress-gzip", feature = "self-decompress"))]
+#[actix_rt::test]
+async fn test_decompression_bomb() {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    // 1MB of whitespace compresses to about 1KB
+    let mut gzip = GzEncoder::new(Vec::new(), Compression::best());
+    gzip.write_all(b"<MyObject name=\"test\">").unwrap();
+    gzip.write_all(&vec![b' '; 1 << 20]).unwrap();
+    gzip.write_all(b"</MyObject>").unwrap();
+    let payload = gzip.finish().unwrap();
+    assert!(payload.len() < 4096);
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .insert_header((
+            header::CONTENT_ENCODING,
+            header::HeaderValue::from_static("gzip"),
+        ))
+        .set_payload(payload)
+        .app_data(XmlConfig::default().limit(4096))
+        .to_http_parts();
+
+    let err = Xml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Overflow {
+            limit: 4096,
+            decompressed: true,
+            ..
+        })
+    ));
+    assert!(err
+        .to_string()
+        .ends_with("exceeds limit 4096 once decompressed"));
+
+    // plain payloads overflow as sent
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .set_payload(vec![b' '; 8192])
+        .app_data(XmlConfig::default().limit(4096))
+        .to_http_parts();
+
+    let err = Xml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Overflow {
+            limit: 4096,
+            decompressed: false,
+            ..
+        })
+    ));
+    assert!(err.to_string().ends_with("exceeds limit 4096"));
+}
+
+#[cfg(any(feature = "compress-gzip", feature = "self-decompress"))]
+#[actix_rt::test]
+async fn test_xml_parts() {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use crate::XmlParts;
+
+    let body = b"<MyObject name=\"test\" />";
+    let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+    gzip.write_all(body).unwrap();
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml; charset=utf-8"),
+        ))
+        .insert_header((
+            header::CONTENT_ENCODING,
+            header::HeaderValue::from_static("gzip"),
+        ))
+        .set_payload(gzip.finish().unwrap())
+        .to_http_parts();
+
+    let (value, info) = XmlParts::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap()
+        .into_parts();
+    assert_eq!(
+        value,
+        MyObject {
+            name: "test".to_string()
+        }
+    );
+    assert_eq!(
+        info.content_type().map(mime::Mime::essence_str),
+        Some("application/xml")
+    );
+    assert_eq!(
+        info.content_type()
+            .and_then(|mime| mime.get_param(mime::CHARSET)),
+        Some(mime::UTF_8)
+    );
+    assert_eq!(info.byte_len(), body.len());
+    assert!(info.decompressed());
+
+    // plain payloads are not decompressed
+    let (req, mut pl) = xml_test_request(body).to_http_parts();
+    let parts = XmlParts::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert!(!parts.info().decompressed());
+    assert_eq!(parts.info().byte_len(), body.len());
+}
+
+#[actix_rt::test]
+async fn test_overflow_status() {
+    let (req, mut pl) = xml_test_request(b"<MyObject name=\"test\" />")
+        .app_data(
+            XmlConfig::default()
+                .limit(10)
+                .overflow_status(StatusCode::BAD_REQUEST),
+        )
+        .to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    let resp = s.err().unwrap().error_response();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn test_semantic_status_codes() {
+    let status = |config: XmlConfig, body: &'static [u8]| async move {
+        let (req, mut pl) = xml_test_request(body).app_data(config).to_http_parts();
+        let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+        s.err().unwrap().error_response().status()
+    };
+    let shape: &'static [u8] = b"<MyObject><nam>test</nam></MyObject>";
+    let syntax: &'static [u8] = b"<MyObject><name>test</nam></MyObject>";
+
+    // both are bad requests by default
+    assert_eq!(
+        status(XmlConfig::default(), shape).await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        status(XmlConfig::default(), syntax).await,
+        StatusCode::BAD_REQUEST
+    );
+
+    let config = || XmlConfig::default().semantic_status_codes(true);
+    assert_eq!(
+        status(config(), shape).await,
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    assert_eq!(status(config(), syntax).await, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        status(config().check_well_formed(true), syntax).await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+/// Validator rejecting names already taken, as listed in the app data.
+struct UniqueName;
+
+impl XmlValidator<MyObject> for UniqueName {
+    fn validate<'a>(
+        req: &'a HttpRequest,
+        value: &'a MyObject,
+    ) -> LocalBoxFuture<'a, Result<(), actix_web::Error>> {
+        async move {
+            let taken = req.app_data::<Vec<String>>().unwrap();
+            if taken.contains(&value.name) {
+                return Err(actix_web::error::ErrorConflict("name taken"));
+            }
+            Ok(())
+        }
+        .boxed_local()
+    }
+}
+
+#[actix_rt::test]
+async fn test_validated_xml() {
+    let request = |body: &'static [u8]| {
+        xml_test_request(body)
+            .app_data(vec!["taken".to_string()])
+            .to_http_parts()
+    };
+
+    let (req, mut pl) = request(b"<MyObject name=\"free\" />");
+    let s = ValidatedXml::<MyObject, UniqueName>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert_eq!(s.name, "free");
+
+    // parsed, but rejected by the validator
+    let (req, mut pl) = request(b"<MyObject name=\"taken\" />");
+    let s = ValidatedXml::<MyObject, UniqueName>::from_request(&req, &mut pl).await;
+    let resp = s.unwrap_err().error_response();
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    // extraction errors come first
+    let (req, mut pl) = request(b"<MyObject />");
+    let s = ValidatedXml::<MyObject, UniqueName>::from_request(&req, &mut pl).await;
+    let err = s.unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Deserialize { .. })
+    ));
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+    body: String,
+}
+
+#[actix_rt::test]
+async fn test_xml_with_root() {
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/soap+xml"),
+        ))
+        .set_payload(Bytes::from_static(
+            b"<soap:Envelope xmlns:soap=\"http://www.w3.org/2003/05/soap-envelope\">\
+            <body>test</body></soap:Envelope>",
+        ))
+        .to_http_parts();
+
+    let s = XmlWithRoot::<Envelope>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert_eq!(s.root(), "Envelope");
+    assert_eq!(s.qualified_root(), "soap:Envelope");
+    assert_eq!(s.body, "test");
+
+    let (req, mut pl) = xml_test_request(b"<MyObject name=\"test\" />").to_http_parts();
+
+    let (root, s) = XmlBody::<MyObject>::new(&req, &mut pl)
+        .with_root()
+        .await
+        .unwrap();
+    assert_eq!(root, "MyObject");
+    assert_eq!(s.name, "test");
+}
+
+#[actix_rt::test]
+async fn test_root_dispatch() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Pong {
+        id: u32,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Message {
+        Ping,
+        Pong(Pong),
+    }
+
+    let extract = |config: XmlConfig, body: &'static [u8]| {
+        xml_test_request(body).app_data(config).to_http_parts()
+    };
+
+    let (req, mut pl) = extract(XmlConfig::default(), b"<Ping/>");
+    let s = Xml::<Message>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.into_inner(), Message::Ping);
+
+    let (req, mut pl) = extract(
+        XmlConfig::default(),
+        b"<?xml version=\"1.0\"?><Pong id=\"3\"/>",
+    );
+    let s = Xml::<Message>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.into_inner(), Message::Pong(Pong { id: 3 }));
+
+    // also through the options wrapping the deserializer
+    let config = XmlConfig::default()
+        .lenient_scalars(true)
+        .verbose_errors(true);
+    let (req, mut pl) = extract(config, b"<Pong><id> 3 </id></Pong>");
+    let s = Xml::<Message>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.into_inner(), Message::Pong(Pong { id: 3 }));
+
+    let (req, mut pl) = extract(XmlConfig::default(), b"<Other/>");
+    let err = Xml::<Message>::from_request(&req, &mut pl)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Deserialize { .. })
+    ));
+    assert!(
+        err.to_string().contains("unknown variant `Other`"),
+        "{}",
+        err
+    );
+    assert_eq!(err.error_response().status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn test_mixed_content() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Inline {
+        #[serde(rename = "$text")]
+        Text(String),
+        #[serde(rename = "b")]
+        Bold(String),
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Paragraph {
+        #[serde(rename = "$value")]
+        content: Vec<Inline>,
+    }
+
+    let extract = |config: XmlConfig, body: &'static [u8]| {
+        xml_test_request(body).app_data(config).to_http_parts()
+    };
+    let config = || XmlConfig::default().mixed_content(true);
+
+    let (req, mut pl) = extract(config(), b"<p>Hello <b>world</b>!</p>");
+    let s = Xml::<Paragraph>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(
+        s.content,
+        vec![
+            Inline::Text("Hello ".to_owned()),
+            Inline::Bold("world".to_owned()),
+            Inline::Text("!".to_owned()),
+        ]
+    );
+
+    // whitespace-only runs are dropped, CDATA is merged into the text around it, and text of
+    // elements without child elements is trimmed as usual
+    let (req, mut pl) = extract(
+        config(),
+        b"<p>\n  <b> a &amp; b </b> <b>c</b> x <![CDATA[<y>]]></p>",
+    );
+    let s = Xml::<Paragraph>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(
+        s.content,
+        vec![
+            Inline::Bold("a & b".to_owned()),
+            Inline::Bold("c".to_owned()),
+            Inline::Text(" x <y>".to_owned()),
+        ]
+    );
+}
+
+#[actix_rt::test]
+async fn test_deny_unknown_elements() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Item {
+        name: String,
+        #[serde(default)]
+        price: u32,
+        #[serde(default)]
+        tags: Option<MyObject>,
+    }
+
+    let extract = |config: XmlConfig, body: &'static [u8]| {
+        xml_test_request(body).app_data(config).to_http_parts()
+    };
+    let body: &'static [u8] =
+        b"<Item xmlns=\"urn:shop\" id=\"1\"><name>tea</name><color>red</color></Item>";
+
+    // unknown elements and attributes are ignored, and missing ones take their default
+    let (req, mut pl) = extract(XmlConfig::default(), body);
+    let s = Xml::<Item>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(
+        s.into_inner(),
+        Item {
+            name: "tea".to_owned(),
+            price: 0,
+            tags: None,
+        }
+    );
+
+    let (req, mut pl) = extract(XmlConfig::default().deny_unknown_elements(true), body);
+    let err = Xml::<Item>::from_request(&req, &mut pl).await.unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Deserialize { .. })
+    ));
+    assert!(err.to_string().contains("unknown field `id`"), "{}", err);
+    assert_eq!(err.error_response().status(), StatusCode::BAD_REQUEST);
+
+    // namespace bindings are allowed, and nested structs are checked too
+    let config = || XmlConfig::default().deny_unknown_elements(true);
+    let (req, mut pl) = extract(
+        config(),
+        b"<Item xmlns=\"urn:shop\"><name>tea</name></Item>",
+    );
+    let s = Xml::<Item>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "tea");
+
+    let (req, mut pl) = extract(
+        config(),
+        b"<Item><name>tea</name><tags><name>a</name><color>red</color></tags></Item>",
+    );
+    let err = Xml::<Item>::from_request(&req, &mut pl).await.unwrap_err();
+    assert!(err.to_string().contains("unknown field `color`"), "{}", err);
+
+    // along with the other options wrapping the deserializer
+    let config = config()
+        .lenient_scalars(true)
+        .verbose_errors(true)
+        .prefix_attributes(true);
+    let (req, mut pl) = extract(
+        config.clone(),
+        b"<Item><name>tea</name><price> 3 </price></Item>",
+    );
+    let s = Xml::<Item>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.price, 3);
+    let (req, mut pl) = extract(config, b"<Item><name>tea</name><color>red</color></Item>");
+    let err = Xml::<Item>::from_request(&req, &mut pl).await.unwrap_err();
+    assert!(err.to_string().contains("(field `color`"), "{}", err);
+}
+
+/// Buffer pool counting the buffers it allocates.
+#[derive(Default)]
+struct CountingPool {
+    buffers: Mutex<Vec<BytesMut>>,
+    allocated: AtomicUsize,
+    taken: AtomicUsize,
+}
+
+impl BufferPool for CountingPool {
+    fn take(&self) -> BytesMut {
+        self.taken.fetch_add(1, Ordering::SeqCst);
+        self.buffers.lock().unwrap().pop().unwrap_or_else(|| {
+            self.allocated.fetch_add(1, Ordering::SeqCst);
+            BytesMut::with_capacity(8192)
+        })
+    }
+
+    fn put(&self, buffer: BytesMut) {
+        assert!(buffer.is_empty());
+        self.buffers.lock().unwrap().push(buffer);
+    }
+}
+
+#[actix_rt::test]
+async fn test_buffer_pool() {
+    let pool = Arc::new(CountingPool::default());
+    let config = XmlConfig::default().buffer_pool(pool.clone());
+    let extract = |body: &'static [u8]| {
+        xml_test_request(body)
+            .app_data(config.clone())
+            .to_http_parts()
+    };
+
+    for _ in 0..100 {
+        let (req, mut pl) = extract(b"<MyObject><name>test</name></MyObject>");
+        let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+        assert_eq!(s.name, "test");
+    }
+    // invalid documents still give their buffer back
+    let (req, mut pl) = extract(b"<MyObject></MyObject>");
+    assert!(Xml::<MyObject>::from_request(&req, &mut pl).await.is_err());
+    // so do payloads overflowing while being read, without a content length to reject early
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .set_payload(Bytes::from_static(b"<MyObject name=\"test\" />"))
+        .app_data(config.clone().limit(10))
+        .to_http_parts();
+    let err = Xml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Overflow { .. })
+    ));
+
+    assert_eq!(pool.taken.load(Ordering::SeqCst), 102);
+    assert_eq!(pool.allocated.load(Ordering::SeqCst), 1);
+    assert_eq!(pool.buffers.lock().unwrap().len(), 1);
+
+    // the bundled pool keeps up to `max_buffers` buffers
+    let pool = BytesPool::new(1);
+    let (first, second) = (pool.take(), pool.take());
+    let ptr = first.as_ptr();
+    pool.put(first);
+    pool.put(second);
+    assert_eq!(pool.take().as_ptr(), ptr);
+}
+
+#[actix_rt::test]
+async fn test_xml_body_from_stream() {
+    let chunks = || {
+        futures::stream::iter(vec![
+            Ok::<_, PayloadError>(Bytes::from_static(b"<MyObject ")),
+            Ok(Bytes::from_static(b"name=\"test\" />")),
+        ])
+    };
+
+    let s = XmlBody::<MyObject>::from_stream(chunks(), Some(24))
+        .await
+        .unwrap();
+    assert_eq!(s.name, "test");
+
+    let s = XmlBody::<MyObject>::from_stream(chunks(), None)
+        .limit(16)
+        .await;
+    assert!(xml_eq(
+        s.err().unwrap(),
+        XMLPayloadError::Overflow {
+            limit: 16,
+            length: Some(24),
+            decompressed: false,
+        }
+    ));
+}
+
+#[actix_rt::test]
+async fn test_overflow_early_abort() {
+    /// Sets a flag when dropped.
+    struct DropGuard(Arc<AtomicUsize>);
+
+    impl Drop for DropGuard {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let consumed = Arc::new(AtomicUsize::new(0));
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let chunks = {
+        let (consumed, guard) = (consumed.clone(), DropGuard(dropped.clone()));
+        futures::stream::repeat_with(|| Ok::<_, PayloadError>(Bytes::from(vec![b' '; 1024])))
+            .take(1024)
+            .map(move |chunk| {
+                let _ = &guard;
+                consumed.fetch_add(1, Ordering::SeqCst);
+                chunk
+            })
+    };
+
+    // no content length, so the limit is only crossed while reading
+    let mut body = XmlBody::<MyObject>::from_stream(chunks, None).limit(4096);
+    let s = (&mut body).await;
+    assert!(xml_eq(
+        s.err().unwrap(),
+        XMLPayloadError::Overflow {
+            limit: 4096,
+            length: Some(5120),
+            decompressed: false,
+        }
+    ));
+    // reading stops at the chunk crossing the limit, and the stream is released right away
+    assert_eq!(consumed.load(Ordering::SeqCst), 5);
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+    drop(body);
+}
+
+#[actix_rt::test]
+async fn test_read_timeout() {
+    // the client sends the start of the payload, then stalls
+    let stalled =
+        futures::stream::once(async { Ok::<_, PayloadError>(Bytes::from_static(b"<MyObject ")) })
+            .chain(futures::stream::pending());
+
+    let (req, _) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .app_data(XmlConfig::default().read_timeout(Duration::from_millis(50)))
+        .to_http_parts();
+    let mut pl = actix_web::dev::Payload::from(stalled.boxed_local());
+
+    let err = Xml::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Timeout { timeout }) if *timeout == Duration::from_millis(50)
+    ));
+    assert_eq!(
+        err.as_response_error().status_code(),
+        StatusCode::REQUEST_TIMEOUT
+    );
+
+    // payloads read in time are not affected
+    let (req, mut pl) = xml_test_request(b"<MyObject name=\"test\" />")
+        .app_data(XmlConfig::default().read_timeout(Duration::from_secs(5)))
+        .to_http_parts();
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+}
+
+#[actix_rt::test]
+async fn test_xml_stream_read_timeout() {
+    let stats = Arc::new(Mutex::new(Vec::new()));
+    let config = {
+        let stats = stats.clone();
+        XmlConfig::default()
+            .read_timeout(Duration::from_millis(50))
+            .on_complete(move |s: &XmlStats| stats.lock().unwrap().push(*s))
+    };
+
+    // the client sends the start of the payload, then stalls
+    let stalled =
+        futures::stream::once(async { Ok::<_, PayloadError>(Bytes::from_static(b"<MyObject ")) })
+            .chain(futures::stream::pending());
+    let (req, _) = TestRequest::default()
+        .insert_header((header::CONTENT_TYPE, "application/xml"))
+        .app_data(config.clone())
+        .to_http_parts();
+    let mut pl = actix_web::dev::Payload::from(stalled.boxed_local());
+
+    let err = XmlStream::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Timeout { timeout }) if *timeout == Duration::from_millis(50)
+    ));
+
+    let (req, mut pl) = xml_test_request(b"<MyObject name=\"test\" />")
+        .app_data(config)
+        .to_http_parts();
+    let s = XmlStream::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert_eq!(s.name, "test");
+
+    let stats = stats.lock().unwrap();
+    assert_eq!(stats.len(), 2);
+    assert_eq!((stats[0].bytes, stats[0].outcome), (10, Outcome::Timeout));
+    assert_eq!(stats[0].parse_duration, Duration::ZERO);
+    assert_eq!((stats[1].bytes, stats[1].outcome), (24, Outcome::Ok));
+}
+
+#[actix_rt::test]
+async fn test_xml_stream_parsers() {
+    // without a free parser, payloads are read in full before being parsed
+    let config = XmlConfig::default().stream_parsers(0);
+    let request = |chunks: Vec<&'static [u8]>| {
+        let (req, _) = TestRequest::default()
+            .insert_header((header::CONTENT_TYPE, "application/xml"))
+            .app_data(config.clone().stream_limit(30))
+            .to_http_parts();
+        let chunks = futures::stream::iter(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok::<_, PayloadError>(Bytes::from_static(chunk))),
+        );
+        (req, actix_web::dev::Payload::from(chunks.boxed_local()))
+    };
+
+    let (req, mut pl) = request(vec![b"<MyObject ", b"name=\"te", b"st\" />"]);
+    let s = XmlStream::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert_eq!(s.name, "test");
+
+    let (req, mut pl) = request(vec![b"<MyObject ", b"name=\"test\" ", b"extra=\"1\" />"]);
+    let err = XmlStream::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Overflow {
+            limit: 30,
+            length: Some(34),
+            ..
+        })
+    ));
+
+    let (req, mut pl) = request(vec![b"<MyObject>", b"</Other>"]);
+    let err = XmlStream::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Deserialize { .. })
+    ));
+}
+
+#[derive(Deserialize)]
+struct Borrowed<'a> {
+    #[serde(borrow)]
+    name: std::borrow::Cow<'a, str>,
+    #[serde(borrow)]
+    note: std::borrow::Cow<'a, str>,
+}
+
+#[actix_rt::test]
+async fn test_xml_buf() {
+    let (req, mut pl) =
+        xml_test_request(b"<Borrowed name=\"test\"><note>a &amp; b</note></Borrowed>")
+            .to_http_parts();
+
+    let buf = XmlBuf::from_request(&req, &mut pl).await.unwrap();
+    let s: Borrowed = buf.deserialize().unwrap();
+    // borrowed from the buffer unless unescaping is needed
+    assert!(matches!(s.name, std::borrow::Cow::Borrowed("test")));
+    assert_eq!(s.note, "a & b");
+}
+
+#[actix_rt::test]
+async fn test_empty_payload() {
+    for payload in [&b""[..], b" \r\n\t"] {
+        let (req, mut pl) = xml_test_request(payload).to_http_parts();
+
+        let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+        let err = s.err().unwrap();
+        assert!(matches!(
+            err.as_error::<XMLPayloadError>(),
+            Some(XMLPayloadError::Empty)
+        ));
+        assert_eq!(err.to_string(), "Xml payload is empty");
+        assert_eq!(err.error_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    // types deserializable from nothing are still accepted
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/xml"),
+        ))
+        .to_http_parts();
+    let s = Xml::<Option<MyObject>>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert!(s.is_none());
+}
+
+#[actix_rt::test]
+async fn test_not_an_element() {
+    for payload in [
+        &b"hello world"[..],
+        b"<?xml version=\"1.0\"?>\n hello <MyObject/>",
+        b"<!-- comment --><![CDATA[hello]]><MyObject/>",
+    ] {
+        let (req, mut pl) = xml_test_request(payload).to_http_parts();
+
+        let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+        let err = s.err().unwrap();
+        assert!(matches!(
+            err.as_error::<XMLPayloadError>(),
+            Some(XMLPayloadError::NotAnElement)
+        ));
+        assert_eq!(err.to_string(), "expected XML element at root");
+        assert_eq!(err.error_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    // a prolog before the root element is fine
+    let config = XmlConfig::default();
+    let s: MyObject = parse_xml(
+        b"<?xml version=\"1.0\"?>\n<!-- comment --><MyObject name=\"test\"/>",
+        &config,
+    )
+    .unwrap();
+    assert_eq!(s.name, "test");
+    // and so is text wrapped in a root element
+    #[derive(Deserialize)]
+    struct Text {
+        #[serde(rename = "$value")]
+        value: String,
+    }
+    let s: Text = parse_xml(b"hello", &config.wrap_root("Text")).unwrap();
+    assert_eq!(s.value, "hello");
+}
+
+#[actix_rt::test]
+async fn test_not_xml() {
+    for (payload, found) in [(&b"{\"json\":true}"[..], '{'), (b"\n [1, 2]", '[')] {
+        let (req, mut pl) = xml_test_request(payload).to_http_parts();
+
+        let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+        let err = s.err().unwrap();
+        assert!(matches!(
+            err.as_error::<XMLPayloadError>(),
+            Some(XMLPayloadError::NotXml { found: f }) if *f == found
+        ));
+        assert_eq!(err.error_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    let err = parse_xml::<MyObject>(b"{\"json\":true}", &XmlConfig::default()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Xml payload is not XML: it starts with `{` instead of `<`"
+    );
+    // a byte order mark isn't taken for content
+    let s: MyObject = parse_xml(
+        b"\xEF\xBB\xBF <MyObject name=\"test\"/>",
+        &XmlConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(s.name, "test");
+}
+
+/// Sets an environment variable until dropped.
+struct EnvGuard(&'static str);
+
+impl EnvGuard {
+    fn set(name: &'static str, value: &str) -> Self {
+        std::env::set_var(name, value);
+        EnvGuard(name)
+    }
+}
+
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        std::env::remove_var(self.0);
+    }
+}
+
+#[test]
+fn test_config_from_env() {
+    assert_eq!(XmlConfig::from_env().limit, Some(262_144));
+
+    {
+        let _limit = EnvGuard::set("XML_MAX_BODY_BYTES", "4096");
+        let _accept = EnvGuard::set("XML_ACCEPT_ANY_CONTENT_TYPE", "true");
+        let config = XmlConfig::from_env();
+        assert_eq!(config.limit, Some(4096));
+        let req = TestRequest::default().to_http_request();
+        assert!(config.check_content_type(&req).is_ok());
+    }
+
+    {
+        let _limit = EnvGuard::set("XML_MAX_BODY_BYTES", "4k");
+        assert_eq!(XmlConfig::from_env().limit, Some(262_144));
+    }
+
+    assert_eq!(XmlConfig::from_env().limit, Some(262_144));
+}
+
+#[actix_rt::test]
+async fn test_xml_seq() {
+    let (req, mut pl) = xml_test_request(
+        b"<Record name=\"a\" />\n<Record name=\"b\" />\n<Record><name>c</name></Record>",
+    )
+    .to_http_parts();
+
+    let s = XmlSeq::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    let names: Vec<_> = s.iter().map(|record| record.name.as_str()).collect();
+    assert_eq!(names, ["a", "b", "c"]);
+
+    let (req, mut pl) = xml_test_request(b"<Record name=\"a\" /><Record name=\"b\" />")
+        .app_data(XmlConfig::default().limit(16))
+        .to_http_parts();
+
+    let s = XmlSeq::<MyObject>::from_request(&req, &mut pl).await;
+    assert!(matches!(
+        s.err().unwrap().as_error::<XMLPayloadError>(),
+        Some(XMLPayloadError::Overflow { limit: 16, .. })
+    ));
+}
+
+#[test]
+fn test_forbid_doctype() {
+    let config = XmlConfig::default();
+    let doctype = b"<!DOCTYPE MyObject SYSTEM \"object.dtd\"><MyObject name=\"test\" />";
+
+    let s: MyObject = parse_xml(doctype, &config).unwrap();
+    assert_eq!(s.name, "test");
+
+    let config = config.forbid_doctype(true);
+    let s = parse_xml::<MyObject>(doctype, &config);
+    assert!(matches!(
+        s.err().unwrap(),
+        XMLPayloadError::DoctypeForbidden
+    ));
+    assert_eq!(
+        XMLPayloadError::DoctypeForbidden.error_response().status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    let s: MyObject = parse_xml(b"<MyObject name=\"test\" />", &config).unwrap();
+    assert_eq!(s.name, "test");
+}
+
+#[derive(Deserialize)]
+struct SoapEnvelope {
+    id: String,
+    body: String,
+}
+
+#[actix_rt::test]
+async fn test_namespace() {
+    let payload = Bytes::from_static(
+        b"<env:Envelope xmlns:env=\"http://www.w3.org/2003/05/soap-envelope\" env:id=\"1\">\
+        <env:body>test</env:body></env:Envelope>",
+    );
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/soap+xml"),
+        ))
+        .set_payload(payload.clone())
+        .to_http_parts();
+
+    let s = Xml::<SoapEnvelope>::from_request(&req, &mut pl).await;
+    assert!(s.is_err());
+
+    let (req, mut pl) = TestRequest::default()
+        .insert_header((
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/soap+xml"),
+        ))
+        .set_payload(payload)
+        .app_data(XmlConfig::default().namespace("", "http://www.w3.org/2003/05/soap-envelope"))
+        .to_http_parts();
+
+    let s = Xml::<SoapEnvelope>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert_eq!(s.id, "1");
+    assert_eq!(s.body, "test");
+}
+
+#[cfg(feature = "xml-error-body")]
+#[actix_rt::test]
+async fn test_xml_error_body() {
+    let (req, mut pl) = xml_test_request(b"<MyObject><nam>test</nam></MyObject>").to_http_parts();
+
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await;
+    let resp = s.err().unwrap().error_response();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "application/xml"
+    );
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    assert_eq!(
+        body,
+        "<error><code>deserialize</code>\
+        <message>Xml deserialize error: missing field `name`</message></error>"
+    );
+}
+
+#[actix_rt::test]
+async fn test_expand_empty_elements() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Record {
+        field: Option<String>,
+        #[serde(default)]
+        item: Vec<String>,
+    }
+
+    let extract = |config: XmlConfig, body: &'static [u8]| {
+        xml_test_request(body).app_data(config).to_http_parts()
+    };
+
+    let (req, mut pl) = extract(
+        XmlConfig::default(),
+        b"<A><field/><item/><item>a</item></A>",
+    );
+    let s = Xml::<Record>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.field.as_deref(), Some(""));
+    assert_eq!(s.item, vec!["".to_owned(), "a".to_owned()]);
+
+    let config = || XmlConfig::default().expand_empty_elements(false);
+    let (req, mut pl) = extract(config(), b"<A><field/><item/><item>a</item></A>");
+    let s = Xml::<Record>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.field, None);
+    assert_eq!(s.item, vec!["a".to_owned()]);
+
+    // elements written out in full are still empty
+    let (req, mut pl) = extract(config(), b"<A><field></field></A>");
+    let s = Xml::<Record>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.field.as_deref(), Some(""));
+
+    // and so is a self-closing root element
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Empty {
+        field: Option<String>,
+    }
+    let (req, mut pl) = extract(config(), b"<A/>");
+    let s = Xml::<Empty>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.field, None);
+}
+
+#[actix_rt::test]
+async fn test_xml_part() {
+    let extract = |content_type: &'static str, chunks: Vec<&'static [u8]>, config: XmlConfig| {
+        let (req, _) = TestRequest::default()
+            .insert_header((
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static(content_type),
+            ))
+            .app_data(config)
+            .to_http_parts();
+        let stream = futures::stream::iter(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok::<_, PayloadError>(Bytes::from_static(chunk))),
+        );
+        (req, actix_web::dev::Payload::from(stream.boxed_local()))
+    };
+    let related = "multipart/related; boundary=\"MIME_boundary\"; type=\"text/xml\"";
+    let body: &'static [u8] = b"--MIME_boundary\r\n\
+        Content-Type: application/octet-stream\r\n\
+        Content-ID: <attachment>\r\n\
+        \r\n\
+        0123456789abcdef0123456789abcdef\r\n\
+        --MIME_boundary\r\n\
+        Content-Type: text/xml; charset=utf-8\r\n\
+        Content-ID: <root>\r\n\
+        \r\n\
+        <MyObject><name>test</name></MyObject>\r\n\
+        --MIME_boundary--\r\n";
+
+    let (req, mut pl) = extract(related, vec![body], XmlConfig::default());
+    let s = XmlPart::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert_eq!(s.name, "test");
+
+    // delimiters split across chunks are found all the same
+    let chunks = body.chunks(7).collect();
+    let (req, mut pl) = extract(related, chunks, XmlConfig::default());
+    let s = XmlPart::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert_eq!(s.name, "test");
+
+    // the limit applies to the XML part only, not to the attachment
+    let (req, mut pl) = extract(related, vec![body], XmlConfig::default().limit(40));
+    let s = XmlPart::<MyObject>::from_request(&req, &mut pl)
+        .await
+        .unwrap();
+    assert_eq!(s.name, "test");
+    let (req, mut pl) = extract(related, vec![body], XmlConfig::default().limit(20));
+    let s = XmlPart::<MyObject>::from_request(&req, &mut pl).await;
+    assert_eq!(
+        s.err().unwrap().as_response_error().status_code(),
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+
+    let (req, mut pl) = extract(
+        related,
+        vec![b"--MIME_boundary\r\nContent-Type: text/plain\r\n\r\nhello\r\n--MIME_boundary--"],
+        XmlConfig::default(),
+    );
+    let s = XmlPart::<MyObject>::from_request(&req, &mut pl).await;
+    let err = s.err().unwrap();
+    assert_eq!(
+        err.as_response_error().status_code(),
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(err.to_string(), "Multipart payload has no XML part");
+
+    let (req, mut pl) = extract("application/xml", vec![body], XmlConfig::default());
+    let s = XmlPart::<MyObject>::from_request(&req, &mut pl).await;
+    assert_eq!(
+        s.err().unwrap().as_response_error().status_code(),
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+}
+
+#[actix_rt::test]
+async fn test_on_warning() {
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let config = |config: XmlConfig| {
+        let warnings = warnings.clone();
+        config
+            .on_warning(move |warning: &XmlWarning| warnings.lock().unwrap().push(warning.clone()))
+    };
+    let request = |config: XmlConfig, content_type: &'static str, body: &'static [u8]| {
+        TestRequest::default()
+            .insert_header((
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static(content_type),
+            ))
+            .set_payload(Bytes::from_static(body))
+            .app_data(config)
+            .to_http_parts()
+    };
+
+    let (req, mut pl) = request(
+        config(XmlConfig::default()),
+        "application/xml; charset=utf-8",
+        b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><MyObject name=\"test\" />",
+    );
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+    let warning = XmlWarning::EncodingMismatch {
+        charset: "utf-8".to_owned(),
+        declared: "ISO-8859-1".to_owned(),
+    };
+    assert_eq!(
+        warning.to_string(),
+        "Xml payload has charset `utf-8` but declares encoding `ISO-8859-1`"
+    );
+    assert_eq!(*warnings.lock().unwrap(), vec![warning]);
+
+    // labels of the same encoding agree
+    warnings.lock().unwrap().clear();
+    let (req, mut pl) = request(
+        config(XmlConfig::default()),
+        "application/xml; charset=UTF-8",
+        b"<?xml version=\"1.0\" encoding=\"utf-8\"?><MyObject name=\"test\" />",
+    );
+    Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert!(warnings.lock().unwrap().is_empty());
+
+    let (req, mut pl) = request(
+        config(XmlConfig::default().lenient(true)),
+        "application/xml",
+        b"<MyObject><name>test</b></name><extra>",
+    );
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+    assert_eq!(
+        *warnings.lock().unwrap(),
+        vec![
+            XmlWarning::DanglingEndTag {
+                name: "b".to_owned()
+            },
+            XmlWarning::UnclosedElement {
+                name: "extra".to_owned()
+            },
+            XmlWarning::UnclosedElement {
+                name: "MyObject".to_owned()
+            },
+        ]
+    );
+
+    warnings.lock().unwrap().clear();
+    let (req, mut pl) = request(
+        config(XmlConfig::default().check_end_names(false)),
+        "application/xml",
+        b"<MyObject><name>test</nmae></MyObject>",
+    );
+    let s = Xml::<MyObject>::from_request(&req, &mut pl).await.unwrap();
+    assert_eq!(s.name, "test");
+    assert_eq!(
+        *warnings.lock().unwrap(),
+        vec![XmlWarning::MismatchedEndTag {
+            expected: "name".to_owned(),
+            found: "nmae".to_owned(),
+        }]
+    );
+}
+
+#[cfg(feature = "c14n")]
+#[test]
+fn test_canonicalize() {
+    use crate::canonicalize;
+
+    let c14n = |input: &str| String::from_utf8(canonicalize(input.as_bytes()).unwrap()).unwrap();
+
+    // PIs, comments, and outside of document element
+    assert_eq!(
+        c14n(concat!(
+            "<?xml version=\"1.0\"?>\n\n",
+            "<?xml-stylesheet   href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n\n",
+            "<!DOCTYPE doc SYSTEM \"doc.dtd\">\n\n",
+            "<doc>Hello, world!<!-- Comment 1 --></doc>\n\n",
+            "<?pi-without-data     ?>\n\n",
+            "<!-- Comment 2 -->\n\n",
+            "<!-- Comment 3 -->\n",
+        )),
+        concat!(
+            "<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n",
+            "<doc>Hello, world!</doc>\n",
+            "<?pi-without-data?>",
+        )
+    );
+
+    // start and end tags, with the namespace declarations of exclusive canonicalization
+    assert_eq!(
+        c14n(concat!(
+            "<doc>\n",
+            "   <e1   />\n",
+            "   <e2   ></e2>\n",
+            "   <e3   name = \"elem3\"   id=\"elem3\"   />\n",
+            "   <e4   name=\"elem4\"   id=\"elem4\"   ></e4>\n",
+            "   <e5 a:attr=\"out\" b:attr=\"sorted\" attr2=\"all\" attr=\"I'm\"\n",
+            "      xmlns:b=\"http://www.ietf.org\"\n",
+            "      xmlns:a=\"http://www.w3.org\"\n",
+            "      xmlns=\"http://example.org\"/>\n",
+            "   <e6 xmlns=\"\" xmlns:a=\"http://www.w3.org\">\n",
+            "      <e7 xmlns=\"http://www.ietf.org\">\n",
+            "         <e8 xmlns=\"\" xmlns:a=\"http://www.w3.org\">\n",
+            "            <e9 xmlns=\"\" xmlns:a=\"http://www.ietf.org\"/>\n",
+            "         </e8>\n",
+            "      </e7>\n",
+            "   </e6>\n",
+            "</doc>",
+        )),
+        concat!(
+            "<doc>\n",
+            "   <e1></e1>\n",
+            "   <e2></e2>\n",
+            "   <e3 id=\"elem3\" name=\"elem3\"></e3>\n",
+            "   <e4 id=\"elem4\" name=\"elem4\"></e4>\n",
+            "   <e5 xmlns=\"http://example.org\" xmlns:a=\"http://www.w3.org\" ",
+            "xmlns:b=\"http://www.ietf.org\" attr=\"I'm\" attr2=\"all\" b:attr=\"sorted\" ",
+            "a:attr=\"out\"></e5>\n",
+            "   <e6>\n",
+            "      <e7 xmlns=\"http://www.ietf.org\">\n",
+            "         <e8 xmlns=\"\">\n",
+            "            <e9></e9>\n",
+            "         </e8>\n",
+            "      </e7>\n",
+            "   </e6>\n",
+            "</doc>",
+        )
+    );
+
+    // character modifications and character references, without a DTD typing attributes
+    assert_eq!(
+        c14n(concat!(
+            "<doc>\r\n",
+            "   <text>First line&#x0d;&#10;Second line</text>\r\n",
+            "   <value>&#x32;</value>\n",
+            "   <compute><![CDATA[value>\"0\" && value<\"10\" ?\"valid\":\"error\"]]></compute>\n",
+            "   <compute expr='value>\"0\" &amp;&amp; value&lt;\"10\" ?\"valid\":\"error\"'>valid</compute>\n",
+            "   <norm attr=' &apos;   &#x20;&#13;&#xa;&#9;   &apos; '/>\n",
+            "   <normNames attr='   A   &#x20;&#13;&#xa;&#9;   B   '/>\n",
+            "</doc>",
+        )),
+        concat!(
+            "<doc>\n",
+            "   <text>First line&#xD;\nSecond line</text>\n",
+            "   <value>2</value>\n",
+            "   <compute>value&gt;\"0\" &amp;&amp; value&lt;\"10\" ?\"valid\":\"error\"</compute>\n",
+            "   <compute expr=\"value>&quot;0&quot; &amp;&amp; value&lt;&quot;10&quot; ",
+            "?&quot;valid&quot;:&quot;error&quot;\">valid</compute>\n",
+            "   <norm attr=\" '    &#xD;&#xA;&#x9;   ' \"></norm>\n",
+            "   <normNames attr=\"   A    &#xD;&#xA;&#x9;   B   \"></normNames>\n",
+            "</doc>",
+        )
+    );
+
+    // prefixes are declared where they're used, and the `xml` prefix never is
+    assert_eq!(
+        c14n(concat!(
+            "<n0:local xmlns:n0=\"foo:bar\" xmlns:n3=\"ftp://example.org\">",
+            "<n1:elem2 xmlns:n1=\"http://example.net\" xml:lang=\"en\" n3:b=\"1\" a=\"2\">",
+            "<n3:stuff xmlns:n3=\"ftp://example.org\"/></n1:elem2></n0:local>",
+        )),
+        concat!(
+            "<n0:local xmlns:n0=\"foo:bar\">",
+            "<n1:elem2 xmlns:n1=\"http://example.net\" xmlns:n3=\"ftp://example.org\" a=\"2\" ",
+            "n3:b=\"1\" xml:lang=\"en\">",
+            "<n3:stuff></n3:stuff></n1:elem2></n0:local>",
+        )
+    );
+
+    assert!(canonicalize(b"<a:doc/>").is_err());
+    assert!(canonicalize(b"<doc>&custom;</doc>").is_err());
+}
+
+#[cfg(feature = "c14n")]
+#[actix_rt::test]
+async fn test_xml_body_into_canonical() {
+    let (req, mut pl) =
+        xml_test_request(b"<?xml version=\"1.0\"?><MyObject name='test' id=\"1\"/>")
+            .to_http_parts();
+
+    let canonical = XmlBody::<()>::new(&req, &mut pl)
+        .into_canonical()
+        .await
+        .unwrap();
+    assert_eq!(canonical, b"<MyObject id=\"1\" name=\"test\"></MyObject>");
 }
@@ -0,0 +1,53 @@
+use std::time::Instant;
+
+use actix_web::http::header;
+use actix_web::HttpRequest;
+use tracing::field::Empty;
+use tracing::Span;
+
+use crate::error::XMLPayloadError;
+use crate::stats::Outcome;
+
+/// Tracing span covering a single extraction, named `xml_extract` with target `actix_xml`.
+///
+/// Records the declared `content_length` and the configured `limit` on creation, and the
+/// `outcome` and `elapsed_us` on completion.
+pub(crate) struct Extraction {
+    span: Span,
+    start: Instant,
+}
+
+impl Extraction {
+    pub(crate) fn new(req: &HttpRequest, limit: Option<usize>) -> Self {
+        let content_length = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+
+        let span = tracing::debug_span!(
+            target: "actix_xml",
+            "xml_extract",
+            content_length,
+            limit = limit.map(|limit| limit as u64),
+            outcome = Empty,
+            elapsed_us = Empty,
+        );
+
+        Extraction {
+            span,
+            start: Instant::now(),
+        }
+    }
+
+    pub(crate) fn span(&self) -> Span {
+        self.span.clone()
+    }
+
+    pub(crate) fn finish(&self, error: Option<&XMLPayloadError>) {
+        self.span
+            .record("outcome", &Outcome::from_error(error).as_str());
+        self.span
+            .record("elapsed_us", &(self.start.elapsed().as_micros() as u64));
+    }
+}
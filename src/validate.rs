@@ -0,0 +1,126 @@
+use std::marker::PhantomData;
+use std::{fmt, ops};
+
+use actix_web::dev;
+use actix_web::Error as ActixError;
+use actix_web::{FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use serde::de::DeserializeOwned;
+
+use crate::Xml;
+
+/// Check run on a deserialized value by [`ValidatedXml`](struct.ValidatedXml.html) before the
+/// handler is called
+///
+/// Validation is asynchronous, so it can query a database or another service, e.g. through
+/// the app data of the request.
+pub trait XmlValidator<T> {
+    /// Accept `value`, or reject the request with an error, which is turned into the response
+    fn validate<'a>(
+        req: &'a HttpRequest,
+        value: &'a T,
+    ) -> LocalBoxFuture<'a, Result<(), ActixError>>;
+}
+
+/// Xml extractor validating the deserialized value with `V`
+///
+/// The payload is extracted like with [`Xml`](struct.Xml.html), then handed to
+/// [`V::validate`](trait.XmlValidator.html#tymethod.validate). Errors of the extraction are
+/// returned as is, and so are the errors of the validation, without going through the error
+/// handler of the config.
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_web::error::ErrorConflict;
+/// use actix_web::{Error, HttpRequest};
+/// use actix_xml::{ValidatedXml, XmlValidator};
+/// use futures::future::LocalBoxFuture;
+/// use futures::FutureExt;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Tenant {
+///     name: String,
+/// }
+///
+/// struct NewTenant;
+///
+/// impl XmlValidator<Tenant> for NewTenant {
+///     fn validate<'a>(
+///         _req: &'a HttpRequest,
+///         tenant: &'a Tenant,
+///     ) -> LocalBoxFuture<'a, Result<(), Error>> {
+///         async move {
+///             // look the tenant up, e.g. in a pool from the app data of the request
+///             if tenant.name == "taken" {
+///                 return Err(ErrorConflict("tenant already exists"));
+///             }
+///             Ok(())
+///         }
+///         .boxed_local()
+///     }
+/// }
+///
+/// async fn index(tenant: ValidatedXml<Tenant, NewTenant>) -> String {
+///     format!("Created {}", tenant.name)
+/// }
+/// ```
+pub struct ValidatedXml<T, V> {
+    value: T,
+    marker: PhantomData<fn() -> V>,
+}
+
+impl<T, V> ValidatedXml<T, V> {
+    /// Deconstruct to the validated value
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T, V> ops::Deref for ValidatedXml<T, V> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T, V> ops::DerefMut for ValidatedXml<T, V> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T, V> fmt::Debug for ValidatedXml<T, V>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ValidatedXml: {:?}", self.value)
+    }
+}
+
+impl<T, V> FromRequest for ValidatedXml<T, V>
+where
+    T: DeserializeOwned + 'static,
+    V: XmlValidator<T> + 'static,
+{
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self, ActixError>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let req = req.clone();
+        let extract = Xml::<T>::from_request(&req, payload);
+        async move {
+            let value = extract.await?.into_inner();
+            V::validate(&req, &value).await?;
+            Ok(ValidatedXml {
+                value,
+                marker: PhantomData,
+            })
+        }
+        .boxed_local()
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+
+/// Newtype name `XmlValue` requests the document with, intercepted by [`Document`].
+const DOCUMENT_TOKEN: &str = "$actix_xml::private::Document";
+
+/// Dynamic XML tree, for payloads without a fixed schema
+///
+/// Extract `Xml<XmlValue>` or call [`parse_xml`](fn.parse_xml.html) to get the root element
+/// of the payload as a tree of [`XmlElement`](struct.XmlElement.html)s and text. The tree is
+/// read from the payload once the options of [`XmlConfig`](struct.XmlConfig.html) are applied,
+/// with whitespace-only text dropped and CDATA sections read as text. Comments and processing
+/// instructions are dropped.
+///
+/// `XmlValue` can only be deserialized as a whole payload, not as a field of another type nor
+/// with [`Xml::from_slice`](struct.Xml.html#method.from_slice).
+///
+/// ## Example
+///
+/// ```rust
+/// use actix_xml::{parse_xml, XmlConfig, XmlValue};
+///
+/// let value: XmlValue = parse_xml(
+///     b"<order id=\"7\"><item><name>Tea</name></item></order>",
+///     &XmlConfig::default(),
+/// )
+/// .unwrap();
+/// let order = value.as_element().unwrap();
+/// assert_eq!(order.name, "order");
+/// assert_eq!(order.attribute("id"), Some("7"));
+/// let name = order.child("item").and_then(|item| item.child("name")).unwrap();
+/// assert_eq!(name.text(), "Tea");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XmlValue {
+    /// An element
+    Element(XmlElement),
+    /// Unescaped text content
+    Text(String),
+}
+
+/// Element of an [`XmlValue`](enum.XmlValue.html) tree
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XmlElement {
+    /// Qualified name, prefix included
+    pub name: String,
+    /// Unescaped attribute values by qualified name, namespace bindings included
+    pub attributes: BTreeMap<String, String>,
+    /// Child elements and text, in document order
+    pub children: Vec<XmlValue>,
+}
+
+impl XmlValue {
+    /// The element, if this is one
+    pub fn as_element(&self) -> Option<&XmlElement> {
+        match self {
+            XmlValue::Element(element) => Some(element),
+            XmlValue::Text(_) => None,
+        }
+    }
+
+    /// The text, if this is text
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            XmlValue::Element(_) => None,
+            XmlValue::Text(text) => Some(text),
+        }
+    }
+}
+
+impl XmlElement {
+    /// Value of the attribute named `name`
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    /// First child element named `name`
+    pub fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.elements().find(|element| element.name == name)
+    }
+
+    /// Child elements named `name`, in document order
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.elements().filter(move |element| element.name == name)
+    }
+
+    /// Child elements, in document order
+    pub fn elements(&self) -> impl Iterator<Item = &XmlElement> {
+        self.children.iter().filter_map(XmlValue::as_element)
+    }
+
+    /// Text content of the element, excluding that of child elements
+    pub fn text(&self) -> String {
+        self.children.iter().filter_map(XmlValue::as_text).collect()
+    }
+}
+
+impl<'de> Deserialize<'de> for XmlValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DocumentVisitor;
+
+        impl<'de> Visitor<'de> for DocumentVisitor {
+            type Value = XmlValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a whole XML payload")
+            }
+
+            fn visit_str<E>(self, text: &str) -> Result<XmlValue, E>
+            where
+                E: de::Error,
+            {
+                read_tree(text).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(DOCUMENT_TOKEN, DocumentVisitor)
+    }
+}
+
+/// Read the root element of `text` as a tree.
+fn read_tree(text: &str) -> Result<XmlValue, quick_xml::Error> {
+    let mut reader = Reader::from_str(text);
+    reader.trim_text(true);
+
+    let mut open: Vec<XmlElement> = Vec::new();
+    loop {
+        let node = match reader.read_event()? {
+            Event::Start(start) => {
+                open.push(element(&start, &reader)?);
+                continue;
+            }
+            Event::Empty(start) => XmlValue::Element(element(&start, &reader)?),
+            Event::End(_) => match open.pop() {
+                Some(element) => XmlValue::Element(element),
+                None => continue,
+            },
+            Event::Text(content) => XmlValue::Text(content.unescape()?.into_owned()),
+            Event::CData(content) => {
+                XmlValue::Text(String::from_utf8(content.into_inner().into_owned())?)
+            }
+            Event::Eof => return Err(quick_xml::Error::UnexpectedEof("root element".into())),
+            _ => continue,
+        };
+        match open.last_mut() {
+            Some(parent) => parent.children.push(node),
+            // Text outside of the root element is ignored
+            None if matches!(node, XmlValue::Element(_)) => return Ok(node),
+            None => {}
+        }
+    }
+}
+
+/// Create an element without children from a start tag.
+fn element<B>(start: &BytesStart, reader: &Reader<B>) -> Result<XmlElement, quick_xml::Error> {
+    let mut attributes = BTreeMap::new();
+    for attribute in start.attributes() {
+        let attribute = attribute?;
+        attributes.insert(
+            String::from_utf8(attribute.key.as_ref().to_vec())?,
+            attribute.decode_and_unescape_value(reader)?.into_owned(),
+        );
+    }
+    Ok(XmlElement {
+        name: String::from_utf8(start.name().as_ref().to_vec())?,
+        attributes,
+        children: Vec::new(),
+    })
+}
+
+/// Deserializer handing the whole document to [`XmlValue`], and forwarding everything else.
+pub(crate) struct Document<'de, D> {
+    pub(crate) text: &'de str,
+    pub(crate) inner: D,
+}
+
+macro_rules! forward {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: Visitor<'de>,
+        {
+            self.inner.$method($($arg,)* visitor)
+        }
+    )*};
+}
+
+impl<'de, D> Deserializer<'de> for Document<'de, D>
+where
+    D: Deserializer<'de>,
+{
+    type Error = D::Error;
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if name == DOCUMENT_TOKEN {
+            visitor.visit_borrowed_str(self.text)
+        } else {
+            self.inner.deserialize_newtype_struct(name, visitor)
+        }
+    }
+
+    forward! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
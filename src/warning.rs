@@ -0,0 +1,69 @@
+use std::fmt;
+
+/// Recoverable oddity met while reading a payload, passed to the callback registered with
+/// [`XmlConfig::on_warning`](struct.XmlConfig.html#method.on_warning).
+///
+/// The payload is read as if it was well-formed, so warnings are a way to keep track of data
+/// quality issues without rejecting requests.
+///
+/// # Example
+///
+/// ```rust
+/// use actix_xml::{XmlConfig, XmlWarning};
+///
+/// let config = XmlConfig::default()
+///     .lenient(true)
+///     .on_warning(|warning: &XmlWarning| log::warn!("{}", warning));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum XmlWarning {
+    /// The charset of the content type and the encoding of the XML declaration disagree. The
+    /// charset of the content type wins, unless an encoding is forced by the config
+    EncodingMismatch {
+        /// The charset of the content type
+        charset: String,
+        /// The encoding of the XML declaration
+        declared: String,
+    },
+    /// An end tag closes an element other than the open one, which is accepted with
+    /// [`check_end_names(false)`](struct.XmlConfig.html#method.check_end_names)
+    MismatchedEndTag {
+        /// Name of the open element
+        expected: String,
+        /// Name of the end tag
+        found: String,
+    },
+    /// An end tag matching no open element was dropped in
+    /// [`lenient`](struct.XmlConfig.html#method.lenient) mode
+    DanglingEndTag {
+        /// Name of the end tag
+        name: String,
+    },
+    /// An element left open was closed in [`lenient`](struct.XmlConfig.html#method.lenient)
+    /// mode, by the end tag of an outer element or at the end of the document
+    UnclosedElement {
+        /// Name of the element
+        name: String,
+    },
+}
+
+impl fmt::Display for XmlWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XmlWarning::EncodingMismatch { charset, declared } => write!(
+                f,
+                "Xml payload has charset `{}` but declares encoding `{}`",
+                charset, declared
+            ),
+            XmlWarning::MismatchedEndTag { expected, found } => {
+                write!(f, "Xml end tag `{}` closes element `{}`", found, expected)
+            }
+            XmlWarning::DanglingEndTag { name } => {
+                write!(f, "Xml end tag `{}` matches no open element", name)
+            }
+            XmlWarning::UnclosedElement { name } => {
+                write!(f, "Xml element `{}` isn't closed", name)
+            }
+        }
+    }
+}